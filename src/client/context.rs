@@ -1,14 +1,80 @@
-use crate::SecurityAlgo;
-use iso14229_1::{response::SessionTiming, Configuration, DataIdentifier};
+#[cfg(feature = "std2020")]
+use crate::secured::SessionKey;
+use crate::{EventResponse, SecurityAlgo};
+use bytes::Bytes;
+use iso14229_1::{
+    request::Request, response::SessionTiming, Configuration, DataIdentifier, Service,
+    SUPPRESS_POSITIVE,
+};
+use iso15765_2::IsoTpError;
 use rsutil::types::ByteOrder;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::{collections::HashSet, sync::Arc};
+use tokio::{
+    sync::{broadcast, mpsc, Mutex},
+    task::JoinHandle,
+};
+
+/// The listener of ResponseOnEvent, all received data is dispatched by the listener task when it is active.
+pub(crate) struct EventListener {
+    /// the services of serviceToRespondToRecord
+    pub(crate) services: Arc<Mutex<HashSet<Service>>>,
+    /// the data of request response
+    pub(crate) responses: Arc<Mutex<mpsc::UnboundedReceiver<Result<Bytes, IsoTpError>>>>,
+    pub(crate) sender: broadcast::Sender<EventResponse>,
+    pub(crate) handle: JoinHandle<()>,
+}
+
+/// The request which is waiting for response.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PendingRequest {
+    pub(crate) service: Service,
+    /// the sub-function, DID or routine identifier which is echoed by the positive response
+    pub(crate) echo: Vec<u8>,
+}
+
+impl PendingRequest {
+    pub(crate) fn new(request: &Request) -> Self {
+        let service = request.service();
+        let mut echo = request
+            .sub_function()
+            .map(|v| vec![u8::from(v) & !SUPPRESS_POSITIVE])
+            .unwrap_or_default();
+        let len = match service {
+            Service::ReadDID
+            | Service::ReadScalingDID
+            | Service::WriteDID
+            | Service::IOCtrl
+            | Service::RoutineCtrl => 2,
+            _ => 0,
+        };
+        echo.extend(request.raw_data().iter().take(len));
+
+        Self { service, echo }
+    }
+
+    /// Check the data(include SID) is the response of request,
+    /// the positive response which doesn't echo the request is the response of ResponseOnEvent.
+    pub(crate) fn is_response(&self, data: &[u8]) -> bool {
+        let service = self.service as u8;
+        match data {
+            [sid, target, ..] if *sid == Service::NRC as u8 => *target == service,
+            [sid, rest @ ..] => *sid == service | 0x40 && rest.starts_with(&self.echo),
+            [] => false,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub(crate) struct Context {
     timing: Arc<Mutex<SessionTiming>>,
     cfg: Arc<Mutex<Configuration>>,
     security_algo: Arc<Mutex<Option<SecurityAlgo>>>,
+    /// the request which is waiting for response
+    pub(crate) pending: Arc<Mutex<Option<PendingRequest>>>,
+    pub(crate) listener: Arc<Mutex<Option<EventListener>>>,
+    /// the session key of authentication and the antiReplayCounter of last secured request
+    #[cfg(feature = "std2020")]
+    pub(crate) session_key: Arc<Mutex<Option<(SessionKey, u16)>>>,
     pub(crate) byte_order: ByteOrder,
     pub(crate) p2_offset: u64,
 }
@@ -19,6 +85,10 @@ impl Context {
             timing: Default::default(),
             cfg: Default::default(),
             security_algo: Default::default(),
+            pending: Default::default(),
+            listener: Default::default(),
+            #[cfg(feature = "std2020")]
+            session_key: Default::default(),
             byte_order,
             p2_offset: p2_offset.unwrap_or_default() as u64,
         }
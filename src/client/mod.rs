@@ -1,7 +1,10 @@
 mod context;
 mod service;
 
+pub use service::{EventResponse, SecurityUnlock};
+
 use crate::{constants::LOG_TAG_CLIENT, error::DoCanError, SecurityAlgo};
+use bytes::Bytes;
 use iso14229_1::{
     request::Request,
     response::{Code, Response},
@@ -13,7 +16,7 @@ use iso15765_2::{
 };
 use rs_can::{CanDevice, CanFrame};
 use rsutil::types::ByteOrder;
-use std::{fmt::Display, hash::Hash};
+use std::{fmt::Display, hash::Hash, time::Duration};
 
 #[derive(Clone)]
 pub struct DoCanClient<D, C, F>
@@ -163,6 +166,28 @@ where
         request: Request,
         sub_check: Option<(u8, Service)>,
         cfg: &Configuration,
+    ) -> Result<Response, DoCanError> {
+        self.discard_responses().await;
+        let _ = self
+            .context
+            .pending
+            .lock()
+            .await
+            .replace(context::PendingRequest::new(&request));
+        let result = self
+            .transmit_and_wait(addr_type, request, sub_check, cfg)
+            .await;
+        let _ = self.context.pending.lock().await.take();
+
+        result
+    }
+
+    async fn transmit_and_wait(
+        &self,
+        addr_type: AddressType,
+        request: Request,
+        sub_check: Option<(u8, Service)>,
+        cfg: &Configuration,
     ) -> Result<Response, DoCanError> {
         let service = request.service();
         let data: Vec<_> = request.into();
@@ -174,11 +199,7 @@ where
             .await
             .map_err(DoCanError::IsoTpError)?;
 
-        let data = &self
-            .isotp
-            .wait_data(timing.p2_ms() + p2_offset)
-            .await
-            .map_err(DoCanError::IsoTpError)?;
+        let data = &self.wait_data(timing.p2_ms() + p2_offset).await?;
         let mut response = Response::try_from((data, cfg)).map_err(DoCanError::Iso14229Error)?;
         while Self::response_service_check(&response, service)? {
             rsutil::debug!(
@@ -195,11 +216,7 @@ where
                 .await
                 .map_err(DoCanError::IsoTpError)?;
 
-            let data = &self
-                .isotp
-                .wait_data(timing.p2_star_ms())
-                .await
-                .map_err(DoCanError::IsoTpError)?;
+            let data = &self.wait_data(timing.p2_star_ms()).await?;
 
             response = Response::try_from((data, cfg)).map_err(DoCanError::Iso14229Error)?;
         }
@@ -211,6 +228,55 @@ where
         Ok(response)
     }
 
+    /// Discard the late responses of the previous requests which are received by the listener task,
+    /// the listener drops the unexpected data when no request is pending.
+    async fn discard_responses(&self) {
+        let responses = self
+            .context
+            .listener
+            .lock()
+            .await
+            .as_ref()
+            .map(|v| v.responses.clone());
+        if let Some(responses) = responses {
+            let mut responses = responses.lock().await;
+            while let Ok(data) = responses.try_recv() {
+                rsutil::warn!("{} stale response: {:?} is dropped", LOG_TAG_CLIENT, data);
+            }
+        }
+    }
+
+    /// Wait the response data, the data is received by the listener task if ResponseOnEvent listener is active.
+    async fn wait_data(&self, timeout: u64) -> Result<Bytes, DoCanError> {
+        let responses = self
+            .context
+            .listener
+            .lock()
+            .await
+            .as_ref()
+            .map(|v| v.responses.clone());
+        match responses {
+            Some(responses) => {
+                let mut responses = responses.lock().await;
+                match tokio::time::timeout(Duration::from_millis(timeout), responses.recv()).await {
+                    Ok(Some(data)) => data.map_err(DoCanError::IsoTpError),
+                    Ok(None) => Err(DoCanError::OtherError(
+                        "ResponseOnEvent listener is stopped".into(),
+                    )),
+                    Err(_) => Err(DoCanError::IsoTpError(IsoTpError::Timeout {
+                        value: timeout,
+                        unit: "ms",
+                    })),
+                }
+            }
+            None => self
+                .isotp
+                .wait_data(timeout)
+                .await
+                .map_err(DoCanError::IsoTpError),
+        }
+    }
+
     fn sub_func_check(response: &Response, source: u8, service: Service) -> Result<(), DoCanError> {
         match response.sub_function() {
             Some(v) => {
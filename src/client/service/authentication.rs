@@ -1,10 +1,20 @@
 //! response of Service 29
 
-use crate::{client::DoCanClient, DoCanResult};
+use crate::{
+    client::DoCanClient,
+    pki::{self, auth_data, SigningKey},
+    secured::EphemeralKey,
+    DoCanError, DoCanResult,
+};
 use iso14229_1::{request, response, *};
 use iso15765_2::can::AddressType;
+use rand::{rng, RngExt};
 use rs_can::{CanDevice, CanFrame};
-use std::{fmt::Display, hash::Hash};
+use std::{fmt::Display, hash::Hash, path::Path};
+use x509_cert::{der::Encode, Certificate};
+
+/// the length of challenge generated by client in bidirectional authentication
+const CLIENT_CHALLENGE_LEN: usize = 32;
 
 impl<D, C, F> DoCanClient<D, C, F>
 where
@@ -29,4 +39,201 @@ where
         )
         .await
     }
+
+    /// Authenticate with the tester certificate and its PKCS#8 PEM private key(ECDSA P-256 or Ed25519)
+    /// by verifyCertificateUnidirectional and proofOfOwnership.
+    ///
+    /// Return the sessionKeyInfo of ECU, the session key of SecuredDataTransmission is established if it is not empty.
+    pub async fn authenticate_unidirectional(
+        &mut self,
+        certificate: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+    ) -> DoCanResult<Vec<u8>> {
+        let (certificate, key) = Self::load_credential(certificate, private_key).await?;
+        let auth_task = AuthenticationTask::VerifyCertificateUnidirectional;
+        let data = request::Authentication::VerifyCertificateUnidirectional {
+            config: 0x00,
+            certificate: Self::auth_param(certificate)?,
+            challenge: Default::default(),
+        };
+        let challenge = match self.authentication(auth_task, data).await? {
+            response::Authentication::VerifyCertificateUnidirectional {
+                value, challenge, ..
+            } => {
+                Self::auth_return_value(
+                    value,
+                    response::AuthReturnValue::CertificateVerifiedOrOwnershipVerificationNecessary,
+                )?;
+                auth_data(challenge)
+            }
+            _ => return Err(Self::unexpected_authentication(auth_task)),
+        };
+
+        self.proof_of_ownership(&key, &challenge).await
+    }
+
+    /// Authenticate with the tester certificate and its PKCS#8 PEM private key
+    /// by verifyCertificateBidirectional and proofOfOwnership.
+    ///
+    /// The certificate of ECU is verified with `trust_anchor` and its proofOfOwnership is verified with the challenge of client.
+    /// Return the sessionKeyInfo of ECU, the session key of SecuredDataTransmission is established if it is not empty.
+    pub async fn authenticate_bidirectional(
+        &mut self,
+        certificate: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+        trust_anchor: impl AsRef<Path>,
+    ) -> DoCanResult<Vec<u8>> {
+        let (certificate, key) = Self::load_credential(certificate, private_key).await?;
+        let anchor = Self::load_certificate(trust_anchor).await?;
+        let mut client_challenge = vec![0u8; CLIENT_CHALLENGE_LEN];
+        rng().fill(&mut client_challenge);
+
+        let auth_task = AuthenticationTask::VerifyCertificateBidirectional;
+        let data = request::Authentication::VerifyCertificateBidirectional {
+            config: 0x00,
+            certificate: Self::auth_param(certificate)?,
+            challenge: Self::auth_param(client_challenge.clone())?,
+        };
+        let challenge = match self.authentication(auth_task, data).await? {
+            response::Authentication::VerifyCertificateBidirectional {
+                value,
+                challenge,
+                certificate,
+                proof_of_ownership,
+                ..
+            } => {
+                Self::auth_return_value(
+                    value,
+                    response::AuthReturnValue::CertificateVerifiedOrOwnershipVerificationNecessary,
+                )?;
+
+                let certificate = pki::parse_certificate(&auth_data(certificate))
+                    .map_err(DoCanError::UntrustedCertificate)?;
+                pki::verify_certificate(&certificate, &anchor)
+                    .map_err(DoCanError::UntrustedCertificate)?;
+                pki::verify_signature(
+                    &certificate.tbs_certificate.subject_public_key_info,
+                    &client_challenge,
+                    &auth_data(proof_of_ownership),
+                )
+                .map_err(|_| DoCanError::InvalidProofOfOwnership)?;
+
+                auth_data(challenge)
+            }
+            _ => return Err(Self::unexpected_authentication(auth_task)),
+        };
+
+        self.proof_of_ownership(&key, &challenge).await
+    }
+
+    /// Send proofOfOwnership with an ephemeral public key and agree the session key with the sessionKeyInfo of ECU.
+    async fn proof_of_ownership(
+        &mut self,
+        key: &SigningKey,
+        challenge: &[u8],
+    ) -> DoCanResult<Vec<u8>> {
+        let _ = self.context.session_key.lock().await.take();
+        let ephemeral = EphemeralKey::new();
+        let auth_task = AuthenticationTask::ProofOfOwnership;
+        let data = request::Authentication::ProofOfOwnership {
+            proof_of_ownership: Self::auth_param(key.sign(challenge))?,
+            ephemeral_public_key: NullableData::new(ephemeral.public_key())
+                .map_err(DoCanError::Iso14229Error)?,
+        };
+        match self.authentication(auth_task, data).await? {
+            response::Authentication::ProofOfOwnership {
+                value,
+                session_keyinfo,
+            } => {
+                Self::auth_return_value(
+                    value,
+                    response::AuthReturnValue::OwnershipVerifiedOrAuthenticationComplete,
+                )?;
+
+                let session_keyinfo = auth_data(session_keyinfo);
+                if !session_keyinfo.is_empty() {
+                    let session_key =
+                        ephemeral
+                            .agree(&session_keyinfo, challenge)
+                            .ok_or_else(|| {
+                                DoCanError::SecuredDataVerificationFailed(
+                                    "invalid sessionKeyInfo of ECU".into(),
+                                )
+                            })?;
+                    let _ = self
+                        .context
+                        .session_key
+                        .lock()
+                        .await
+                        .replace((session_key, 0));
+                }
+
+                Ok(session_keyinfo)
+            }
+            _ => Err(Self::unexpected_authentication(auth_task)),
+        }
+    }
+
+    /// load the DER certificate and private key of tester
+    async fn load_credential(
+        certificate: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+    ) -> DoCanResult<(Vec<u8>, SigningKey)> {
+        let path = certificate.as_ref().display().to_string();
+        let certificate = Self::load_certificate(certificate)
+            .await?
+            .to_der()
+            .map_err(|e| DoCanError::InvalidCertificate {
+                path,
+                reason: e.to_string(),
+            })?;
+
+        let path = private_key.as_ref().display().to_string();
+        let pem = tokio::fs::read_to_string(private_key)
+            .await
+            .map_err(|e| DoCanError::OtherError(format!("{}: {}", path, e)))?;
+        let key = SigningKey::from_pem(&pem).ok_or(DoCanError::InvalidPrivateKey(path))?;
+
+        Ok((certificate, key))
+    }
+
+    async fn load_certificate(path: impl AsRef<Path>) -> DoCanResult<Certificate> {
+        let path = path.as_ref();
+        let data = tokio::fs::read(path)
+            .await
+            .map_err(|e| DoCanError::OtherError(format!("{}: {}", path.display(), e)))?;
+
+        pki::parse_certificate(&data).map_err(|e| DoCanError::InvalidCertificate {
+            path: path.display().to_string(),
+            reason: format!("{:?}", e),
+        })
+    }
+
+    #[inline(always)]
+    fn auth_param(data: Vec<u8>) -> DoCanResult<NotNullableData> {
+        NotNullableData::new(data).map_err(DoCanError::Iso14229Error)
+    }
+
+    #[inline(always)]
+    fn auth_return_value(
+        actual: response::AuthReturnValue,
+        expect: response::AuthReturnValue,
+    ) -> DoCanResult<()> {
+        if actual != expect {
+            return Err(DoCanError::UnexpectedAuthReturnValue {
+                expect: expect.into(),
+                actual: actual.into(),
+            });
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn unexpected_authentication(auth_task: AuthenticationTask) -> DoCanError {
+        DoCanError::OtherError(format!(
+            "unexpected response of authentication task: {:?}",
+            auth_task
+        ))
+    }
 }
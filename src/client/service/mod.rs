@@ -8,8 +8,10 @@ mod ctrl_dtc_setting; // 0x85 ✅
 mod ecu_reset; // 0x11 ✅
 mod link_ctrl; // 0x87 ✅
 mod response_on_event; // 0x86 ✅
+pub use response_on_event::EventResponse;
 mod secured_data_trans; // 0x84 ✅
 mod security_access; // 0x27 ✅
+pub use security_access::SecurityUnlock;
 mod session_ctrl; // 0x10 ✅
 mod tester_present; // 0x3E ✅
 
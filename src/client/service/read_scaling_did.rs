@@ -1,6 +1,6 @@
 //! response of Service 24
 
-use crate::{client::DoCanClient, DoCanResult};
+use crate::{client::DoCanClient, DoCanResult, ScalingDescription};
 use iso14229_1::{request, response, DataIdentifier, Service};
 use iso15765_2::can::AddressType;
 use rs_can::{CanDevice, CanFrame};
@@ -23,4 +23,14 @@ where
         self.send_and_parse(AddressType::Physical, request, None, &cfg)
             .await
     }
+
+    /// read scaling data of DID and decode it into a structured scaling description
+    pub async fn read_scaling_description(
+        &mut self,
+        did: DataIdentifier,
+    ) -> DoCanResult<ScalingDescription> {
+        let resp = self.read_scaling_data_by_identifier(did).await?;
+
+        ScalingDescription::try_from(resp)
+    }
 }
@@ -1,10 +1,15 @@
 //! response of Service 38
 
-use crate::{client::DoCanClient, DoCanError, DoCanResult};
-use iso14229_1::{request, response, ModeOfOperation, Service};
+use crate::{client::DoCanClient, crc::crc32, DoCanError, DoCanResult};
+use iso14229_1::{
+    request, response, DataFormatIdentifier, Iso14229Error, ModeOfOperation, Service,
+};
 use iso15765_2::can::AddressType;
 use rs_can::{CanDevice, CanFrame};
-use std::{fmt::Display, hash::Hash};
+use std::{fmt::Display, hash::Hash, path::Path};
+
+/// the byte length of file size parameters in request
+const FILE_SIZE_LEN: u8 = 8;
 
 impl<D, C, F> DoCanClient<D, C, F>
 where
@@ -20,7 +25,9 @@ where
         let service = Service::RequestFileTransfer;
         let sub_func = operation.into();
         let cfg = self.context.get_cfg().await;
-        let request = Self::make_request(service, Some(sub_func), data, &cfg)?;
+        // the encoded data starts with modeOfOperation that is sent as sub-function
+        let data: Vec<_> = data.into();
+        let request = Self::make_request(service, Some(sub_func), &data[1..], &cfg)?;
 
         let response = self
             .send_and_response(
@@ -35,4 +42,272 @@ where
             .data::<response::RequestFileTransfer>(&cfg)
             .map_err(DoCanError::Iso14229Error)
     }
+
+    /// Upload a local file to the server with AddFile(or ReplaceFile if `replace`).
+    ///
+    /// `progress` is called with transferred and total bytes after each TransferData.
+    /// Return the CRC32 of the file which is sent as transferRequestParameterRecord of RequestTransferExit.
+    pub async fn upload_file<P>(
+        &mut self,
+        local: impl AsRef<Path>,
+        remote: &str,
+        replace: bool,
+        progress: P,
+    ) -> DoCanResult<u32>
+    where
+        P: FnMut(u128, u128),
+    {
+        let content = Self::read_local_file(local).await?;
+        let size = content.len() as u128;
+        let (operation, data) = if replace {
+            (
+                ModeOfOperation::ReplaceFile,
+                request::RequestFileTransfer::ReplaceFile {
+                    filepath: remote.into(),
+                    dfi: Default::default(),
+                    filesize_len: FILE_SIZE_LEN,
+                    uncompressed_size: size,
+                    compressed_size: size,
+                },
+            )
+        } else {
+            (
+                ModeOfOperation::AddFile,
+                request::RequestFileTransfer::AddFile {
+                    filepath: remote.into(),
+                    dfi: Default::default(),
+                    filesize_len: FILE_SIZE_LEN,
+                    uncompressed_size: size,
+                    compressed_size: size,
+                },
+            )
+        };
+
+        let max_block_len = match self.request_file_transfer(operation, data).await? {
+            response::RequestFileTransfer::AddFile { max_block_len, .. }
+            | response::RequestFileTransfer::ReplaceFile { max_block_len, .. } => max_block_len,
+            _ => return Err(Self::unexpected_file_transfer(operation)),
+        };
+
+        self.transfer_file_data(&content, 0, max_block_len, progress)
+            .await
+    }
+
+    /// Resume an interrupted upload from the file position reported by the server.
+    ///
+    /// Return the CRC32 of the whole file.
+    pub async fn resume_upload_file<P>(
+        &mut self,
+        local: impl AsRef<Path>,
+        remote: &str,
+        progress: P,
+    ) -> DoCanResult<u32>
+    where
+        P: FnMut(u128, u128),
+    {
+        let content = Self::read_local_file(local).await?;
+        let size = content.len() as u128;
+        let data = request::RequestFileTransfer::ResumeFile {
+            filepath: remote.into(),
+            dfi: Default::default(),
+            filesize_len: FILE_SIZE_LEN,
+            uncompressed_size: size,
+            compressed_size: size,
+        };
+
+        let operation = ModeOfOperation::ResumeFile;
+        let (max_block_len, position) = match self.request_file_transfer(operation, data).await? {
+            response::RequestFileTransfer::ResumeFile {
+                max_block_len,
+                file_pos,
+                ..
+            } => (max_block_len, u64::from_be_bytes(file_pos) as u128),
+            _ => return Err(Self::unexpected_file_transfer(operation)),
+        };
+        if position > size {
+            return Err(DoCanError::UnexpectedFileSize {
+                expect: size,
+                actual: position,
+            });
+        }
+
+        self.transfer_file_data(&content, position as usize, max_block_len, progress)
+            .await
+    }
+
+    /// Download a remote file to `local` with ReadFile.
+    ///
+    /// The received size is verified with the size of response and the CRC32 is verified if `crc` is present.
+    /// Return the CRC32 of the file.
+    pub async fn download_file<P>(
+        &mut self,
+        remote: &str,
+        local: impl AsRef<Path>,
+        crc: Option<u32>,
+        progress: P,
+    ) -> DoCanResult<u32>
+    where
+        P: FnMut(u128, u128),
+    {
+        let operation = ModeOfOperation::ReadFile;
+        let data = request::RequestFileTransfer::ReadFile {
+            filepath: remote.into(),
+            dfi: DataFormatIdentifier::default(),
+        };
+        let (max_block_len, size) = match self.request_file_transfer(operation, data).await? {
+            response::RequestFileTransfer::ReadFile {
+                max_block_len,
+                uncompressed_size_or_dir_len,
+                ..
+            } => (max_block_len, uncompressed_size_or_dir_len),
+            _ => return Err(Self::unexpected_file_transfer(operation)),
+        };
+
+        let content = self
+            .receive_file_data(size, max_block_len, progress)
+            .await?;
+        let actual = crc32(&content);
+        if let Some(expect) = crc {
+            if expect != actual {
+                return Err(DoCanError::UnexpectedCrc { expect, actual });
+            }
+        }
+
+        tokio::fs::write(local, content)
+            .await
+            .map_err(|e| DoCanError::OtherError(e.to_string()))?;
+
+        Ok(actual)
+    }
+
+    /// List the entries of a remote directory with ReadDir, the directory is end with `/`.
+    pub async fn list_dir(&mut self, remote: &str) -> DoCanResult<Vec<String>> {
+        let operation = ModeOfOperation::ReadDir;
+        let data = request::RequestFileTransfer::ReadDir {
+            filepath: remote.into(),
+        };
+        let (max_block_len, size) = match self.request_file_transfer(operation, data).await? {
+            response::RequestFileTransfer::ReadDir {
+                max_block_len,
+                uncompressed_size_or_dir_len,
+                ..
+            } => (max_block_len, uncompressed_size_or_dir_len),
+            _ => return Err(Self::unexpected_file_transfer(operation)),
+        };
+
+        let content = self
+            .receive_file_data(size, max_block_len, |_, _| {})
+            .await?;
+
+        Ok(String::from_utf8_lossy(&content)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    async fn transfer_file_data<P>(
+        &mut self,
+        content: &[u8],
+        position: usize,
+        max_block_len: u128,
+        mut progress: P,
+    ) -> DoCanResult<u32>
+    where
+        P: FnMut(u128, u128),
+    {
+        let total = content.len() as u128;
+        let block_len = Self::block_data_len(max_block_len)?;
+        let mut sequence = 1u8;
+        let mut transferred = position as u128;
+        for chunk in content[position..].chunks(block_len) {
+            self.transfer_data(sequence, chunk.to_vec()).await?;
+            sequence = sequence.wrapping_add(1);
+            transferred += chunk.len() as u128;
+            progress(transferred, total);
+        }
+
+        // the CRC32 is sent for the server to verify the file, the record of response
+        // is not compared since an echoed record proves nothing
+        let crc = crc32(content);
+        self.request_transfer_exit(crc.to_be_bytes().to_vec())
+            .await?;
+
+        Ok(crc)
+    }
+
+    async fn receive_file_data<P>(
+        &mut self,
+        size: u128,
+        max_block_len: u128,
+        mut progress: P,
+    ) -> DoCanResult<Vec<u8>>
+    where
+        P: FnMut(u128, u128),
+    {
+        let block_len = Self::block_data_len(max_block_len)?;
+        let mut content = Vec::new();
+        let mut sequence = 1u8;
+        while (content.len() as u128) < size {
+            let data = self.transfer_data(sequence, vec![]).await?.data;
+            if data.is_empty() {
+                break;
+            }
+            if data.len() > block_len {
+                return Err(DoCanError::Iso14229Error(
+                    Iso14229Error::InvalidDataLength {
+                        expect: block_len,
+                        actual: data.len(),
+                    },
+                ));
+            }
+            let actual = (content.len() + data.len()) as u128;
+            if actual > size {
+                return Err(DoCanError::UnexpectedFileSize {
+                    expect: size,
+                    actual,
+                });
+            }
+            content.extend(data);
+            sequence = sequence.wrapping_add(1);
+            progress(content.len() as u128, size);
+        }
+
+        if content.len() as u128 != size {
+            return Err(DoCanError::UnexpectedFileSize {
+                expect: size,
+                actual: content.len() as u128,
+            });
+        }
+
+        self.request_transfer_exit(vec![]).await?;
+
+        Ok(content)
+    }
+
+    async fn read_local_file(path: impl AsRef<Path>) -> DoCanResult<Vec<u8>> {
+        tokio::fs::read(path)
+            .await
+            .map_err(|e| DoCanError::OtherError(e.to_string()))
+    }
+
+    /// the data length of one TransferData exclude SID and blockSequenceCounter
+    #[inline(always)]
+    fn block_data_len(max_block_len: u128) -> DoCanResult<usize> {
+        match max_block_len {
+            0..=2 => Err(DoCanError::OtherError(format!(
+                "invalid max number of block length: {}",
+                max_block_len
+            ))),
+            v => Ok(usize::try_from(v - 2).unwrap_or(usize::MAX)),
+        }
+    }
+
+    #[inline(always)]
+    fn unexpected_file_transfer(operation: ModeOfOperation) -> DoCanError {
+        DoCanError::OtherError(format!(
+            "unexpected response of file transfer operation: {:?}",
+            operation
+        ))
+    }
 }
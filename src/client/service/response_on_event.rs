@@ -1,11 +1,38 @@
 //! response of Service 86
 
-use crate::{client::DoCanClient, DoCanResult};
+use crate::{
+    client::{context::EventListener, DoCanClient},
+    constants::LOG_TAG_CLIENT,
+    DoCanError, DoCanResult,
+};
 
-use iso14229_1::{request, response, Service};
-use iso15765_2::can::AddressType;
+use iso14229_1::{
+    request::{self, EventTypeParameter},
+    response::{self, Response},
+    Service,
+};
+use iso15765_2::{can::AddressType, IsoTp};
 use rs_can::{CanDevice, CanFrame};
-use std::{fmt::Display, hash::Hash};
+use std::{collections::HashSet, fmt::Display, hash::Hash, sync::Arc};
+use tokio::{
+    spawn,
+    sync::{broadcast, mpsc, Mutex},
+};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+/// the offset of positive response SID
+const POSITIVE_OFFSET: u8 = 0x40;
+/// The timeout of listener task waiting data, the task is aborted when the listener is stopped.
+const LISTENER_WAIT_MS: u64 = 60_000;
+
+/// The asynchronous response of ResponseOnEvent.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EventResponse {
+    /// the response of serviceToRespondToRecord when the event fires
+    Respond(Response),
+    /// the final response when the event window is closed
+    WindowClosed(response::ResponseOnEvent),
+}
 
 impl<D, C, F> DoCanClient<D, C, F>
 where
@@ -23,4 +50,147 @@ where
         self.send_and_parse(AddressType::Physical, request, None, &cfg)
             .await
     }
+
+    /// Set up the event and listen the asynchronous responses of it.
+    ///
+    /// The event is triggered after StartResponseOnEvent is requested.
+    /// The returned stream yields the responses of the service to respond to and the final response of the event window,
+    /// the normal request/response is not interfered by the listener.
+    pub async fn listen_response_on_event(
+        &mut self,
+        data: request::ResponseOnEvent,
+    ) -> DoCanResult<impl Stream<Item = EventResponse>> {
+        let service = match data.param {
+            EventTypeParameter::OnDTCStatusChange { service, .. }
+            | EventTypeParameter::OnChangeOfDataIdentifier { service, .. }
+            | EventTypeParameter::OnComparisonOfValues { service, .. } => service,
+            EventTypeParameter::ReportMostRecentDtcOnStatusChange { .. }
+            | EventTypeParameter::ReportDTCRecordInformationOnDtcStatusChange { .. } => {
+                Service::ReadDTCInfo
+            }
+            _ => {
+                return Err(DoCanError::OtherError(format!(
+                    "ResponseOnEvent `{:?}` is not an event",
+                    data.param
+                )))
+            }
+        };
+        // the first byte of request is eventType
+        let event_type = Vec::<u8>::from(data)[0];
+
+        let stream = self.start_event_listener(service).await;
+        self.response_on_event(data).await?;
+
+        Ok(stream.filter_map(move |v| match v {
+            Ok(EventResponse::Respond(resp)) if resp.service() == service => {
+                Some(EventResponse::Respond(resp))
+            }
+            Ok(EventResponse::WindowClosed(resp))
+                if resp.data.first().is_some_and(|v| v & 0x3F == event_type) =>
+            {
+                Some(EventResponse::WindowClosed(resp))
+            }
+            Ok(_) => None,
+            Err(e) => {
+                rsutil::warn!("{} ResponseOnEvent listener error: {}", LOG_TAG_CLIENT, e);
+                None
+            }
+        }))
+    }
+
+    /// Stop the ResponseOnEvent listener, the streams of listener are ended.
+    pub async fn stop_listen_response_on_event(&mut self) {
+        if let Some(listener) = self.context.listener.lock().await.take() {
+            listener.handle.abort();
+        }
+    }
+
+    async fn start_event_listener(&self, service: Service) -> BroadcastStream<EventResponse> {
+        let mut guard = self.context.listener.lock().await;
+        if let Some(listener) = guard.as_ref() {
+            listener.services.lock().await.insert(service);
+            return BroadcastStream::new(listener.sender.subscribe());
+        }
+
+        let services = Arc::new(Mutex::new(HashSet::from([service])));
+        let (resp_tx, resp_rx) = mpsc::unbounded_channel();
+        let (sender, receiver) = broadcast::channel(64);
+        let isotp = self.isotp.clone();
+        let context = self.context.clone();
+        let event_services = services.clone();
+        let event_sender = sender.clone();
+        let handle = spawn(async move {
+            loop {
+                let data = match isotp.wait_data(LISTENER_WAIT_MS).await {
+                    Ok(data) => data,
+                    Err(iso15765_2::IsoTpError::Timeout { .. }) => continue,
+                    Err(e) => {
+                        if context.pending.lock().await.is_some() {
+                            let _ = resp_tx.send(Err(e));
+                        }
+                        continue;
+                    }
+                };
+                if data.is_empty() {
+                    continue;
+                }
+
+                // the service of response or negative response
+                let target = match data[0] {
+                    v if v == Service::NRC as u8 => data.get(1).copied(),
+                    v => Some(v & !POSITIVE_OFFSET),
+                }
+                .and_then(|v| Service::try_from(v).ok());
+                let pending = context.pending.lock().await.clone();
+                if pending.as_ref().is_some_and(|v| v.is_response(&data)) {
+                    let _ = resp_tx.send(Ok(data));
+                    continue;
+                }
+
+                let cfg = context.get_cfg().await;
+                let event = match target {
+                    Some(Service::ResponseOnEvent) => Response::try_from((&data, &cfg))
+                        .and_then(|v| v.data::<response::ResponseOnEvent>(&cfg))
+                        .map(EventResponse::WindowClosed),
+                    Some(v) if event_services.lock().await.contains(&v) => {
+                        Response::try_from((&data, &cfg)).map(EventResponse::Respond)
+                    }
+                    _ => {
+                        if pending.is_some() {
+                            // checked by the request
+                            let _ = resp_tx.send(Ok(data));
+                        } else {
+                            rsutil::warn!(
+                                "{} unexpected data: {} is dropped",
+                                LOG_TAG_CLIENT,
+                                hex::encode(&data)
+                            );
+                        }
+                        continue;
+                    }
+                };
+
+                match event {
+                    Ok(event) => {
+                        let _ = event_sender.send(event);
+                    }
+                    Err(e) => rsutil::warn!(
+                        "{} can't parse ResponseOnEvent data: {}, because of: {}",
+                        LOG_TAG_CLIENT,
+                        hex::encode(&data),
+                        e
+                    ),
+                }
+            }
+        });
+
+        let _ = guard.replace(EventListener {
+            services,
+            responses: Arc::new(Mutex::new(resp_rx)),
+            sender,
+            handle,
+        });
+
+        BroadcastStream::new(receiver)
+    }
 }
@@ -41,4 +41,98 @@ where
             .data::<response::SecuredDataTrans>(&cfg)
             .map_err(DoCanError::Iso14229Error)
     }
+
+    /// Send the internal request(`service` and its parameters) secured by the session key of authentication.
+    ///
+    /// The request is signed and its parameters are encrypted if `encrypted`, the signature of response is requested.
+    /// Return the verified internal response, a negative internal response is returned as [`DoCanError::NRCError`].
+    #[cfg(feature = "std2020")]
+    pub async fn secured_request(
+        &mut self,
+        service: Service,
+        data: Vec<u8>,
+        encrypted: bool,
+    ) -> DoCanResult<response::Response> {
+        use crate::secured::{secured_header, SECURED_ALGORITHM};
+
+        let (key, counter) = {
+            let mut session_key = self.context.session_key.lock().await;
+            let (key, counter) = session_key.as_mut().ok_or(DoCanError::NoSessionKey)?;
+            *counter = counter.checked_add(1).ok_or(DoCanError::NoSessionKey)?;
+            (key.clone(), *counter)
+        };
+
+        let mut apar = AdministrativeParameter::new();
+        apar.request_set(true)
+            .signed_set(true)
+            .encrypted_set(encrypted)
+            .signature_on_response_set(true);
+        let algorithm = SignatureEncryptionCalculation::try_from(SECURED_ALGORITHM)
+            .map_err(DoCanError::Iso14229Error)?;
+        let header = secured_header(apar.into(), SECURED_ALGORITHM, counter);
+        let data = if encrypted {
+            key.encrypt(&header, &data)
+                .ok_or_else(|| DoCanError::OtherError("can't encrypt secured data".into()))?
+        } else {
+            data
+        };
+        let mut message = vec![service.into()];
+        message.extend(&data);
+        let signature = key.sign(&header, &message);
+
+        let resp = self
+            .secured_data_transmit(apar, algorithm, counter, service.into(), data, signature)
+            .await?;
+        let (apar, anti_replay_cnt, mut message, signature) = match resp {
+            response::SecuredDataTrans::Successful(v) => {
+                let mut message = vec![v.response];
+                message.extend(v.response_params);
+                (v.apar, v.anti_replay_cnt, message, v.signature_data)
+            }
+            response::SecuredDataTrans::Unsuccessful(v) => (
+                v.apar,
+                v.anti_replay_cnt,
+                vec![Service::NRC.into(), v.service, v.response],
+                v.signature_data,
+            ),
+        };
+        if anti_replay_cnt != counter {
+            return Err(DoCanError::SecuredDataVerificationFailed(format!(
+                "antiReplayCounter of response(0x{:04x}) does not match request(0x{:04x})",
+                anti_replay_cnt, counter
+            )));
+        }
+
+        let header = secured_header(apar.into(), SECURED_ALGORITHM, anti_replay_cnt);
+        if !apar.is_signed() || !key.verify(&header, &message, &signature) {
+            return Err(DoCanError::SecuredDataVerificationFailed(
+                "invalid signature of response".into(),
+            ));
+        }
+        if apar.is_encrypted() && message[0] != Service::NRC as u8 {
+            let data = key.decrypt(&header, &message[1..]).ok_or_else(|| {
+                DoCanError::SecuredDataVerificationFailed("can't decrypt response".into())
+            })?;
+            message.truncate(1);
+            message.extend(data);
+        }
+
+        let cfg = self.context.get_cfg().await;
+        let resp =
+            response::Response::try_from((message, &cfg)).map_err(DoCanError::Iso14229Error)?;
+        if resp.is_negative() {
+            return Err(DoCanError::NRCError {
+                service: resp.service(),
+                code: resp.nrc_code().map_err(DoCanError::Iso14229Error)?,
+            });
+        }
+        if resp.service() != service {
+            return Err(DoCanError::UnexpectedResponse {
+                expect: service,
+                actual: resp.service(),
+            });
+        }
+
+        Ok(resp)
+    }
 }
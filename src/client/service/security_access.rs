@@ -1,10 +1,35 @@
 //! response of Service 27
 
-use crate::{client::DoCanClient, DoCanError, DoCanResult};
-use iso14229_1::Service;
+use crate::{
+    client::DoCanClient,
+    constants::{LOG_TAG_CLIENT, SA_DELAY_POLL_MS},
+    DoCanError, DoCanResult,
+};
+use iso14229_1::{response::Code, Service};
 use iso15765_2::can::AddressType;
 use rs_can::{CanDevice, CanFrame};
-use std::{fmt::Display, hash::Hash};
+use std::{
+    fmt::Display,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// The result of [`DoCanClient::try_unlock_security_access`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SecurityUnlock {
+    /// the key is accepted, `waited` is the time spent on the delay timer
+    Unlocked { waited: Duration },
+    /// the seed is zero, the level is unlocked already
+    AlreadyUnlocked,
+    /// the security algorithm has no key of the seed
+    NoKey,
+    /// the key is rejected, the key is not sent again to save the attempts
+    InvalidKey,
+    /// the key is rejected and the delay timer of server is armed
+    AttemptsExceeded,
+    /// the delay timer is not expired within the maximum wait
+    DelayNotExpired { waited: Duration },
+}
 
 impl<D, C, F> DoCanClient<D, C, F>
 where
@@ -61,4 +86,66 @@ where
             None => Ok(()),
         }
     }
+
+    /// Unlock the security level, the request seed is repeated until the delay timer is expired or `max_delay` is elapsed.
+    pub async fn try_unlock_security_access(
+        &mut self,
+        level: u8,
+        params: Vec<u8>,
+        salt: Vec<u8>,
+        max_delay: Duration,
+    ) -> DoCanResult<SecurityUnlock> {
+        let start = Instant::now();
+        let seed = loop {
+            match self.security_access(level, params.clone()).await {
+                Ok(seed) => break seed,
+                Err(DoCanError::NRCError {
+                    code: Code::RequiredTimeDelayNotExpired,
+                    ..
+                }) => {
+                    let waited = start.elapsed();
+                    if waited >= max_delay {
+                        return Ok(SecurityUnlock::DelayNotExpired { waited });
+                    }
+                    rsutil::debug!(
+                        "{} SecurityAccess level: {} is delayed, waited: {:?}",
+                        LOG_TAG_CLIENT,
+                        level,
+                        waited
+                    );
+                    let poll = Duration::from_millis(SA_DELAY_POLL_MS).min(max_delay - waited);
+                    tokio::time::sleep(poll).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let waited = start.elapsed();
+
+        if !seed.is_empty() && seed.iter().all(|&v| v == 0) {
+            return Ok(SecurityUnlock::AlreadyUnlocked);
+        }
+
+        // the algorithm is not required if the level is unlocked already
+        let algo = self
+            .context
+            .get_security_algo()
+            .await
+            .ok_or_else(|| DoCanError::OtherError("security algorithm required".into()))?;
+        let Some(key) = algo(level, &seed, &salt)? else {
+            return Ok(SecurityUnlock::NoKey);
+        };
+        match self.security_access(level + 1, key).await {
+            Ok(_) => Ok(SecurityUnlock::Unlocked { waited }),
+            Err(DoCanError::NRCError { code, .. }) => match code {
+                Code::InvalidKey => Ok(SecurityUnlock::InvalidKey),
+                Code::ExceedNumberOfAttempts => Ok(SecurityUnlock::AttemptsExceeded),
+                Code::RequiredTimeDelayNotExpired => Ok(SecurityUnlock::DelayNotExpired { waited }),
+                _ => Err(DoCanError::NRCError {
+                    service: Service::SecurityAccess,
+                    code,
+                }),
+            },
+            Err(e) => Err(e),
+        }
+    }
 }
@@ -1,6 +1,28 @@
 pub const P2_MAX: u16 = 50;
 pub const P2_STAR_MAX: u16 = 500;
 pub const DEFAULT_P2_START_MS: u64 = 5_000;
+/// the default S3 timer of server
+#[cfg(feature = "server")]
+pub(crate) const S3_SERVER_MS: u64 = 5_000;
+/// the wait of server request loop, the data received when the wait is timeout is dropped by ISO-TP
+#[cfg(feature = "server")]
+pub(crate) const SERVER_WAIT_MS: u64 = 60_000;
+/// suppressPosRspMsgIndicationBit of ResponseOnEvent eventType
+#[cfg(feature = "server")]
+pub(crate) const ROE_SUPPRESS_POSITIVE: u8 = 0x80;
+/// the interval of ResponseOnEvent event engine
+#[cfg(feature = "server")]
+pub(crate) const ROE_INTERVAL_MS: u64 = 10;
+/// the rates of ResponseOnEvent timerSchedule
+#[cfg(feature = "server")]
+pub(crate) const ROE_TIMER_SLOW_MS: u64 = 1_000;
+#[cfg(feature = "server")]
+pub(crate) const ROE_TIMER_MEDIUM_MS: u64 = 500;
+#[cfg(feature = "server")]
+pub(crate) const ROE_TIMER_FAST_MS: u64 = 100;
+/// the interval of requestSeed while the SecurityAccess delay timer is not expired
+#[cfg(feature = "client")]
+pub(crate) const SA_DELAY_POLL_MS: u64 = 1_000;
 
 #[cfg(feature = "client")]
 pub(crate) const LOG_TAG_CLIENT: &'static str = "DoCanClient - ";
@@ -23,6 +23,43 @@ pub enum DoCanError {
     #[error("DoCAN - block sequence number of response (0x{actual:02x}) does not match request block sequence number (0x{expect:02x})")]
     UnexpectedTransferSequence { expect: u8, actual: u8 },
 
+    #[error(
+        "DoCAN - transferred file size ({actual}) does not match expected file size ({expect})"
+    )]
+    UnexpectedFileSize { expect: u128, actual: u128 },
+
+    #[error(
+        "DoCAN - CRC32 of file (0x{actual:08x}) does not match expected CRC32 (0x{expect:08x})"
+    )]
+    UnexpectedCrc { expect: u32, actual: u32 },
+
+    #[error("DoCAN - invalid certificate `{path}`: {reason}")]
+    InvalidCertificate { path: String, reason: String },
+
+    #[error("DoCAN - invalid private key `{0}`")]
+    InvalidPrivateKey(String),
+
+    #[error("DoCAN - authentication got an unexpected return value(expect: 0x{expect:02x}, actual: 0x{actual:02x})")]
+    UnexpectedAuthReturnValue { expect: u8, actual: u8 },
+
+    #[error("DoCAN - certificate of ECU is not trusted: {0:?}")]
+    UntrustedCertificate(Code),
+
+    #[error("DoCAN - proof of ownership of ECU is invalid")]
+    InvalidProofOfOwnership,
+
+    #[error("DoCAN - no session key is established by authentication")]
+    NoSessionKey,
+
+    #[error("DoCAN - secured data verification failed: {0}")]
+    SecuredDataVerificationFailed(String),
+
+    #[error("DoCAN - invalid configuration of DTC 0x{dtc:06X}: {reason}")]
+    InvalidDtcConfig { dtc: u32, reason: String },
+
+    #[error("DoCAN - invalid configuration of DID 0x{did:04X}: {reason}")]
+    InvalidDidConfig { did: u16, reason: String },
+
     #[error("DoCAN - service `{service}` got a NRC({code:?})")]
     NRCError { service: Service, code: Code },
 
@@ -2,6 +2,17 @@ mod error;
 pub use error::*;
 mod constants;
 pub use constants::*;
+mod scaling;
+pub use scaling::*;
+#[cfg(all(
+    any(feature = "std2013", feature = "std2020"),
+    any(feature = "client", feature = "server")
+))]
+mod crc;
+#[cfg(all(feature = "std2020", any(feature = "client", feature = "server")))]
+mod pki;
+#[cfg(all(feature = "std2020", any(feature = "client", feature = "server")))]
+mod secured;
 
 #[cfg(feature = "client")]
 mod client;
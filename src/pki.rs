@@ -0,0 +1,219 @@
+//! Certificates and signatures of Service 29(APCE), ECDSA P-256 and Ed25519 are supported.
+
+use iso14229_1::response::Code;
+use p256::pkcs8::DecodePrivateKey;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use x509_cert::{
+    der::{asn1::ObjectIdentifier, Decode, DecodePem, Encode},
+    spki::SubjectPublicKeyInfoOwned,
+    Certificate,
+};
+
+/// id-ecPublicKey
+const OID_EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+/// secp256r1
+const OID_P256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+/// ecdsa-with-SHA256
+const OID_ECDSA_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+/// id-Ed25519
+const OID_ED25519: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+/// Parse a DER or PEM encoded certificate.
+pub(crate) fn parse_certificate(data: &[u8]) -> Result<Certificate, Code> {
+    Certificate::from_der(data)
+        .or_else(|_| Certificate::from_pem(data))
+        .map_err(|_| Code::CertificateVerificationFailedInvalidFormat)
+}
+
+/// Verify the certificate is issued by the trust anchor and is in its validity period.
+pub(crate) fn verify_certificate(cert: &Certificate, anchor: &Certificate) -> Result<(), Code> {
+    let tbs = &cert.tbs_certificate;
+    if tbs.issuer != anchor.tbs_certificate.subject {
+        return Err(Code::CertificateVerificationFailedInvalidChainOfTrust);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO);
+    if now < tbs.validity.not_before.to_unix_duration()
+        || now > tbs.validity.not_after.to_unix_duration()
+    {
+        return Err(Code::CertificateVerificationFailedInvalidTimePeriod);
+    }
+
+    let algorithm = cert.signature_algorithm.oid;
+    if algorithm != OID_ECDSA_SHA256 && algorithm != OID_ED25519 {
+        return Err(Code::CertificateVerificationFailedInvalidType);
+    }
+    let message = tbs
+        .to_der()
+        .map_err(|_| Code::CertificateVerificationFailedInvalidFormat)?;
+    let signature = cert
+        .signature
+        .as_bytes()
+        .ok_or(Code::CertificateVerificationFailedInvalidFormat)?;
+    verify_signature(
+        &anchor.tbs_certificate.subject_public_key_info,
+        &message,
+        signature,
+    )
+    .map_err(|_| Code::CertificateVerificationFailedInvalidSignature)
+}
+
+/// Verify the signature(DER or raw `r || s` of ECDSA) with the public key of certificate.
+pub(crate) fn verify_signature(
+    spki: &SubjectPublicKeyInfoOwned,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), Code> {
+    use p256::ecdsa::signature::Verifier;
+
+    let key = spki
+        .subject_public_key
+        .as_bytes()
+        .ok_or(Code::OwnershipVerificationFailed)?;
+    let algorithm = &spki.algorithm;
+    if algorithm.oid == OID_EC_PUBLIC_KEY {
+        let curve = algorithm
+            .parameters
+            .as_ref()
+            .and_then(|v| v.decode_as::<ObjectIdentifier>().ok());
+        if curve != Some(OID_P256) {
+            return Err(Code::CertificateVerificationFailedInvalidType);
+        }
+
+        let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(key)
+            .map_err(|_| Code::CertificateVerificationFailedInvalidContent)?;
+        let signature = p256::ecdsa::Signature::from_der(signature)
+            .or_else(|_| p256::ecdsa::Signature::from_slice(signature))
+            .map_err(|_| Code::OwnershipVerificationFailed)?;
+        key.verify(message, &signature)
+            .map_err(|_| Code::OwnershipVerificationFailed)
+    } else if algorithm.oid == OID_ED25519 {
+        let key = <[u8; ed25519_dalek::PUBLIC_KEY_LENGTH]>::try_from(key)
+            .ok()
+            .and_then(|v| ed25519_dalek::VerifyingKey::from_bytes(&v).ok())
+            .ok_or(Code::CertificateVerificationFailedInvalidContent)?;
+        let signature = ed25519_dalek::Signature::from_slice(signature)
+            .map_err(|_| Code::OwnershipVerificationFailed)?;
+        key.verify(message, &signature)
+            .map_err(|_| Code::OwnershipVerificationFailed)
+    } else {
+        Err(Code::CertificateVerificationFailedInvalidType)
+    }
+}
+
+/// The role of certificate extension, the value is the content of an OCTET STRING(or the raw extension value).
+#[cfg(feature = "server")]
+pub(crate) fn certificate_role(cert: &Certificate, oid: &ObjectIdentifier) -> Option<Vec<u8>> {
+    cert.tbs_certificate
+        .extensions
+        .as_ref()?
+        .iter()
+        .find(|ext| ext.extn_id == *oid)
+        .map(|ext| {
+            let value = ext.extn_value.as_bytes();
+            x509_cert::der::asn1::OctetString::from_der(value)
+                .map(|v| v.as_bytes().to_vec())
+                .unwrap_or_else(|_| value.to_vec())
+        })
+}
+
+/// The data of a length-prefixed parameter of Authentication.
+#[inline(always)]
+pub(crate) fn auth_data<T: Into<Vec<u8>>>(data: T) -> Vec<u8> {
+    let mut data: Vec<u8> = data.into();
+    data.split_off(2)
+}
+
+/// The private key of proof of ownership.
+pub(crate) enum SigningKey {
+    EcdsaP256(p256::ecdsa::SigningKey),
+    Ed25519(ed25519_dalek::SigningKey),
+}
+
+impl SigningKey {
+    /// Parse a PKCS#8 PEM private key.
+    pub(crate) fn from_pem(pem: &str) -> Option<Self> {
+        p256::ecdsa::SigningKey::from_pkcs8_pem(pem)
+            .map(Self::EcdsaP256)
+            .or_else(|_| ed25519_dalek::SigningKey::from_pkcs8_pem(pem).map(Self::Ed25519))
+            .ok()
+    }
+
+    /// Sign the message, the signature of ECDSA is DER encoded.
+    pub(crate) fn sign(&self, message: &[u8]) -> Vec<u8> {
+        use p256::ecdsa::signature::Signer;
+
+        match self {
+            Self::EcdsaP256(key) => {
+                let signature: p256::ecdsa::Signature = key.sign(message);
+                signature.to_der().as_bytes().to_vec()
+            }
+            Self::Ed25519(key) => key.sign(message).to_bytes().to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{
+        certificate_role, parse_certificate, verify_certificate, verify_signature, SigningKey,
+    };
+    use iso14229_1::response::Code;
+    use x509_cert::der::asn1::ObjectIdentifier;
+
+    pub(crate) const ROOT: &[u8] = include_bytes!("../examples/pki/root.pem");
+    pub(crate) const TESTER: &[u8] = include_bytes!("../examples/pki/tester.pem");
+    pub(crate) const TESTER_KEY: &str = include_str!("../examples/pki/tester.key");
+    pub(crate) const TESTER_ED25519: &[u8] = include_bytes!("../examples/pki/tester_ed25519.pem");
+    pub(crate) const TESTER_ED25519_KEY: &str = include_str!("../examples/pki/tester_ed25519.key");
+    pub(crate) const ECU: &[u8] = include_bytes!("../examples/pki/ecu.pem");
+    pub(crate) const ECU_KEY: &str = include_str!("../examples/pki/ecu.key");
+    pub(crate) const UNTRUSTED: &[u8] = include_bytes!("../examples/pki/untrusted.pem");
+
+    #[test]
+    fn verify_certificate_against_anchor() {
+        let root = parse_certificate(ROOT).unwrap();
+        for cert in [TESTER, TESTER_ED25519] {
+            let cert = parse_certificate(cert).unwrap();
+            verify_certificate(&cert, &root).unwrap();
+        }
+
+        let cert = parse_certificate(UNTRUSTED).unwrap();
+        assert_eq!(
+            verify_certificate(&cert, &root).unwrap_err(),
+            Code::CertificateVerificationFailedInvalidChainOfTrust
+        );
+        assert_eq!(
+            parse_certificate(&[0x30, 0x00]).unwrap_err(),
+            Code::CertificateVerificationFailedInvalidFormat
+        );
+    }
+
+    #[test]
+    fn sign_and_verify_proof_of_ownership() {
+        for (cert, key) in [(TESTER, TESTER_KEY), (TESTER_ED25519, TESTER_ED25519_KEY)] {
+            let cert = parse_certificate(cert).unwrap();
+            let spki = &cert.tbs_certificate.subject_public_key_info;
+            let key = SigningKey::from_pem(key).unwrap();
+            let signature = key.sign(b"challenge");
+
+            verify_signature(spki, b"challenge", &signature).unwrap();
+            assert_eq!(
+                verify_signature(spki, b"other", &signature).unwrap_err(),
+                Code::OwnershipVerificationFailed
+            );
+        }
+    }
+
+    #[test]
+    fn role_of_certificate_extension() {
+        let cert = parse_certificate(TESTER).unwrap();
+        let oid = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.55555.1.1");
+        assert_eq!(certificate_role(&cert, &oid), Some(vec![0x00, 0x03]));
+
+        let oid = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.55555.1.2");
+        assert_eq!(certificate_role(&cert, &oid), None);
+    }
+}
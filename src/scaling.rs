@@ -0,0 +1,451 @@
+//! Scaling records of Service 24, see `ISO-14229(2020) Annex C`.
+
+use crate::{DoCanError, DoCanResult};
+use iso14229_1::{
+    response::{Formula, ReadScalingDID, ScalingByteData, ScalingByteType},
+    DataIdentifier, ScalingByteExtensionUnit,
+};
+use rsutil::types::ByteOrder;
+
+/// the max value of scalingByte low nibble
+const SCALING_LEN_MAX: usize = 0x0F;
+
+/// One scalingByte with its scalingByteExtension.
+///
+/// The `len` of data-bearing records is the number of bytes of the DID data
+/// described by this record.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "server", derive(serde::Deserialize))]
+#[cfg_attr(feature = "server", serde(tag = "type", rename_all = "snake_case"))]
+pub enum ScalingRecord {
+    UnsignedNumeric {
+        len: u8,
+    },
+    SignedNumeric {
+        len: u8,
+    },
+    /// bitMappedReportedWithOutMask, the `mask` is the validity mask
+    BitMapped {
+        mask: Vec<u8>,
+    },
+    BitMappedWithMask {
+        len: u8,
+    },
+    Bcd {
+        len: u8,
+    },
+    StateEncoded {
+        len: u8,
+    },
+    Ascii {
+        len: u8,
+    },
+    Float {
+        len: u8,
+    },
+    Packet {
+        len: u8,
+    },
+    /// formulaIdentifier and constants(C0, C1, ...)
+    Formula {
+        id: u8,
+        constants: Vec<f64>,
+    },
+    /// unit or format identifier, see `ISO-14229(2020) Table C.8`
+    Unit {
+        id: u8,
+    },
+    StateAndConnection {
+        len: u8,
+    },
+}
+
+impl ScalingRecord {
+    /// encode to scalingByte and scalingByteExtension
+    pub fn encode(&self) -> DoCanResult<Vec<u8>> {
+        let (byte_type, mut extensions, len) = match self {
+            Self::UnsignedNumeric { len } => (ScalingByteType::UnSignedNumeric, vec![], *len),
+            Self::SignedNumeric { len } => (ScalingByteType::SignedNumeric, vec![], *len),
+            Self::BitMapped { mask } => (
+                ScalingByteType::BitMappedReportedWithOutMask,
+                mask.clone(),
+                mask.len() as u8,
+            ),
+            Self::BitMappedWithMask { len } => {
+                (ScalingByteType::BitMappedReportedWithMask, vec![], *len)
+            }
+            Self::Bcd { len } => (ScalingByteType::BinaryCodedDecimal, vec![], *len),
+            Self::StateEncoded { len } => (ScalingByteType::StateEncodedVariable, vec![], *len),
+            Self::Ascii { len } => (ScalingByteType::ASCII, vec![], *len),
+            Self::Float { len } => (ScalingByteType::SignedFloatingPoint, vec![], *len),
+            Self::Packet { len } => (ScalingByteType::Packet, vec![], *len),
+            Self::Formula { id, constants } => {
+                let mut extensions = vec![*id];
+                for &constant in constants {
+                    extensions.extend(encode_real_number(constant)?.to_be_bytes());
+                }
+                let len = extensions.len() as u8;
+                (ScalingByteType::Formula, extensions, len)
+            }
+            Self::Unit { id } => {
+                ScalingByteExtensionUnit::try_from(*id)?;
+                (ScalingByteType::UnitFormat, vec![*id], 1)
+            }
+            Self::StateAndConnection { len } => {
+                (ScalingByteType::StateAndConnectionType, vec![], *len)
+            }
+        };
+
+        if len as usize > SCALING_LEN_MAX || extensions.len() > SCALING_LEN_MAX {
+            return Err(DoCanError::OtherError(format!(
+                "scaling record {:?} is too long",
+                self
+            )));
+        }
+
+        let byte_type: u8 = byte_type.into();
+        let mut result = vec![byte_type | len];
+        result.append(&mut extensions);
+
+        Ok(result)
+    }
+
+    /// the data length of DID described by this record
+    #[inline]
+    fn data_len(&self) -> Option<usize> {
+        match self {
+            Self::UnsignedNumeric { len }
+            | Self::SignedNumeric { len }
+            | Self::BitMappedWithMask { len }
+            | Self::Bcd { len }
+            | Self::StateEncoded { len }
+            | Self::Ascii { len }
+            | Self::Float { len }
+            | Self::Packet { len }
+            | Self::StateAndConnection { len } => Some(*len as usize),
+            Self::BitMapped { mask } => Some(mask.len()),
+            Self::Formula { .. } | Self::Unit { .. } => None,
+        }
+    }
+}
+
+impl TryFrom<ScalingByteData> for ScalingRecord {
+    type Error = DoCanError;
+
+    fn try_from(data: ScalingByteData) -> Result<Self, Self::Error> {
+        let len = data.byte_len;
+        match data.byte_type {
+            ScalingByteType::UnSignedNumeric => Ok(Self::UnsignedNumeric { len }),
+            ScalingByteType::SignedNumeric => Ok(Self::SignedNumeric { len }),
+            ScalingByteType::BitMappedReportedWithOutMask => Ok(Self::BitMapped {
+                mask: data.extensions,
+            }),
+            ScalingByteType::BitMappedReportedWithMask => Ok(Self::BitMappedWithMask { len }),
+            ScalingByteType::BinaryCodedDecimal => Ok(Self::Bcd { len }),
+            ScalingByteType::StateEncodedVariable => Ok(Self::StateEncoded { len }),
+            ScalingByteType::ASCII => Ok(Self::Ascii { len }),
+            ScalingByteType::SignedFloatingPoint => Ok(Self::Float { len }),
+            ScalingByteType::Packet => Ok(Self::Packet { len }),
+            ScalingByteType::Formula => match data.extensions.split_first() {
+                Some((&id, constants)) if constants.len() % 2 == 0 => Ok(Self::Formula {
+                    id,
+                    constants: constants
+                        .chunks(2)
+                        .map(|v| decode_real_number(u16::from_be_bytes([v[0], v[1]])))
+                        .collect(),
+                }),
+                _ => Err(DoCanError::OtherError(format!(
+                    "invalid formula scaling extension: {}",
+                    hex::encode(&data.extensions)
+                ))),
+            },
+            ScalingByteType::UnitFormat => match data.extensions.as_slice() {
+                [id] => Ok(Self::Unit { id: *id }),
+                _ => Err(DoCanError::OtherError(format!(
+                    "invalid unit scaling extension: {}",
+                    hex::encode(&data.extensions)
+                ))),
+            },
+            ScalingByteType::StateAndConnectionType => Ok(Self::StateAndConnection { len }),
+        }
+    }
+}
+
+/// Physical value of DID data decoded by [`ScalingDescription`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScalingValue {
+    Numeric {
+        value: f64,
+        unit: Option<ScalingByteExtensionUnit>,
+    },
+    Text(String),
+    Raw(Vec<u8>),
+}
+
+/// Structured scaling description of a DID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalingDescription {
+    pub did: DataIdentifier,
+    pub records: Vec<ScalingRecord>,
+}
+
+impl TryFrom<ReadScalingDID> for ScalingDescription {
+    type Error = DoCanError;
+
+    fn try_from(resp: ReadScalingDID) -> Result<Self, Self::Error> {
+        let records = std::iter::once(resp.data)
+            .chain(resp.others)
+            .map(ScalingRecord::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            did: resp.did,
+            records,
+        })
+    }
+}
+
+impl ScalingDescription {
+    /// encode to the positive response data of service 24(without service id)
+    pub fn encode(&self) -> DoCanResult<Vec<u8>> {
+        if self.records.is_empty() {
+            return Err(DoCanError::OtherError(format!(
+                "DID: {:?} has no scaling record",
+                self.did
+            )));
+        }
+
+        let did: u16 = self.did.into();
+        let mut result = did.to_be_bytes().to_vec();
+        for record in &self.records {
+            result.append(&mut record.encode()?);
+        }
+
+        Ok(result)
+    }
+
+    /// the unit of DID if present
+    pub fn unit(&self) -> Option<ScalingByteExtensionUnit> {
+        self.records.iter().find_map(|record| match record {
+            ScalingRecord::Unit { id } => ScalingByteExtensionUnit::try_from(*id).ok(),
+            _ => None,
+        })
+    }
+
+    /// the formula of DID if present
+    pub fn formula(&self) -> Option<(Formula, &[f64])> {
+        self.records.iter().find_map(|record| match record {
+            ScalingRecord::Formula { id, constants } => {
+                Some((Formula::from(*id), constants.as_slice()))
+            }
+            _ => None,
+        })
+    }
+
+    /// decode DID data to physical value
+    pub fn physical_value(&self, data: &[u8], byte_order: ByteOrder) -> DoCanResult<ScalingValue> {
+        let record = self
+            .records
+            .iter()
+            .find(|record| record.data_len().is_some())
+            .ok_or_else(|| {
+                DoCanError::OtherError(format!("DID: {:?} has no data scaling record", self.did))
+            })?;
+
+        let expect = record.data_len().unwrap_or_default();
+        if data.len() < expect {
+            return Err(DoCanError::OtherError(format!(
+                "DID: {:?} data length {} is less than {}",
+                self.did,
+                data.len(),
+                expect
+            )));
+        }
+        let data = &data[..expect];
+
+        let raw = match record {
+            ScalingRecord::UnsignedNumeric { .. } => unsigned_numeric(data, byte_order)? as f64,
+            ScalingRecord::SignedNumeric { .. } => signed_numeric(data, byte_order)? as f64,
+            ScalingRecord::Float { len: 4 } => {
+                f32::from_bits(unsigned_numeric(data, byte_order)? as u32) as f64
+            }
+            ScalingRecord::Float { len: 8 } => f64::from_bits(unsigned_numeric(data, byte_order)?),
+            ScalingRecord::Ascii { .. } => {
+                return Ok(ScalingValue::Text(
+                    String::from_utf8_lossy(data)
+                        .trim_end_matches('\0')
+                        .to_string(),
+                ))
+            }
+            _ => return Ok(ScalingValue::Raw(data.to_vec())),
+        };
+
+        let value = match self.formula() {
+            Some((formula, constants)) => apply_formula(formula, constants, raw)?,
+            None => raw,
+        };
+
+        Ok(ScalingValue::Numeric {
+            value,
+            unit: self.unit(),
+        })
+    }
+}
+
+fn unsigned_numeric(data: &[u8], byte_order: ByteOrder) -> DoCanResult<u64> {
+    if data.is_empty() || data.len() > 8 {
+        return Err(DoCanError::OtherError(format!(
+            "unsupported numeric length: {}",
+            data.len()
+        )));
+    }
+
+    let mut buf = [0u8; 8];
+    Ok(if byte_order.is_little() {
+        buf[..data.len()].copy_from_slice(data);
+        u64::from_le_bytes(buf)
+    } else {
+        buf[8 - data.len()..].copy_from_slice(data);
+        u64::from_be_bytes(buf)
+    })
+}
+
+fn signed_numeric(data: &[u8], byte_order: ByteOrder) -> DoCanResult<i64> {
+    let value = unsigned_numeric(data, byte_order)?;
+    let shift = 64 - data.len() * 8;
+    Ok(((value << shift) as i64) >> shift)
+}
+
+fn apply_formula(formula: Formula, constants: &[f64], x: f64) -> DoCanResult<f64> {
+    let c = |index: usize| {
+        constants.get(index).copied().ok_or_else(|| {
+            DoCanError::OtherError(format!("formula {:?} requires C{}", formula, index))
+        })
+    };
+
+    match formula {
+        Formula::Formula0 => Ok(c(0)? * x + c(1)?),
+        Formula::Formula1 => Ok(c(0)? * (x + c(1)?)),
+        Formula::Formula2 => Ok(c(0)? / (x + c(1)?) + c(2)?),
+        Formula::Formula3 => Ok(x / c(0)? + c(1)?),
+        Formula::Formula4 => Ok((x + c(0)?) / c(1)?),
+        Formula::Formula5 => Ok((x + c(0)?) / c(1)? + c(2)?),
+        Formula::Formula6 => Ok(c(0)? * x),
+        Formula::Formula7 => Ok(x / c(0)?),
+        Formula::Formula8 => Ok(x + c(0)?),
+        Formula::Formula9 => Ok(x * c(0)? / c(1)?),
+        Formula::Reserved(_) | Formula::VehicleManufacturerSpecific(_) => Err(
+            DoCanError::OtherError(format!("formula {:?} is not supported", formula)),
+        ),
+    }
+}
+
+/// decode two byte real number(`ISO-14229(2020) Table C.7`):
+/// signed 4 bits exponent and signed 12 bits mantissa
+fn decode_real_number(value: u16) -> f64 {
+    let exponent = ((value as i16) >> 12) as i32;
+    let mantissa = (((value << 4) as i16) >> 4) as f64;
+    mantissa * 10f64.powi(exponent)
+}
+
+/// encode two byte real number with the max precision
+fn encode_real_number(value: f64) -> DoCanResult<u16> {
+    (-8..=7)
+        .find_map(|exponent: i32| {
+            let mantissa = (value / 10f64.powi(exponent)).round();
+            (-2048.0..=2047.0)
+                .contains(&mantissa)
+                .then_some(((exponent as u16 & 0x0F) << 12) | (mantissa as i16 as u16 & 0x0F_FF))
+        })
+        .ok_or_else(|| {
+            DoCanError::OtherError(format!(
+                "{} can't be encoded as two byte real number",
+                value
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_real_number, encode_real_number, ScalingDescription, ScalingRecord};
+    use crate::ScalingValue;
+    use iso14229_1::{
+        response::{ReadScalingDID, Response},
+        Configuration, DataIdentifier, Service,
+    };
+    use rsutil::types::ByteOrder;
+
+    fn speed_description() -> ScalingDescription {
+        ScalingDescription {
+            did: DataIdentifier::from(0x4101),
+            records: vec![
+                ScalingRecord::UnsignedNumeric { len: 2 },
+                ScalingRecord::Formula {
+                    id: 0x00,
+                    constants: vec![0.01, -40.0],
+                },
+                ScalingRecord::Unit { id: 0x30 },
+            ],
+        }
+    }
+
+    #[test]
+    fn real_number_round_trip() {
+        for value in [0.0, 1.0, -40.0, 0.01, 2047.0, -2048.0, 12.5, 65_000.0] {
+            let encoded = encode_real_number(value).unwrap();
+            let decoded = decode_real_number(encoded);
+            assert!((decoded - value).abs() <= value.abs() * 1e-3, "{}", value);
+        }
+
+        assert_eq!(encode_real_number(1.0).unwrap(), 0xD3E8);
+        assert!(encode_real_number(1e12).is_err());
+    }
+
+    #[test]
+    fn description_encode_and_decode_round_trip() {
+        let desc = speed_description();
+        let data = desc.encode().unwrap();
+        assert_eq!(&data[..4], &[0x41, 0x01, 0x02, 0x95]);
+        assert_eq!(data[data.len() - 2..], [0xA1, 0x30]);
+
+        let cfg = Configuration::default();
+        let resp = Response::new(Service::ReadScalingDID, None, data, &cfg).unwrap();
+        let resp = resp.data::<ReadScalingDID>(&cfg).unwrap();
+        let decoded = ScalingDescription::try_from(resp).unwrap();
+        assert_eq!(decoded, desc);
+    }
+
+    #[test]
+    fn physical_value_applies_formula_and_unit() {
+        let desc = speed_description();
+        let value = desc.physical_value(&[0x27, 0x10], ByteOrder::Big).unwrap();
+        match value {
+            ScalingValue::Numeric { value, unit } => {
+                assert!((value - 60.0).abs() < 1e-9);
+                assert_eq!(unit.map(u8::from), Some(0x30));
+            }
+            _ => panic!("unexpected scaling value"),
+        }
+
+        let ascii = ScalingDescription {
+            did: DataIdentifier::VIN,
+            records: vec![ScalingRecord::Ascii { len: 3 }],
+        };
+        assert_eq!(
+            ascii.physical_value(b"ABC", ByteOrder::Big).unwrap(),
+            ScalingValue::Text("ABC".into())
+        );
+    }
+
+    #[test]
+    fn encode_rejects_too_long_record() {
+        let record = ScalingRecord::Ascii { len: 16 };
+        assert!(record.encode().is_err());
+
+        let record = ScalingRecord::Formula {
+            id: 0x00,
+            constants: vec![1.0; 8],
+        };
+        assert!(record.encode().is_err());
+    }
+}
@@ -0,0 +1,166 @@
+//! Session key of Service 29 and secured data of Service 84.
+//!
+//! The session key is agreed by ECDH(P-256) with the ephemeral public keys of proofOfOwnership,
+//! the key material is derived by HKDF-SHA256 with the challenge of server as salt.
+//!
+//! The internal message is signed by HMAC-SHA256 and its parameters(exclude SID) are encrypted by AES-256-GCM.
+
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit,
+};
+use hmac::{Hmac, Mac};
+use p256::{ecdh, elliptic_curve::sec1::ToEncodedPoint, PublicKey, SecretKey};
+use rand::{rng, RngExt};
+use sha2::Sha256;
+
+/// signatureEncryptionCalculation of HMAC-SHA256 and AES-256-GCM
+pub(crate) const SECURED_ALGORITHM: u8 = 0x80;
+/// the HKDF info of session key
+const SESSION_KEY_INFO: &[u8] = b"DoCAN session key";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// The ephemeral key pair of session key agreement.
+pub(crate) struct EphemeralKey(SecretKey);
+
+impl EphemeralKey {
+    pub(crate) fn new() -> Self {
+        loop {
+            let mut bytes = [0u8; KEY_LEN];
+            rng().fill(&mut bytes);
+            if let Ok(key) = SecretKey::from_slice(&bytes) {
+                return Self(key);
+            }
+        }
+    }
+
+    /// the uncompressed SEC1 public key
+    pub(crate) fn public_key(&self) -> Vec<u8> {
+        self.0
+            .public_key()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec()
+    }
+
+    /// Agree the session key with the public key of peer, `salt` is the challenge of server.
+    pub(crate) fn agree(&self, peer: &[u8], salt: &[u8]) -> Option<SessionKey> {
+        let peer = PublicKey::from_sec1_bytes(peer).ok()?;
+        let shared = ecdh::diffie_hellman(self.0.to_nonzero_scalar(), peer.as_affine());
+        let mut material = [0u8; KEY_LEN * 2];
+        shared
+            .extract::<Sha256>(Some(salt))
+            .expand(SESSION_KEY_INFO, &mut material)
+            .ok()?;
+
+        let mut mac = [0u8; KEY_LEN];
+        let mut enc = [0u8; KEY_LEN];
+        mac.copy_from_slice(&material[..KEY_LEN]);
+        enc.copy_from_slice(&material[KEY_LEN..]);
+        Some(SessionKey { mac, enc })
+    }
+}
+
+/// The session key of authenticated client.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct SessionKey {
+    mac: [u8; KEY_LEN],
+    enc: [u8; KEY_LEN],
+}
+
+impl SessionKey {
+    /// The HMAC-SHA256 of header(administrativeParameter, signatureEncryptionCalculation and antiReplayCounter)
+    /// and internal message.
+    pub(crate) fn sign(&self, header: &[u8], message: &[u8]) -> Vec<u8> {
+        self.mac(header, message).finalize().into_bytes().to_vec()
+    }
+
+    pub(crate) fn verify(&self, header: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        self.mac(header, message).verify_slice(signature).is_ok()
+    }
+
+    /// Encrypt the parameters with the header as associated data, the result is `nonce || ciphertext || tag`.
+    pub(crate) fn encrypt(&self, header: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rng().fill(&mut nonce);
+        let cipher = Aes256Gcm::new_from_slice(&self.enc).ok()?;
+        let payload = Payload {
+            msg: data,
+            aad: header,
+        };
+        let mut result = nonce.to_vec();
+        result.extend(cipher.encrypt((&nonce).into(), payload).ok()?);
+        Some(result)
+    }
+
+    pub(crate) fn decrypt(&self, header: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+
+        let (nonce, data) = data.split_at(NONCE_LEN);
+        let cipher = Aes256Gcm::new_from_slice(&self.enc).ok()?;
+        let payload = Payload {
+            msg: data,
+            aad: header,
+        };
+        cipher.decrypt(nonce.into(), payload).ok()
+    }
+
+    fn mac(&self, header: &[u8], message: &[u8]) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.mac)
+            .expect("HMAC accepts keys of any length");
+        mac.update(header);
+        mac.update(message);
+        mac
+    }
+}
+
+/// The header of secured data which is signed and used as associated data of encryption.
+#[inline(always)]
+pub(crate) fn secured_header(apar: u16, algorithm: u8, anti_replay_cnt: u16) -> Vec<u8> {
+    let mut result = apar.to_be_bytes().to_vec();
+    result.push(algorithm);
+    result.extend(anti_replay_cnt.to_be_bytes());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{secured_header, EphemeralKey, SECURED_ALGORITHM};
+
+    #[test]
+    fn agree_session_key() {
+        let client = EphemeralKey::new();
+        let server = EphemeralKey::new();
+        let key = client.agree(&server.public_key(), b"challenge").unwrap();
+        assert_eq!(
+            server.agree(&client.public_key(), b"challenge"),
+            Some(key.clone())
+        );
+        assert_ne!(
+            server.agree(&client.public_key(), b"other challenge"),
+            Some(key)
+        );
+        assert!(client.agree(&[0x04; 65], b"challenge").is_none());
+    }
+
+    #[test]
+    fn sign_and_encrypt_secured_data() {
+        let client = EphemeralKey::new();
+        let key = client
+            .agree(&EphemeralKey::new().public_key(), b"challenge")
+            .unwrap();
+        let header = secured_header(0x0061, SECURED_ALGORITHM, 0x0001);
+
+        let signature = key.sign(&header, &[0x22, 0xF1, 0x90]);
+        assert!(key.verify(&header, &[0x22, 0xF1, 0x90], &signature));
+        assert!(!key.verify(&header, &[0x22, 0xF1, 0x91], &signature));
+
+        let data = key.encrypt(&header, &[0xF1, 0x90]).unwrap();
+        assert_eq!(key.decrypt(&header, &data), Some(vec![0xF1, 0x90]));
+        let other = secured_header(0x0061, SECURED_ALGORITHM, 0x0002);
+        assert_eq!(key.decrypt(&other, &data), None);
+    }
+}
@@ -1,39 +1,113 @@
-use crate::{Config, DoCanError, SecurityAlgo};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{
+        did::{DidAccessType, DidGenerator},
+        dtc::DtcMonitor,
+        request::error_code,
+        routine::RoutineState,
+        storage::{DtcState, MemoryState, NvmState},
+        util, DidSource, FileStorage, Routine, RoutineAccessConfig, SecurityLevelConfig,
+        ServiceAccessConfig, SessionConfig, Storage,
+    },
+    Config, DoCanError, ScalingDescription, SecurityAlgo,
+};
+#[cfg(feature = "std2020")]
+use crate::{
+    pki::{self, auth_data, SigningKey},
+    secured::{secured_header, EphemeralKey, SessionKey, SECURED_ALGORITHM},
+    server::AuthenticationConfig,
+};
 use bytes::{Bytes, BytesMut};
 use iso14229_1::{
     request::{self, ClearDiagnosticInfo, IOCtrl},
     response::{self, Code, DTCFormatIdentifier, SessionTiming},
     utils::U24,
     CheckProgrammingDependencies, CommunicationCtrlType, CommunicationType, Configuration,
-    DTCSettingType, DataFormatIdentifier, DataIdentifier, IOCtrlParameter, MemoryLocation,
-    RoutineCtrlType, RoutineId,
+    DTCSettingType, DataFormatIdentifier, DataIdentifier, DynamicallyMemAddr, EraseMemory,
+    IOCtrlParameter, MemoryLocation, ResponseOnEventType, RoutineCtrlType, RoutineId, Service,
+    SessionType, RECOMMENDED_SERVICES,
+};
+#[cfg(feature = "std2020")]
+use iso14229_1::{AdministrativeParameter, SignatureEncryptionCalculation};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::{collections::HashMap, sync::Arc};
 use tokio::{
-    fs::read,
+    fs::{self, read},
+    io::AsyncWriteExt,
     sync::{Mutex, MutexGuard},
 };
+#[cfg(feature = "std2020")]
+use x509_cert::{
+    der::{asn1::ObjectIdentifier, Encode},
+    spki::SubjectPublicKeyInfoOwned,
+    Certificate,
+};
 
 #[derive(Clone)]
 pub(crate) struct Context {
     pub(crate) config: Config,
     /// static did
     pub(crate) did_st: Arc<Mutex<HashMap<DataIdentifier, Bytes>>>,
-    /// dynamic did
-    pub(crate) did_dyn: Arc<Mutex<HashMap<DataIdentifier, Bytes>>>,
+    /// the source DIDs of dynamic did
+    pub(crate) did_dyn: Arc<Mutex<HashMap<DataIdentifier, Vec<DynamicallyMemAddr>>>>,
+    /// the live data sources of static did
+    pub(crate) did_sources: Arc<Mutex<HashMap<DataIdentifier, DidGenerator>>>,
     pub(crate) sa_algo: Arc<Mutex<Option<SecurityAlgo>>>,
     pub(crate) sa_ctx: Arc<Mutex<Option<(u8, Bytes)>>>,
+    /// the algorithms of security levels which override `sa_algo`
+    pub(crate) sa_level_algo: Arc<Mutex<HashMap<u8, SecurityAlgo>>>,
+    /// the failed attempts of security levels
+    pub(crate) sa_attempts: Arc<Mutex<HashMap<u8, SecurityAttempts>>>,
     #[allow(dead_code)]
     pub(crate) memories: Arc<Mutex<HashMap<MemoryLocation, Bytes>>>,
     pub(crate) dtcs: Arc<Mutex<Vec<DtcRecord>>>,
     pub(crate) dtc_setting_enabled: Arc<Mutex<bool>>,
     pub(crate) active_timing: Arc<Mutex<SessionTiming>>,
     pub(crate) comm_ctrl_state: Arc<Mutex<CommunicationControlState>>,
-    pub(crate) routine_results: Arc<Mutex<HashMap<u16, Vec<u8>>>>,
+    /// the routines which are started
+    pub(crate) routines: Arc<Mutex<HashMap<u16, RoutineState>>>,
+    /// the user handlers of routines
+    pub(crate) routine_handlers: Arc<Mutex<HashMap<u16, Arc<dyn Routine>>>>,
     pub(crate) transfer_meta: Arc<Mutex<Option<TransferMeta>>>,
+    pub(crate) file_transfer: Arc<Mutex<Option<FileTransfer>>>,
+    pub(crate) roe: Arc<Mutex<RoeState>>,
+    /// the persistence backend of non-volatile memory
+    pub(crate) storage: Arc<Mutex<Option<Arc<dyn Storage>>>>,
+    /// the non-volatile memory is changed but not flushed
+    pub(crate) nvm_dirty: Arc<Mutex<bool>>,
+    #[cfg(feature = "std2020")]
+    pub(crate) pki: Option<Arc<AuthPki>>,
+    #[cfg(feature = "std2020")]
+    pub(crate) auth: Arc<Mutex<AuthState>>,
     // pub(crate) session: SessionManager,
 }
 
+/// The failed attempts of a security level.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub(crate) struct SecurityAttempts {
+    pub(crate) count: u8,
+    /// the seed is not accepted before the delay is expired
+    pub(crate) delay_until: Option<Instant>,
+}
+
+/// The file of active RequestFileTransfer.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct FileTransfer {
+    /// the real path under the sandbox root
+    pub(crate) path: PathBuf,
+    /// the file or directory info content of upload
+    pub(crate) content: Bytes,
+    /// the written file of download, it replaces `path` when the transfer is exited
+    pub(crate) temp: Option<PathBuf>,
+}
+
+/// the record number and DID data of a DTC snapshot
+pub(crate) type DtcSnapshot = (u8, Vec<(DataIdentifier, Vec<u8>)>);
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(crate) struct DtcRecord {
     pub(crate) dtc: U24,
@@ -43,9 +117,14 @@ pub(crate) struct DtcRecord {
     pub(crate) fault_counter: u8,
     pub(crate) permanent: bool,
     pub(crate) ext_data: Vec<(u8, Vec<u8>)>,
+    /// snapshot(freeze frame) records, the DID data is captured when the DTC is confirmed
+    pub(crate) snapshots: Vec<DtcSnapshot>,
+    /// the MemorySelection of user defined memory, `None` is the primary memory
+    pub(crate) user_memory: Option<u8>,
     pub(crate) mirror: bool,
     pub(crate) emissions_obd: bool,
     pub(crate) wwh_obd: Option<WwhObdMeta>,
+    pub(crate) monitor: DtcMonitor,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -78,54 +157,459 @@ pub(crate) enum TransferDirection {
     Upload,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum TransferTarget {
+    Memory(MemoryLocation),
+    /// the file context is stored in [`Context::file_transfer`]
+    #[allow(dead_code)]
+    File,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub(crate) struct TransferMeta {
     pub(crate) direction: TransferDirection,
     pub(crate) dfi: DataFormatIdentifier,
-    pub(crate) mem_loc: MemoryLocation,
+    pub(crate) target: TransferTarget,
     pub(crate) max_num_of_block_len: u128,
     pub(crate) next_sequence: u8,
     pub(crate) transferred: u128,
 }
 
+/// The event of ResponseOnEvent.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct RoeEvent {
+    pub(crate) event_type: ResponseOnEventType,
+    /// storeEvent bit of eventType
+    pub(crate) store: bool,
+    pub(crate) window_time: u8,
+    /// eventTypeRecord
+    pub(crate) record: Vec<u8>,
+    /// serviceToRespondToRecord
+    pub(crate) service_record: Vec<u8>,
+    pub(crate) active: bool,
+    /// numberOfIdentifiedEvents in the active window
+    pub(crate) identified: u8,
+    /// the end of the active window, `None` means infinite
+    pub(crate) deadline: Option<Instant>,
+    /// the last observed value of DTC status or DID
+    pub(crate) last: Option<Vec<u8>>,
+    /// the comparison event is re-armed after the condition is left(with hysteresis)
+    pub(crate) armed: bool,
+    /// the next timer interrupt
+    pub(crate) next: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RoeState {
+    pub(crate) events: Vec<RoeEvent>,
+    /// the session in which ResponseOnEvent is started
+    pub(crate) started: Option<SessionType>,
+}
+
+/// The action of an event engine tick.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum RoeAction {
+    /// execute the serviceToRespondToRecord
+    Respond(Vec<u8>),
+    /// the final positive response when the event window is closed
+    WindowClosed(Vec<u8>),
+}
+
+/// The loaded PKI of Authentication.
+#[cfg(feature = "std2020")]
+pub(crate) struct AuthPki {
+    pub(crate) anchor: Certificate,
+    /// the DER certificate and private key of server for bidirectional authentication
+    pub(crate) identity: Option<(Vec<u8>, SigningKey)>,
+    pub(crate) role_oid: Option<ObjectIdentifier>,
+    pub(crate) challenge_len: usize,
+}
+
+#[cfg(feature = "std2020")]
+impl AuthPki {
+    pub(crate) async fn load(config: &AuthenticationConfig) -> Result<Self, DoCanError> {
+        async fn read_file(path: &Path) -> Result<Vec<u8>, DoCanError> {
+            read(path)
+                .await
+                .map_err(|e| DoCanError::OtherError(format!("{}: {:?}", path.display(), e)))
+        }
+        fn certificate(data: &[u8], path: &Path) -> Result<Certificate, DoCanError> {
+            pki::parse_certificate(data).map_err(|_| {
+                DoCanError::OtherError(format!("invalid certificate: {}", path.display()))
+            })
+        }
+
+        let anchor = certificate(
+            &read_file(&config.trust_anchor).await?,
+            &config.trust_anchor,
+        )?;
+        let identity = match (&config.certificate, &config.private_key) {
+            (Some(cert), Some(key)) => {
+                let der = certificate(&read_file(cert).await?, cert)?
+                    .to_der()
+                    .map_err(|e| DoCanError::OtherError(format!("{:?}", e)))?;
+                let key = SigningKey::from_pem(&String::from_utf8_lossy(&read_file(key).await?))
+                    .ok_or_else(|| {
+                        DoCanError::OtherError(format!("invalid private key: {}", key.display()))
+                    })?;
+                Some((der, key))
+            }
+            (None, None) => None,
+            _ => {
+                return Err(DoCanError::OtherError(
+                    "the certificate and private key of authentication must be both present".into(),
+                ))
+            }
+        };
+        let role_oid = match &config.role_oid {
+            Some(v) => Some(
+                ObjectIdentifier::new(v).map_err(|e| DoCanError::OtherError(format!("{:?}", e)))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            anchor,
+            identity,
+            role_oid,
+            challenge_len: config.challenge_len,
+        })
+    }
+}
+
+/// The state of Authentication.
+#[cfg(feature = "std2020")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AuthState {
+    /// the client which is waiting for proofOfOwnership
+    pub(crate) pending: Option<AuthPending>,
+    pub(crate) authenticated: Option<Authenticated>,
+}
+
+/// The authenticated client, it is valid until the default session is entered.
+#[cfg(feature = "std2020")]
+#[derive(Debug, Clone)]
+pub(crate) struct Authenticated {
+    pub(crate) session: SessionType,
+    pub(crate) role: Vec<u8>,
+    /// the key of SecuredDataTransmission if the client sent an ephemeral public key
+    pub(crate) session_key: Option<SessionKey>,
+    /// the antiReplayCounter of last secured request
+    pub(crate) anti_replay_cnt: Option<u16>,
+}
+
+#[cfg(feature = "std2020")]
+#[derive(Debug, Clone)]
+pub(crate) struct AuthPending {
+    /// the public key of verified client certificate
+    pub(crate) public_key: SubjectPublicKeyInfoOwned,
+    /// the challenge generated by server
+    pub(crate) challenge: Vec<u8>,
+    pub(crate) role: Vec<u8>,
+}
+
 impl Context {
     pub async fn new() -> Result<Self, DoCanError> {
-        let reader = read("docan.server.yaml")
+        let config = Self::read_config("docan.server.yaml").await?;
+        Self::from_config(config).await
+    }
+
+    /// Read the configuration of server from the YAML file.
+    pub(crate) async fn read_config<P: AsRef<Path>>(path: P) -> Result<Config, DoCanError> {
+        let reader = read(path)
             .await
             .map_err(|e| DoCanError::OtherError(format!("{:?}", e)))?;
-        let config = serde_yaml::from_slice::<Config>(reader.as_slice())
-            .map_err(|e| DoCanError::OtherError(format!("{:?}", e)))?;
+        serde_yaml::from_slice::<Config>(reader.as_slice())
+            .map_err(|e| DoCanError::OtherError(format!("{:?}", e)))
+    }
+
+    pub(crate) async fn from_config(mut config: Config) -> Result<Self, DoCanError> {
+        let mut ids = HashSet::new();
+        if let Some(id) = config.physical_ids().into_iter().find(|v| !ids.insert(*v)) {
+            return Err(DoCanError::OtherError(format!(
+                "the physical address 0x{:X} of testers is duplicated",
+                id
+            )));
+        }
+        if config.sessions.is_empty() {
+            config.sessions = SessionConfig::defaults();
+        }
+        for (sid, rule) in ServiceAccessConfig::defaults(config.extend_sa_level) {
+            config.service_access.entry(sid).or_insert(rule);
+        }
+        for (id, rule) in RoutineAccessConfig::defaults(config.program_sa_level) {
+            config.routines.entry(id).or_insert(rule);
+        }
         let active_timing = config.timing;
+        for (did, records) in &config.did_scaling {
+            ScalingDescription {
+                did: *did,
+                records: records.clone(),
+            }
+            .encode()?;
+        }
 
-        Ok(Self {
+        let dtcs = preconfigured_dtcs(&config)?;
+        let storage = config
+            .storage
+            .as_ref()
+            .map(|v| Arc::new(FileStorage::new(&v.path)) as Arc<dyn Storage>);
+
+        #[cfg(feature = "std2020")]
+        let pki = match &config.authentication {
+            Some(v) => Some(Arc::new(AuthPki::load(v).await?)),
+            None => None,
+        };
+
+        let context = Self {
             config,
             did_st: Default::default(),
             did_dyn: Default::default(),
+            did_sources: Default::default(),
             sa_algo: Default::default(),
             sa_ctx: Default::default(),
+            sa_level_algo: Default::default(),
+            sa_attempts: Default::default(),
             memories: Default::default(),
-            dtcs: Default::default(),
+            dtcs: Arc::new(Mutex::new(dtcs)),
             dtc_setting_enabled: Arc::new(Mutex::new(true)),
             active_timing: Arc::new(Mutex::new(active_timing)),
             comm_ctrl_state: Arc::new(Mutex::new(CommunicationControlState::default())),
-            routine_results: Default::default(),
+            routines: Default::default(),
+            routine_handlers: Default::default(),
             transfer_meta: Default::default(),
+            file_transfer: Default::default(),
+            roe: Default::default(),
+            storage: Arc::new(Mutex::new(storage)),
+            nvm_dirty: Default::default(),
+            #[cfg(feature = "std2020")]
+            pki,
+            #[cfg(feature = "std2020")]
+            auth: Default::default(),
             // session: Default::default(),
-        })
+        };
+        for (did, source) in &context.config.did_values {
+            context.set_did_source(*did, source.clone()).await?;
+        }
+        context.load_nvm().await?;
+        context.arm_power_up_delay(Instant::now()).await;
+
+        Ok(context)
+    }
+
+    /// Make the context of additional tester, the data of ECU(e.g. DIDs, DTCs and storage) is shared,
+    /// the security seed, timing, transfer, ResponseOnEvent and authentication states are its own.
+    pub(crate) fn for_tester(&self) -> Self {
+        Self {
+            sa_ctx: Default::default(),
+            active_timing: Arc::new(Mutex::new(self.config.timing)),
+            transfer_meta: Default::default(),
+            file_transfer: Default::default(),
+            roe: Default::default(),
+            #[cfg(feature = "std2020")]
+            auth: Default::default(),
+            ..self.clone()
+        }
     }
 
     pub async fn reset(&self) {
+        self.flush_pending_nvm().await;
         self.did_dyn.lock().await.clear();
         let _ = self.sa_ctx.lock().await.take();
-        self.dtcs.lock().await.clear();
+        *self.dtcs.lock().await = preconfigured_dtcs(&self.config).unwrap_or_default();
+        if let Err(e) = self.load_nvm().await {
+            rsutil::warn!(
+                "{} can't load the non-volatile memory: {:?}",
+                LOG_TAG_SERVER,
+                e
+            );
+        }
+        self.arm_power_up_delay(Instant::now()).await;
         *self.dtc_setting_enabled.lock().await = true;
         *self.active_timing.lock().await = self.config.timing;
         *self.comm_ctrl_state.lock().await = CommunicationControlState::default();
-        self.routine_results.lock().await.clear();
+        self.routines.lock().await.clear();
         let _ = self.transfer_meta.lock().await.take();
+        self.abort_file_transfer().await;
+        let mut roe = self.roe.lock().await;
+        roe.started = None;
+        roe.events.retain(|event| event.store);
+        roe.events.iter_mut().for_each(|event| event.active = false);
+        #[cfg(feature = "std2020")]
+        {
+            *self.auth.lock().await = Default::default();
+        }
         // self.session.reset().await;
     }
 
+    /// Replace the persistence backend and reload the non-volatile memory from it.
+    pub(crate) async fn set_storage(&self, storage: Arc<dyn Storage>) -> Result<(), DoCanError> {
+        self.storage.lock().await.replace(storage);
+        self.load_nvm().await
+    }
+
+    /// Set the static value or live data source of DID.
+    pub(crate) async fn set_did_source(
+        &self,
+        did: DataIdentifier,
+        source: DidSource,
+    ) -> Result<(), DoCanError> {
+        let error = |reason: String| DoCanError::InvalidDidConfig {
+            did: did.into(),
+            reason,
+        };
+        let &len = self
+            .config
+            .cfg
+            .did
+            .get(&did)
+            .ok_or_else(|| error("the DID is not defined".into()))?;
+
+        let mut did_sources = self.did_sources.lock().await;
+        match source
+            .initial_value(len, self.config.byte_order)
+            .map_err(error)?
+        {
+            Some(data) => {
+                did_sources.remove(&did);
+                self.did_st.lock().await.insert(did, Bytes::from(data));
+            }
+            None => {
+                did_sources.insert(did, DidGenerator::new(source));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reload the non-volatile DIDs, fault memory and memory regions.
+    async fn load_nvm(&self) -> Result<(), DoCanError> {
+        let Some(storage) = self.storage.lock().await.clone() else {
+            return Ok(());
+        };
+        let Some(data) = storage.load().await? else {
+            return Ok(());
+        };
+
+        let state = NvmState::decode(&data)?;
+        {
+            let mut did_st = self.did_st.lock().await;
+            for (did, data) in state.did_records(self.nvm_dids(), &self.config.cfg) {
+                did_st.insert(did, Bytes::from(data));
+            }
+        }
+        if let Some(dtcs) = state.dtc_records(&self.config.cfg) {
+            *self.dtcs.lock().await = dtcs?;
+        }
+        *self.sa_attempts.lock().await = state
+            .sa_attempts
+            .iter()
+            .map(|(&level, &count)| {
+                let attempts = SecurityAttempts {
+                    count,
+                    delay_until: None,
+                };
+                (level, attempts)
+            })
+            .collect();
+        *self.memories.lock().await = state
+            .memory_records()?
+            .into_iter()
+            .map(|(location, data)| (location, Bytes::from(data)))
+            .collect();
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn nvm_dids(&self) -> &[DataIdentifier] {
+        self.config
+            .storage
+            .as_ref()
+            .map(|v| v.dids.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The non-volatile memory is changed, it's flushed now or by [`Context::nvm_flush_forever`].
+    pub(crate) async fn persist(&self) {
+        if self.storage.lock().await.is_none() {
+            return;
+        }
+
+        match &self.config.storage {
+            Some(v) if v.flush_interval_ms > 0 => *self.nvm_dirty.lock().await = true,
+            _ => {
+                if let Err(e) = self.flush_nvm().await {
+                    rsutil::warn!(
+                        "{} can't flush the non-volatile memory: {:?}",
+                        LOG_TAG_SERVER,
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn flush_nvm(&self) -> Result<(), DoCanError> {
+        let Some(storage) = self.storage.lock().await.clone() else {
+            return Ok(());
+        };
+
+        let mut state = NvmState::default();
+        {
+            let did_st = self.did_st.lock().await;
+            for did in self.nvm_dids() {
+                if let Some(data) = did_st.get(did) {
+                    state.dids.insert(u16::from(*did), data.to_vec());
+                }
+            }
+        }
+        state.dtcs = Some(self.dtcs.lock().await.iter().map(DtcState::from).collect());
+        state.sa_attempts = self
+            .sa_attempts
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, v)| v.count > 0)
+            .map(|(&level, v)| (level, v.count))
+            .collect();
+        state.memories = self
+            .memories
+            .lock()
+            .await
+            .iter()
+            .map(|(location, data)| MemoryState::from((location, data.as_ref())))
+            .collect();
+
+        storage.save(&state.encode()?).await?;
+        *self.nvm_dirty.lock().await = false;
+        Ok(())
+    }
+
+    /// Flush the non-volatile memory if it's changed but not flushed.
+    pub(crate) async fn flush_pending_nvm(&self) {
+        if !*self.nvm_dirty.lock().await {
+            return;
+        }
+
+        if let Err(e) = self.flush_nvm().await {
+            rsutil::warn!(
+                "{} can't flush the non-volatile memory: {:?}",
+                LOG_TAG_SERVER,
+                e
+            );
+        }
+    }
+
+    /// Flush the changed non-volatile memory periodically.
+    pub(crate) async fn nvm_flush_forever(&self, interval_ms: u64) {
+        let mut interval = tokio::time::interval(Duration::from_millis(interval_ms));
+        loop {
+            interval.tick().await;
+            self.flush_pending_nvm().await;
+        }
+    }
+
     #[inline(always)]
     pub async fn get_active_timing(&self) -> SessionTiming {
         *self.active_timing.lock().await
@@ -143,10 +627,14 @@ impl Context {
                 if len != data.len() {
                     false
                 } else {
+                    self.did_sources.lock().await.remove(did);
                     self.did_st
                         .lock()
                         .await
                         .insert(*did, BytesMut::from(data).freeze());
+                    if self.nvm_dids().contains(did) {
+                        self.persist().await;
+                    }
                     true
                 }
             }
@@ -154,200 +642,804 @@ impl Context {
         }
     }
 
+    /// Get the DID value without stepping the live source.
     #[inline(always)]
     pub async fn get_static_did(&self, did: &DataIdentifier) -> Option<Bytes> {
+        if let Some(generator) = self.did_sources.lock().await.get(did) {
+            let &len = self.config.cfg.did.get(did)?;
+            return generator
+                .peek(*did, len, self.config.byte_order)
+                .map(Bytes::from);
+        }
         self.did_get_util(self.did_st.lock().await, &did)
     }
 
+    /// Read the DID value by ReadDID, the live source is stepped.
+    pub(crate) async fn read_static_did(&self, did: &DataIdentifier) -> Option<Bytes> {
+        if let Some(generator) = self.did_sources.lock().await.get_mut(did) {
+            let &len = self.config.cfg.did.get(did)?;
+            return generator
+                .next(*did, len, self.config.byte_order)
+                .map(Bytes::from);
+        }
+        self.did_get_util(self.did_st.lock().await, did)
+    }
+
     #[inline(always)]
     pub fn get_static_did_sa_level(&self, did: &DataIdentifier) -> Option<u8> {
         self.config.did_sa_level.get(did).cloned()
     }
 
-    #[allow(unused)]
-    #[inline(always)]
-    pub async fn set_dynamic_did<T: AsRef<[u8]>>(&mut self, did: &DataIdentifier, data: T) -> bool {
-        match self.config.cfg.did.get(did) {
-            Some(&len) => {
-                let data = data.as_ref();
-                if len != data.len() {
-                    false
-                } else {
-                    self.did_dyn
-                        .lock()
-                        .await
-                        .insert(*did, BytesMut::from(data).freeze());
-                    true
+    /// Check the transition of diagnostic session, return `true` if the server must be reset.
+    pub(crate) fn check_session_transition(
+        &self,
+        from: SessionType,
+        to: SessionType,
+    ) -> Result<bool, Code> {
+        let sessions = &self.config.sessions;
+        if sessions.is_empty() {
+            return Ok(false);
+        }
+        if !sessions.contains_key(&to.into()) {
+            return Err(Code::SubFunctionNotSupported);
+        }
+
+        match sessions.get(&from.into()) {
+            Some(session) => {
+                if !session.transitions.contains(&to.into()) {
+                    return Err(Code::ConditionsNotCorrect);
                 }
+                Ok(from != to && session.reset_on_exit)
             }
-            None => false,
+            None => Ok(false),
         }
     }
 
-    #[allow(unused)]
+    /// The timing of session, the global `timing` is used if absent.
     #[inline(always)]
-    pub async fn get_dynamic_did(&self, did: &DataIdentifier) -> Option<Bytes> {
-        self.did_get_util(self.did_dyn.lock().await, &did)
+    pub(crate) fn session_timing(&self, session: SessionType) -> SessionTiming {
+        self.config
+            .sessions
+            .get(&session.into())
+            .and_then(|v| v.timing)
+            .unwrap_or(self.config.timing)
     }
 
+    /// The simulated processing time of service.
     #[inline(always)]
-    pub fn get_security_salt(&self) -> &[u8] {
-        &self.config.sa_salt
+    pub(crate) fn service_delay(&self, service: u8) -> Option<Duration> {
+        self.config
+            .response_pending
+            .delays
+            .get(&service)
+            .filter(|v| **v > 0)
+            .map(|v| Duration::from_millis(*v))
     }
 
-    #[inline(always)]
-    pub(crate) async fn set_security_algo(&self, alg: SecurityAlgo) {
-        let _ = self.sa_algo.lock().await.replace(alg);
+    /// The time before the first or repeated RequestCorrectlyReceivedResponsePending.
+    pub(crate) fn pending_interval(&self, timing: SessionTiming, first: bool) -> Duration {
+        let cfg = &self.config.response_pending;
+        let (limit, percent) = match first {
+            true => (timing.p2_ms(), cfg.p2_percent),
+            false => (timing.p2_star_ms(), cfg.p2_star_percent),
+        };
+        let ms = limit * percent.clamp(1, 100) as u64 / 100;
+        Duration::from_millis(ms.max(1))
     }
 
+    /// Activate the timing of session.
     #[inline(always)]
-    pub async fn get_security_algo(&self) -> Option<SecurityAlgo> {
-        self.sa_algo.lock().await.clone()
+    pub(crate) async fn set_session_timing(&self, session: SessionType) {
+        *self.active_timing.lock().await = self.session_timing(session);
     }
 
-    #[inline(always)]
-    fn did_get_util<'a>(
-        &self,
-        guard: MutexGuard<'a, HashMap<DataIdentifier, Bytes>>,
-        did: &DataIdentifier,
-    ) -> Option<Bytes> {
-        match guard.get(did) {
-            Some(data) => Some(data.clone()),
-            None => {
-                drop(guard);
-                match self.config.cfg.did.get(did) {
-                    Some(&len) => {
-                        let mut data = Vec::with_capacity(len);
-                        data.resize(len, 0);
-                        Some(Bytes::from(data))
-                    }
-                    None => None,
-                }
-            }
+    /// Exit the session to the default session when S3 is expired,
+    /// the server is reset if `reset_on_exit` of the session is set.
+    pub(crate) async fn exit_session(&self, session: SessionType) {
+        if self
+            .config
+            .sessions
+            .get(&session.into())
+            .is_some_and(|v| v.reset_on_exit)
+        {
+            rsutil::info!(
+                "{} session: {:?} is exited with reset",
+                LOG_TAG_SERVER,
+                session
+            );
+            self.reset().await;
         }
+        self.enter_session(SessionType::Default).await;
     }
 
-    pub(crate) async fn dtc_records(&self) -> Vec<DtcRecord> {
-        self.dtcs.lock().await.clone()
+    /// Enter the session, the DTC setting, communication control and authentication
+    /// are restored when the default session is entered.
+    pub(crate) async fn enter_session(&self, session: SessionType) {
+        self.set_session_timing(session).await;
+        if session == SessionType::Default {
+            *self.dtc_setting_enabled.lock().await = true;
+            *self.comm_ctrl_state.lock().await = CommunicationControlState::default();
+            #[cfg(feature = "std2020")]
+            {
+                self.auth.lock().await.authenticated = None;
+            }
+        }
     }
 
-    #[allow(dead_code)]
-    pub(crate) async fn communication_ctrl_state(&self) -> CommunicationControlState {
-        *self.comm_ctrl_state.lock().await
+    /// Check the access of service in the active session before the request is parsed.
+    pub(crate) fn check_service_access(
+        &self,
+        service: Service,
+        session: SessionType,
+        sa_level: u8,
+    ) -> Result<(), Code> {
+        let Some(rule) = self.config.service_access.get(&service.into()) else {
+            return Ok(());
+        };
+        if !rule.sessions.is_empty() && !rule.sessions.contains(&session.into()) {
+            return Err(Code::ServiceNotSupportedInActiveSession);
+        }
+        if !rule.levels.is_empty() && !rule.levels.contains(&sa_level) {
+            return Err(Code::SecurityAccessDenied);
+        }
+
+        Ok(())
     }
 
-    pub(crate) async fn set_dtc_setting(
+    /// Check the access of sub-function(without suppressPosRspMsgIndicationBit) in the active session.
+    pub(crate) fn check_sub_function_access(
         &self,
-        r#type: DTCSettingType,
-    ) -> Result<DTCSettingType, Code> {
-        let enabled = match r#type {
-            DTCSettingType::On => true,
-            DTCSettingType::Off => false,
-            _ => return Err(Code::SubFunctionNotSupported),
+        service: Service,
+        sub_func: u8,
+        session: SessionType,
+        sa_level: u8,
+    ) -> Result<(), Code> {
+        let Some(rule) = self
+            .config
+            .service_access
+            .get(&service.into())
+            .and_then(|v| v.sub_functions.get(&sub_func))
+        else {
+            return Ok(());
         };
+        if !rule.sessions.is_empty() && !rule.sessions.contains(&session.into()) {
+            return Err(Code::SubFunctionNotSupportedInActiveSession);
+        }
+        if !rule.levels.is_empty() && !rule.levels.contains(&sa_level) {
+            return Err(Code::SecurityAccessDenied);
+        }
 
-        *self.dtc_setting_enabled.lock().await = enabled;
-        Ok(r#type)
+        Ok(())
     }
 
-    pub(crate) async fn communication_ctrl(
+    /// Check the access of DID in the active session.
+    ///
+    /// The DID without policy is checked by `did_sa_level` for reading,
+    /// the extended session and `extend_sa_level` for writing.
+    pub(crate) async fn check_did_access(
         &self,
-        ctrl_type: CommunicationCtrlType,
-        ctrl: &request::CommunicationCtrl,
+        did: &DataIdentifier,
+        access: DidAccessType,
+        session: SessionType,
+        sa_level: u8,
     ) -> Result<(), Code> {
-        match ctrl_type {
-            CommunicationCtrlType::EnableRxAndTx
-            | CommunicationCtrlType::EnableRxAndDisableTx
-            | CommunicationCtrlType::DisableRxAndEnableTx
-            | CommunicationCtrlType::DisableRxAndTx
-            | CommunicationCtrlType::EnableRxAndDisableTxWithEnhancedAddressInformation
-            | CommunicationCtrlType::EnableRxAndTxWithEnhancedAddressInformation => {
-                *self.comm_ctrl_state.lock().await = CommunicationControlState {
-                    ctrl_type,
-                    comm_type: ctrl.comm_type,
-                    node_id: ctrl.node_id.map(Into::into),
-                };
-                Ok(())
-            }
-            CommunicationCtrlType::Reserved(_)
-            | CommunicationCtrlType::VehicleManufacturerSpecific(_)
-            | CommunicationCtrlType::SystemSupplierSpecific(_) => {
-                Err(Code::SubFunctionNotSupported)
+        let Some(policy) = self.config.did_access.get(did) else {
+            return match access {
+                DidAccessType::Read => match self.get_static_did_sa_level(did) {
+                    Some(v) if v != sa_level => Err(Code::SecurityAccessDenied),
+                    _ => Ok(()),
+                },
+                DidAccessType::Write => {
+                    if session != SessionType::Extended {
+                        Err(Code::ServiceNotSupportedInActiveSession)
+                    } else if sa_level != self.config.extend_sa_level {
+                        Err(Code::SecurityAccessDenied)
+                    } else {
+                        Ok(())
+                    }
+                }
+                DidAccessType::IoControl => Ok(()),
+            };
+        };
+
+        let rule = match access {
+            DidAccessType::Read => policy.read.as_ref(),
+            DidAccessType::Write | DidAccessType::IoControl => policy.write.as_ref(),
+        }
+        .ok_or(Code::RequestOutOfRange)?;
+        if !rule.sessions.is_empty() && !rule.sessions.contains(&session.into()) {
+            return Err(Code::RequestOutOfRange);
+        }
+        if (rule.levels.is_empty() && rule.roles.is_empty()) || rule.levels.contains(&sa_level) {
+            return Ok(());
+        }
+        #[cfg(feature = "std2020")]
+        if let Some(role) = self.authenticated_role(session).await {
+            if rule.roles.contains(&role) {
+                return Ok(());
             }
         }
+
+        Err(Code::SecurityAccessDenied)
     }
 
-    pub(crate) async fn io_ctrl(&self, ctrl: &IOCtrl) -> Result<response::IOCtrl, Code> {
-        if !ctrl.mask.is_empty() {
-            return Err(Code::RequestOutOfRange);
-        }
+    pub(crate) fn read_scaling_did(&self, did: &DataIdentifier) -> Result<Vec<u8>, Code> {
+        let records = self
+            .config
+            .did_scaling
+            .get(did)
+            .ok_or(Code::RequestOutOfRange)?;
 
-        if ctrl.option.param != IOCtrlParameter::ShortTermAdjustment {
-            return Err(Code::RequestOutOfRange);
+        ScalingDescription {
+            did: *did,
+            records: records.clone(),
         }
+        .encode()
+        .map_err(|_| Code::RequestOutOfRange)
+    }
 
-        match self.config.cfg.did.get(&ctrl.did) {
-            Some(&len) if len == ctrl.option.state.len() => {
-                self.did_st.lock().await.insert(
-                    ctrl.did,
-                    BytesMut::from(ctrl.option.state.as_slice()).freeze(),
-                );
-            }
-            _ => {
+    /// Append the source DIDs to the definition of dynamic DID,
+    /// the total size must not exceed the length which is defined by `cfg.did`.
+    pub(crate) async fn define_dynamic_did(
+        &self,
+        did: DataIdentifier,
+        sources: &[DynamicallyMemAddr],
+        session: SessionType,
+        sa_level: u8,
+    ) -> Result<(), Code> {
+        let &len = self
+            .config
+            .cfg
+            .did
+            .get(&did)
+            .ok_or(Code::RequestOutOfRange)?;
+        for source in sources {
+            let source_did = DataIdentifier::from(source.did);
+            let &source_len = self
+                .config
+                .cfg
+                .did
+                .get(&source_did)
+                .ok_or(Code::RequestOutOfRange)?;
+            self.check_did_access(&source_did, DidAccessType::Read, session, sa_level)
+                .await?;
+            if source.position == 0
+                || source.mem_size == 0
+                || source.position as usize + source.mem_size as usize - 1 > source_len
+            {
                 return Err(Code::RequestOutOfRange);
             }
         }
 
-        if self.get_static_did(&ctrl.did).await.is_none() {
+        let mut did_dyn = self.did_dyn.lock().await;
+        let defined = did_dyn.get(&did).map(Vec::as_slice).unwrap_or_default();
+        let size = defined
+            .iter()
+            .chain(sources)
+            .map(|v| v.mem_size as usize)
+            .sum::<usize>();
+        if size > len {
             return Err(Code::RequestOutOfRange);
         }
+        did_dyn.entry(did).or_default().extend_from_slice(sources);
 
-        Ok(response::IOCtrl::new(
-            ctrl.did,
-            ctrl.option.param,
-            ctrl.option.state.clone(),
-        ))
+        Ok(())
     }
 
-    pub(crate) async fn routine_ctrl(
+    /// Clear the definition of dynamic DID, all definitions are cleared if `did` is absent.
+    pub(crate) async fn clear_dynamic_did(&self, did: Option<DataIdentifier>) -> Result<(), Code> {
+        let mut did_dyn = self.did_dyn.lock().await;
+        match did {
+            Some(did) => {
+                if !self.config.cfg.did.contains_key(&did) {
+                    return Err(Code::RequestOutOfRange);
+                }
+                did_dyn.remove(&did);
+            }
+            None => did_dyn.clear(),
+        }
+
+        Ok(())
+    }
+
+    /// The data of dynamic DID, the sources are read in the active session and
+    /// the data is padded with 0x00 to the length which is defined by `cfg.did`.
+    pub(crate) async fn read_dynamic_did(
+        &self,
+        did: &DataIdentifier,
+        session: SessionType,
+        sa_level: u8,
+    ) -> Option<Result<Bytes, Code>> {
+        let sources = self.did_dyn.lock().await.get(did).cloned()?;
+        let &len = self.config.cfg.did.get(did)?;
+
+        let mut data = Vec::with_capacity(len);
+        for source in sources {
+            let source_did = DataIdentifier::from(source.did);
+            if let Err(code) = self
+                .check_did_access(&source_did, DidAccessType::Read, session, sa_level)
+                .await
+            {
+                return Some(Err(code));
+            }
+            let start = source.position as usize - 1;
+            match self.get_static_did(&source_did).await.and_then(|v| {
+                v.get(start..start + source.mem_size as usize)
+                    .map(Vec::from)
+            }) {
+                Some(value) => data.extend(value),
+                None => return Some(Err(Code::RequestOutOfRange)),
+            }
+        }
+        data.resize(len, 0x00);
+
+        Some(Ok(Bytes::from(data)))
+    }
+
+    /// The configuration of security level(requestSeed sub-function).
+    fn security_level(&self, level: u8) -> Option<SecurityLevelConfig> {
+        if self.config.security_levels.is_empty() {
+            return Some(Default::default());
+        }
+
+        self.config.security_levels.get(&level).cloned()
+    }
+
+    /// Arm the delay of security levels which is enabled at power-up or the attempts were exceeded.
+    async fn arm_power_up_delay(&self, now: Instant) {
+        let mut sa_attempts = self.sa_attempts.lock().await;
+        let levels = self
+            .config
+            .security_levels
+            .keys()
+            .chain(sa_attempts.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        for level in levels {
+            let Some(cfg) = self.security_level(level) else {
+                continue;
+            };
+            let attempts = sa_attempts.entry(level).or_default();
+            attempts.delay_until = (cfg.power_up_delay || attempts.count >= cfg.max_attempts)
+                .then(|| now + Duration::from_millis(cfg.delay_ms));
+        }
+    }
+
+    /// Generate the seed of security level(requestSeed sub-function), the seed is zero if the level is unlocked.
+    pub(crate) async fn security_seed(
+        &self,
+        level: u8,
+        session: SessionType,
+        unlocked: u8,
+        now: Instant,
+    ) -> Result<Vec<u8>, Code> {
+        let cfg = self
+            .security_level(level)
+            .ok_or(Code::SubFunctionNotSupported)?;
+        if !cfg.sessions.is_empty() && !cfg.sessions.contains(&session.into()) {
+            return Err(Code::SubFunctionNotSupportedInActiveSession);
+        }
+        if self
+            .sa_attempts
+            .lock()
+            .await
+            .get(&level)
+            .and_then(|v| v.delay_until)
+            .is_some_and(|v| now < v)
+        {
+            return Err(Code::RequiredTimeDelayNotExpired);
+        }
+
+        let mut sa_ctx = self.sa_ctx.lock().await;
+        let seed_len = match &cfg.seed {
+            Some(seed) => seed.len(),
+            None => cfg.seed_len.unwrap_or(self.config.seed_len),
+        };
+        if unlocked == level {
+            let _ = sa_ctx.take();
+            return Ok(vec![0x00; seed_len]);
+        }
+
+        let seed = cfg.seed.unwrap_or_else(|| util::gen_seed(seed_len));
+        let _ = sa_ctx.replace((level, Bytes::from(seed.clone())));
+        Ok(seed)
+    }
+
+    /// Verify the key of security level(sendKey sub-function), return the unlocked level.
+    pub(crate) async fn security_key(
+        &self,
+        sub_func: u8,
+        key: &[u8],
+        now: Instant,
+    ) -> Result<u8, Code> {
+        let level = sub_func - 1;
+        let cfg = self
+            .security_level(level)
+            .ok_or(Code::SubFunctionNotSupported)?;
+        let seed = match self.sa_ctx.lock().await.take() {
+            Some((v, seed)) if v == level => seed,
+            _ => return Err(Code::RequestSequenceError),
+        };
+
+        let algo = match self.sa_level_algo.lock().await.get(&level) {
+            Some(&algo) => Some(algo),
+            None => self.get_security_algo().await,
+        }
+        .ok_or(Code::ConditionsNotCorrect)?;
+        let salt = cfg.salt.as_deref().unwrap_or(&self.config.sa_salt);
+        let expected = match algo(level, &seed, salt) {
+            Ok(Some(v)) => v,
+            Ok(None) => return Err(Code::SecurityAccessDenied),
+            Err(e) => {
+                rsutil::warn!("{} error: {} when calculator sa key", LOG_TAG_SERVER, e);
+                return Err(Code::GeneralReject);
+            }
+        };
+
+        let result = {
+            let mut sa_attempts = self.sa_attempts.lock().await;
+            let attempts = sa_attempts.entry(level).or_default();
+            let count = attempts.count;
+            if key == expected.as_slice() {
+                attempts.count = 0;
+                (count > 0, Ok(level))
+            } else {
+                attempts.count = count.saturating_add(1);
+                if attempts.count >= cfg.max_attempts {
+                    attempts.delay_until = Some(now + Duration::from_millis(cfg.delay_ms));
+                    (true, Err(Code::ExceedNumberOfAttempts))
+                } else {
+                    (true, Err(Code::InvalidKey))
+                }
+            }
+        };
+        if result.0 {
+            self.persist().await;
+        }
+
+        result.1
+    }
+
+    #[inline(always)]
+    pub(crate) async fn set_level_security_algo(&self, level: u8, alg: SecurityAlgo) {
+        let _ = self.sa_level_algo.lock().await.insert(level, alg);
+    }
+
+    #[inline(always)]
+    pub(crate) async fn set_security_algo(&self, alg: SecurityAlgo) {
+        let _ = self.sa_algo.lock().await.replace(alg);
+    }
+
+    #[inline(always)]
+    pub async fn get_security_algo(&self) -> Option<SecurityAlgo> {
+        self.sa_algo.lock().await.clone()
+    }
+
+    #[inline(always)]
+    fn did_get_util<'a>(
+        &self,
+        guard: MutexGuard<'a, HashMap<DataIdentifier, Bytes>>,
+        did: &DataIdentifier,
+    ) -> Option<Bytes> {
+        match guard.get(did) {
+            Some(data) => Some(data.clone()),
+            None => {
+                drop(guard);
+                match self.config.cfg.did.get(did) {
+                    Some(&len) => {
+                        let mut data = Vec::with_capacity(len);
+                        data.resize(len, 0);
+                        Some(Bytes::from(data))
+                    }
+                    None => None,
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn dtc_records(&self) -> Vec<DtcRecord> {
+        self.dtcs.lock().await.clone()
+    }
+
+    /// Report the test result of DTC to the fault monitor, the DTC is stored when it's reported at first.
+    ///
+    /// The result is ignored when the DTC setting is off or the DTC isn't configured.
+    pub(crate) async fn report_test_result(&self, dtc: U24, passed: bool) -> bool {
+        if !*self.dtc_setting_enabled.lock().await {
+            return false;
+        }
+
+        let mut dtcs = self.dtcs.lock().await;
+        let index = match dtcs.iter().position(|record| record.dtc == dtc) {
+            Some(index) => index,
+            // the configured DTC is cleared
+            None if self.config.dtcs.iter().any(|v| v.dtc == u32::from(dtc)) => {
+                dtcs.push(DtcRecord::new(dtc));
+                dtcs.len() - 1
+            }
+            None => return false,
+        };
+
+        let record = &mut dtcs[index];
+        if record.test_result(passed, &self.config.dtc_monitor) {
+            self.capture_snapshot(record).await;
+        }
+        drop(dtcs);
+        self.persist().await;
+
+        true
+    }
+
+    pub(crate) async fn start_operation_cycle(&self) {
+        if !*self.dtc_setting_enabled.lock().await {
+            return;
+        }
+
+        self.dtcs
+            .lock()
+            .await
+            .iter_mut()
+            .for_each(|record| record.start_operation_cycle());
+        self.persist().await;
+    }
+
+    pub(crate) async fn end_operation_cycle(&self) {
+        if !*self.dtc_setting_enabled.lock().await {
+            return;
+        }
+
+        self.dtcs
+            .lock()
+            .await
+            .iter_mut()
+            .for_each(|record| record.end_operation_cycle(&self.config.dtc_monitor));
+        self.persist().await;
+    }
+
+    async fn capture_snapshot(&self, record: &mut DtcRecord) {
+        let Some(config) = &self.config.dtc_snapshot else {
+            return;
+        };
+        if config.max_records == 0 {
+            return;
+        }
+
+        let mut data = Vec::with_capacity(config.dids.len());
+        for did in &config.dids {
+            if let Some(value) = self.get_static_did(did).await {
+                data.push((*did, value.to_vec()));
+            }
+        }
+
+        let count = record.snapshots.len() as u8;
+        if count < config.max_records {
+            record.snapshots.push((count + 1, data));
+        } else if let Some(last) = record.snapshots.last_mut() {
+            last.1 = data;
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) async fn communication_ctrl_state(&self) -> CommunicationControlState {
+        *self.comm_ctrl_state.lock().await
+    }
+
+    pub(crate) async fn set_dtc_setting(
+        &self,
+        r#type: DTCSettingType,
+    ) -> Result<DTCSettingType, Code> {
+        let enabled = match r#type {
+            DTCSettingType::On => true,
+            DTCSettingType::Off => false,
+            _ => return Err(Code::SubFunctionNotSupported),
+        };
+
+        *self.dtc_setting_enabled.lock().await = enabled;
+        Ok(r#type)
+    }
+
+    pub(crate) async fn communication_ctrl(
+        &self,
+        ctrl_type: CommunicationCtrlType,
+        ctrl: &request::CommunicationCtrl,
+    ) -> Result<(), Code> {
+        match ctrl_type {
+            CommunicationCtrlType::EnableRxAndTx
+            | CommunicationCtrlType::EnableRxAndDisableTx
+            | CommunicationCtrlType::DisableRxAndEnableTx
+            | CommunicationCtrlType::DisableRxAndTx
+            | CommunicationCtrlType::EnableRxAndDisableTxWithEnhancedAddressInformation
+            | CommunicationCtrlType::EnableRxAndTxWithEnhancedAddressInformation => {
+                *self.comm_ctrl_state.lock().await = CommunicationControlState {
+                    ctrl_type,
+                    comm_type: ctrl.comm_type,
+                    node_id: ctrl.node_id.map(Into::into),
+                };
+                Ok(())
+            }
+            CommunicationCtrlType::Reserved(_)
+            | CommunicationCtrlType::VehicleManufacturerSpecific(_)
+            | CommunicationCtrlType::SystemSupplierSpecific(_) => {
+                Err(Code::SubFunctionNotSupported)
+            }
+        }
+    }
+
+    pub(crate) async fn io_ctrl(&self, ctrl: &IOCtrl) -> Result<response::IOCtrl, Code> {
+        if !ctrl.mask.is_empty() {
+            return Err(Code::RequestOutOfRange);
+        }
+
+        if ctrl.option.param != IOCtrlParameter::ShortTermAdjustment {
+            return Err(Code::RequestOutOfRange);
+        }
+
+        match self.config.cfg.did.get(&ctrl.did) {
+            Some(&len) if len == ctrl.option.state.len() => {
+                self.did_st.lock().await.insert(
+                    ctrl.did,
+                    BytesMut::from(ctrl.option.state.as_slice()).freeze(),
+                );
+            }
+            _ => {
+                return Err(Code::RequestOutOfRange);
+            }
+        }
+
+        if self.get_static_did(&ctrl.did).await.is_none() {
+            return Err(Code::RequestOutOfRange);
+        }
+
+        Ok(response::IOCtrl::new(
+            ctrl.did,
+            ctrl.option.param,
+            ctrl.option.state.clone(),
+        ))
+    }
+
+    /// Register the handler of routine, the built-in routine of the same id is overridden.
+    pub(crate) async fn set_routine(&self, routine_id: RoutineId, routine: Arc<dyn Routine>) {
+        self.routine_handlers
+            .lock()
+            .await
+            .insert(routine_id.into(), routine);
+    }
+
+    /// Check the access of routine in the active session.
+    fn check_routine_access(
+        &self,
+        routine_id: RoutineId,
+        session: SessionType,
+        sa_level: u8,
+    ) -> Result<(), Code> {
+        let Some(rule) = self.config.routines.get(&routine_id.into()) else {
+            return Ok(());
+        };
+        if !rule.sessions.is_empty() && !rule.sessions.contains(&session.into()) {
+            return Err(Code::RequestOutOfRange);
+        }
+        if !rule.levels.is_empty() && !rule.levels.contains(&sa_level) {
+            return Err(Code::SecurityAccessDenied);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn routine_ctrl(
         &self,
         r#type: RoutineCtrlType,
         routine_id: RoutineId,
         option_record: &[u8],
+        session: SessionType,
+        sa_level: u8,
     ) -> Result<response::RoutineCtrl, Code> {
-        if routine_id != CheckProgrammingDependencies {
+        let id = u16::from(routine_id);
+        let handler = self.routine_handlers.lock().await.get(&id).cloned();
+        if handler.is_none() && ![EraseMemory, CheckProgrammingDependencies].contains(&routine_id) {
             return Err(Code::RequestOutOfRange);
         }
+        let stoppable = handler.as_ref().is_some_and(|v| v.stoppable());
+        if r#type == RoutineCtrlType::StopRoutine && !stoppable {
+            return Err(Code::SubFunctionNotSupported);
+        }
+        self.check_routine_access(routine_id, session, sa_level)?;
+        if r#type != RoutineCtrlType::RequestRoutineResults {
+            match &handler {
+                Some(handler) => handler.check_option(r#type, option_record)?,
+                None => Self::check_builtin_option(routine_id, option_record)?,
+            }
+        }
 
-        match r#type {
-            RoutineCtrlType::StartRoutine => {
-                if !option_record.is_empty() {
-                    return Err(Code::RequestOutOfRange);
+        let state = self.routines.lock().await.get(&id).cloned();
+        let record = match (r#type, handler) {
+            (RoutineCtrlType::StartRoutine, handler) => {
+                if state.is_some_and(|v| v.running) {
+                    return Err(Code::RequestSequenceError);
                 }
-
-                let result = vec![0x00];
-                self.routine_results
-                    .lock()
-                    .await
-                    .insert(routine_id.into(), result.clone());
-                response::RoutineCtrl::new(routine_id, Some(0x00), result)
-                    .map_err(|_| Code::GeneralReject)
+                let record = match handler {
+                    Some(handler) => handler.start(option_record).await?,
+                    None => {
+                        self.start_builtin_routine(routine_id, option_record)
+                            .await?
+                    }
+                };
+                let state = RoutineState {
+                    running: stoppable,
+                    record: record.clone(),
+                };
+                self.routines.lock().await.insert(id, state);
+                record
             }
-            RoutineCtrlType::RequestRoutineResults => {
-                let result = self
-                    .routine_results
-                    .lock()
-                    .await
-                    .get(&u16::from(routine_id))
-                    .cloned();
-                match result {
-                    Some(result) => response::RoutineCtrl::new(routine_id, Some(0x00), result)
-                        .map_err(|_| Code::GeneralReject),
-                    None => Err(Code::RequestSequenceError),
+            (RoutineCtrlType::StopRoutine, Some(handler)) => {
+                if !state.is_some_and(|v| v.running) {
+                    return Err(Code::RequestSequenceError);
+                }
+                let record = handler.stop(option_record).await?;
+                let state = RoutineState {
+                    running: false,
+                    record: record.clone(),
+                };
+                self.routines.lock().await.insert(id, state);
+                record
+            }
+            (RoutineCtrlType::StopRoutine, None) => return Err(Code::SubFunctionNotSupported),
+            (RoutineCtrlType::RequestRoutineResults, handler) => {
+                let Some(state) = state else {
+                    return Err(Code::RequestSequenceError);
+                };
+                match handler {
+                    Some(handler) => handler.results(&state.record).await?,
+                    None => state.record,
                 }
             }
-            RoutineCtrlType::StopRoutine => Err(Code::SubFunctionNotSupported),
+        };
+
+        response::RoutineCtrl::new(routine_id, Some(0x00), record).map_err(|_| Code::GeneralReject)
+    }
+
+    /// Check the option record of built-in routine.
+    fn check_builtin_option(routine_id: RoutineId, option_record: &[u8]) -> Result<(), Code> {
+        if routine_id == EraseMemory {
+            // the memory to be erased, encoded as ReadMemoryByAddress
+            let mem_loc = MemoryLocation::from_slice(option_record).map_err(|e| error_code(&e))?;
+            if mem_loc.len() != option_record.len() {
+                return Err(Code::IncorrectMessageLengthOrInvalidFormat);
+            }
+        } else if !option_record.is_empty() {
+            return Err(Code::RequestOutOfRange);
+        }
+
+        Ok(())
+    }
+
+    /// Start the built-in routine, the routineStatusRecord is `0x00`(correct result).
+    async fn start_builtin_routine(
+        &self,
+        routine_id: RoutineId,
+        option_record: &[u8],
+    ) -> Result<Vec<u8>, Code> {
+        if routine_id == EraseMemory {
+            if self.transfer_meta.lock().await.is_some() {
+                return Err(Code::ConditionsNotCorrect);
+            }
+
+            let mem_loc = MemoryLocation::from_slice(option_record).map_err(|e| error_code(&e))?;
+            let start = mem_loc.memory_address();
+            let end = start.saturating_add(mem_loc.memory_size());
+            let mut memories = self.memories.lock().await;
+            let count = memories.len();
+            memories.retain(|location, _| {
+                let address = location.memory_address();
+                address >= end || address.saturating_add(location.memory_size()) <= start
+            });
+            let erased = memories.len() != count;
+            drop(memories);
+            if erased {
+                self.persist().await;
+            }
         }
+
+        Ok(vec![0x00])
     }
 
     pub(crate) async fn request_download(
@@ -356,7 +1448,7 @@ impl Context {
         mem_loc: MemoryLocation,
     ) -> Result<response::RequestDownload, Code> {
         let meta = self
-            .start_transfer(TransferDirection::Download, dfi, mem_loc)
+            .start_memory_transfer(TransferDirection::Download, dfi, mem_loc)
             .await?;
         response::RequestDownload::new(meta.max_num_of_block_len)
             .map_err(|_| Code::UploadDownloadNotAccepted)
@@ -368,13 +1460,13 @@ impl Context {
         mem_loc: MemoryLocation,
     ) -> Result<response::RequestUpload, Code> {
         let meta = self
-            .start_transfer(TransferDirection::Upload, dfi, mem_loc)
+            .start_memory_transfer(TransferDirection::Upload, dfi, mem_loc)
             .await?;
         response::RequestUpload::new(meta.max_num_of_block_len)
             .map_err(|_| Code::UploadDownloadNotAccepted)
     }
 
-    async fn start_transfer(
+    async fn start_memory_transfer(
         &self,
         direction: TransferDirection,
         dfi: DataFormatIdentifier,
@@ -392,11 +1484,12 @@ impl Context {
         let meta = TransferMeta {
             direction,
             dfi,
-            mem_loc,
+            target: TransferTarget::Memory(mem_loc),
             max_num_of_block_len,
             next_sequence: 1,
             transferred: 0,
         };
+        self.abort_file_transfer().await;
         self.transfer_meta.lock().await.replace(meta);
         Ok(meta)
     }
@@ -424,18 +1517,41 @@ impl Context {
                     transfer_meta.replace(meta);
                     return Err(Code::RequestOutOfRange);
                 }
+                // the maxNumberOfBlockLength of RequestFileTransfer response
+                if let (TransferTarget::File, Some(cfg)) = (meta.target, &self.config.file_transfer)
+                {
+                    if data.len() > cfg.block_data_len() {
+                        transfer_meta.replace(meta);
+                        return Err(Code::IncorrectMessageLengthOrInvalidFormat);
+                    }
+                }
 
-                let mut memories = self.memories.lock().await;
-                let entry = memories.entry(meta.mem_loc).or_insert_with(Bytes::new);
-                let mut buf = BytesMut::from(entry.as_ref());
-                buf.extend_from_slice(data);
-                *entry = buf.freeze();
+                match meta.target {
+                    TransferTarget::Memory(mem_loc) => {
+                        let mut memories = self.memories.lock().await;
+                        let entry = memories.entry(mem_loc).or_insert_with(Bytes::new);
+                        let mut buf = BytesMut::from(entry.as_ref());
+                        buf.extend_from_slice(data);
+                        *entry = buf.freeze();
+                    }
+                    TransferTarget::File => {
+                        if let Err(code) = self.append_file(data).await {
+                            transfer_meta.replace(meta);
+                            return Err(code);
+                        }
+                    }
+                }
                 meta.transferred += chunk_len;
                 Vec::new()
             }
             TransferDirection::Upload => {
                 let memories = self.memories.lock().await;
-                let Some(memory) = memories.get(&meta.mem_loc) else {
+                let file_transfer = self.file_transfer.lock().await;
+                let memory = match meta.target {
+                    TransferTarget::Memory(mem_loc) => memories.get(&mem_loc),
+                    TransferTarget::File => file_transfer.as_ref().map(|file| &file.content),
+                };
+                let Some(memory) = memory else {
                     transfer_meta.replace(meta);
                     return Err(Code::RequestOutOfRange);
                 };
@@ -453,7 +1569,12 @@ impl Context {
 
                 let start =
                     usize::try_from(meta.transferred).map_err(|_| Code::RequestOutOfRange)?;
-                let end_u128 = meta.transferred + remaining;
+                let end_u128 = match (meta.target, &self.config.file_transfer) {
+                    (TransferTarget::File, Some(cfg)) => {
+                        meta.transferred + remaining.min(cfg.block_data_len() as u128)
+                    }
+                    _ => meta.transferred + remaining,
+                };
                 let end = usize::try_from(end_u128).map_err(|_| Code::RequestOutOfRange)?;
                 let chunk = memory.slice(start..end.min(memory.len())).to_vec();
                 if chunk.is_empty() {
@@ -488,12 +1609,313 @@ impl Context {
             return Err(Code::RequestSequenceError);
         }
 
-        let _ = transfer_meta.take();
+        // the downloaded memory is persisted once the transfer is completed
+        if let Some(TransferMeta {
+            direction: TransferDirection::Download,
+            target: TransferTarget::Memory(_),
+            ..
+        }) = transfer_meta.take()
+        {
+            self.persist().await;
+        }
+        #[cfg(any(feature = "std2013", feature = "std2020"))]
+        if let Some(FileTransfer {
+            path,
+            temp: Some(temp),
+            ..
+        }) = self.file_transfer.lock().await.take()
+        {
+            if !Self::verify_file_crc(&temp, data).await || fs::rename(&temp, &path).await.is_err()
+            {
+                let _ = fs::remove_file(&temp).await;
+                return Err(Code::GeneralProgrammingFailure);
+            }
+        }
         Ok(response::RequestTransferExit {
             data: data.to_vec(),
         })
     }
 
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    pub(crate) async fn request_file_transfer(
+        &self,
+        req: request::RequestFileTransfer,
+    ) -> Result<Vec<u8>, Code> {
+        use crate::server::util::sandbox_path;
+        use iso14229_1::LengthFormatIdentifier;
+        use request::RequestFileTransfer;
+
+        let cfg = self
+            .config
+            .file_transfer
+            .as_ref()
+            .ok_or(Code::ServiceNotSupported)?;
+        let filepath = match &req {
+            RequestFileTransfer::AddFile { filepath, .. }
+            | RequestFileTransfer::DeleteFile { filepath }
+            | RequestFileTransfer::ReplaceFile { filepath, .. }
+            | RequestFileTransfer::ReadFile { filepath, .. }
+            | RequestFileTransfer::ReadDir { filepath }
+            | RequestFileTransfer::ResumeFile { filepath, .. } => filepath,
+        };
+        let path = sandbox_path(&cfg.root, filepath).ok_or(Code::RequestOutOfRange)?;
+        self.abort_file_transfer().await;
+        let lfi = LengthFormatIdentifier::new(2).map_err(|_| Code::GeneralReject)?;
+        let mut result = vec![lfi.into()];
+        result.extend(cfg.max_block_len.to_be_bytes());
+
+        match req {
+            RequestFileTransfer::AddFile {
+                dfi,
+                uncompressed_size,
+                ..
+            }
+            | RequestFileTransfer::ReplaceFile {
+                dfi,
+                uncompressed_size,
+                ..
+            } => {
+                let is_add = matches!(req, RequestFileTransfer::AddFile { .. });
+                if is_add && fs::try_exists(&path).await.unwrap_or(true) {
+                    return Err(Code::RequestOutOfRange);
+                }
+                if path.is_dir() {
+                    return Err(Code::RequestOutOfRange);
+                }
+                Self::check_file_dfi(dfi, uncompressed_size)?;
+
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .await
+                        .map_err(|_| Code::UploadDownloadNotAccepted)?;
+                }
+                let temp = Self::create_temp_file(&path, &[]).await?;
+
+                self.start_file_transfer(
+                    TransferDirection::Download,
+                    dfi,
+                    uncompressed_size,
+                    0,
+                    FileTransfer {
+                        path,
+                        content: Bytes::new(),
+                        temp: Some(temp),
+                    },
+                )
+                .await;
+                result.push(dfi.into());
+            }
+            RequestFileTransfer::DeleteFile { .. } => {
+                if !path.is_file() {
+                    return Err(Code::RequestOutOfRange);
+                }
+                fs::remove_file(&path)
+                    .await
+                    .map_err(|_| Code::ConditionsNotCorrect)?;
+                // the response of DeleteFile has no parameter after modeOfOperation
+                result.clear();
+            }
+            RequestFileTransfer::ReadFile { dfi, .. } => {
+                if dfi.compression() != 0 || dfi.encryption() != 0 {
+                    return Err(Code::UploadDownloadNotAccepted);
+                }
+                if !path.is_file() {
+                    return Err(Code::RequestOutOfRange);
+                }
+                let content = fs::read(&path)
+                    .await
+                    .map_err(|_| Code::UploadDownloadNotAccepted)?;
+                let size = content.len() as u64;
+
+                self.start_file_transfer(
+                    TransferDirection::Upload,
+                    dfi,
+                    size as u128,
+                    0,
+                    FileTransfer {
+                        path,
+                        content: Bytes::from(content),
+                        temp: None,
+                    },
+                )
+                .await;
+                result.push(dfi.into());
+                result.extend((size_of::<u64>() as u16).to_be_bytes());
+                result.extend(size.to_be_bytes());
+                result.extend(size.to_be_bytes());
+            }
+            RequestFileTransfer::ReadDir { .. } => {
+                if !path.is_dir() {
+                    return Err(Code::RequestOutOfRange);
+                }
+                let content = Self::dir_info(&path)
+                    .await
+                    .map_err(|_| Code::UploadDownloadNotAccepted)?;
+                let size = content.len() as u64;
+
+                self.start_file_transfer(
+                    TransferDirection::Upload,
+                    DataFormatIdentifier::default(),
+                    size as u128,
+                    0,
+                    FileTransfer {
+                        path,
+                        content: Bytes::from(content),
+                        temp: None,
+                    },
+                )
+                .await;
+                result.push(DataFormatIdentifier::default().into());
+                result.extend((size_of::<u64>() as u16).to_be_bytes());
+                result.extend(size.to_be_bytes());
+            }
+            RequestFileTransfer::ResumeFile {
+                dfi,
+                uncompressed_size,
+                ..
+            } => {
+                if !path.is_file() {
+                    return Err(Code::RequestOutOfRange);
+                }
+                Self::check_file_dfi(dfi, uncompressed_size)?;
+                let content = fs::read(&path)
+                    .await
+                    .map_err(|_| Code::UploadDownloadNotAccepted)?;
+                let position = content.len() as u64;
+                if position as u128 >= uncompressed_size {
+                    return Err(Code::RequestSequenceError);
+                }
+                // the existing content is kept if the resumed transfer is aborted
+                let temp = Self::create_temp_file(&path, &content).await?;
+
+                self.start_file_transfer(
+                    TransferDirection::Download,
+                    dfi,
+                    uncompressed_size,
+                    position as u128,
+                    FileTransfer {
+                        path,
+                        content: Bytes::new(),
+                        temp: Some(temp),
+                    },
+                )
+                .await;
+                result.push(dfi.into());
+                result.extend(position.to_be_bytes());
+            }
+        }
+
+        Ok(result)
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[inline(always)]
+    fn check_file_dfi(dfi: DataFormatIdentifier, size: u128) -> Result<(), Code> {
+        if dfi.compression() != 0 || dfi.encryption() != 0 {
+            return Err(Code::UploadDownloadNotAccepted);
+        }
+        if size == 0 {
+            return Err(Code::RequestOutOfRange);
+        }
+
+        Ok(())
+    }
+
+    /// one entry per line, the directory is end with `/`
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    async fn dir_info(path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+        let mut entries = Vec::new();
+        let mut dir = fs::read_dir(path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let mut name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type().await?.is_dir() {
+                name.push('/');
+            }
+            entries.push(name);
+        }
+        entries.sort();
+
+        Ok(entries.join("\n").into_bytes())
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    async fn start_file_transfer(
+        &self,
+        direction: TransferDirection,
+        dfi: DataFormatIdentifier,
+        size: u128,
+        transferred: u128,
+        file: FileTransfer,
+    ) {
+        let meta = TransferMeta {
+            direction,
+            dfi,
+            target: TransferTarget::File,
+            max_num_of_block_len: size,
+            next_sequence: 1,
+            transferred,
+        };
+        self.file_transfer.lock().await.replace(file);
+        self.transfer_meta.lock().await.replace(meta);
+    }
+
+    /// Create the temporary file of download beside the target with the initial content,
+    /// the existing entry(even a link) is removed and never followed.
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    async fn create_temp_file(path: &std::path::Path, content: &[u8]) -> Result<PathBuf, Code> {
+        let name = path.file_name().ok_or(Code::RequestOutOfRange)?;
+        let temp = path.with_file_name(format!(".{}.part", name.to_string_lossy()));
+        let _ = fs::remove_file(&temp).await;
+        let mut f = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .await
+            .map_err(|_| Code::UploadDownloadNotAccepted)?;
+        f.write_all(content)
+            .await
+            .map_err(|_| Code::UploadDownloadNotAccepted)?;
+
+        Ok(temp)
+    }
+
+    /// Verify the downloaded file with the CRC32 of transferRequestParameterRecord,
+    /// the file without a 4 bytes record isn't verified.
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    async fn verify_file_crc(path: &std::path::Path, record: &[u8]) -> bool {
+        let Ok(expect) = <[u8; 4]>::try_from(record) else {
+            return true;
+        };
+
+        match read(path).await {
+            Ok(content) => crate::crc::crc32(&content) == u32::from_be_bytes(expect),
+            Err(_) => false,
+        }
+    }
+
+    /// Abort the active file transfer, the temporary file of download is deleted.
+    async fn abort_file_transfer(&self) {
+        let file = self.file_transfer.lock().await.take();
+        if let Some(temp) = file.and_then(|v| v.temp) {
+            let _ = fs::remove_file(temp).await;
+        }
+    }
+
+    async fn append_file(&self, data: &[u8]) -> Result<(), Code> {
+        let guard = self.file_transfer.lock().await;
+        let file = guard.as_ref().ok_or(Code::RequestSequenceError)?;
+        let temp = file.temp.as_ref().ok_or(Code::RequestSequenceError)?;
+        let mut f = fs::OpenOptions::new()
+            .append(true)
+            .open(temp)
+            .await
+            .map_err(|_| Code::GeneralProgrammingFailure)?;
+        f.write_all(data)
+            .await
+            .map_err(|_| Code::GeneralProgrammingFailure)?;
+        f.flush().await.map_err(|_| Code::GeneralProgrammingFailure)
+    }
+
     #[cfg(any(feature = "std2006", feature = "std2013"))]
     pub(crate) async fn access_timing_parameter(
         &self,
@@ -547,30 +1969,648 @@ impl Context {
         let group = info.group();
         if group == 0xFF_FF_FF {
             self.dtcs.lock().await.clear();
+            self.persist().await;
         } else {
             return Err(Code::RequestOutOfRange);
         }
 
         Ok(())
     }
+
+    /// Process the request of Authentication and return the positive response data(exclude sub-function).
+    #[cfg(feature = "std2020")]
+    pub(crate) async fn authentication(
+        &self,
+        session: SessionType,
+        req: request::Authentication,
+    ) -> Result<Vec<u8>, Code> {
+        use iso14229_1::response::AuthReturnValue;
+
+        let pki = self.pki.as_ref().ok_or(Code::SubFunctionNotSupported)?;
+        let mut auth = self.auth.lock().await;
+        match req {
+            request::Authentication::DeAuthenticate => {
+                *auth = Default::default();
+                Ok(response::Authentication::DeAuthenticate(
+                    AuthReturnValue::DeAuthenticationSuccessful,
+                )
+                .into())
+            }
+            request::Authentication::VerifyCertificateUnidirectional { certificate, .. } => {
+                let (public_key, role) = Self::auth_verify_certificate(pki, certificate)?;
+                let challenge = util::gen_seed(pki.challenge_len);
+                auth.authenticated = None;
+                auth.pending = Some(AuthPending {
+                    public_key,
+                    challenge: challenge.clone(),
+                    role,
+                });
+
+                Ok(response::Authentication::VerifyCertificateUnidirectional {
+                    value: AuthReturnValue::CertificateVerifiedOrOwnershipVerificationNecessary,
+                    challenge: auth_data_new(challenge)?,
+                    ephemeral_public_key: Default::default(),
+                }
+                .into())
+            }
+            request::Authentication::VerifyCertificateBidirectional {
+                certificate,
+                challenge: client_challenge,
+                ..
+            } => {
+                let (server_cert, key) =
+                    pki.identity.as_ref().ok_or(Code::SubFunctionNotSupported)?;
+                let (public_key, role) = Self::auth_verify_certificate(pki, certificate)?;
+                let proof = key.sign(&auth_data(client_challenge));
+                let challenge = util::gen_seed(pki.challenge_len);
+                auth.authenticated = None;
+                auth.pending = Some(AuthPending {
+                    public_key,
+                    challenge: challenge.clone(),
+                    role,
+                });
+
+                // encoded manually, the codec writes the ephemeralPublicKey before the certificate
+                let mut result = vec![
+                    AuthReturnValue::CertificateVerifiedOrOwnershipVerificationNecessary.into(),
+                ];
+                for data in [challenge, server_cert.clone(), proof, vec![]] {
+                    result.extend((data.len() as u16).to_be_bytes());
+                    result.extend(data);
+                }
+
+                Ok(result)
+            }
+            request::Authentication::ProofOfOwnership {
+                proof_of_ownership,
+                ephemeral_public_key,
+            } => {
+                let pending = auth.pending.take().ok_or(Code::RequestSequenceError)?;
+                pki::verify_signature(
+                    &pending.public_key,
+                    &pending.challenge,
+                    &auth_data(proof_of_ownership),
+                )?;
+
+                let ephemeral_public_key = auth_data(ephemeral_public_key);
+                let (session_key, session_keyinfo) = if ephemeral_public_key.is_empty() {
+                    (None, vec![])
+                } else {
+                    let ephemeral = EphemeralKey::new();
+                    let key = ephemeral
+                        .agree(&ephemeral_public_key, &pending.challenge)
+                        .ok_or(Code::SessionKeyCreationDerivationFailed)?;
+                    (Some(key), ephemeral.public_key())
+                };
+                auth.authenticated = Some(Authenticated {
+                    session,
+                    role: pending.role,
+                    session_key,
+                    anti_replay_cnt: None,
+                });
+
+                Ok(response::Authentication::ProofOfOwnership {
+                    value: AuthReturnValue::OwnershipVerifiedOrAuthenticationComplete,
+                    session_keyinfo: iso14229_1::NullableData::new(session_keyinfo)
+                        .map_err(|_| Code::SessionKeyCreationDerivationFailed)?,
+                }
+                .into())
+            }
+            request::Authentication::TransmitCertificate { certificate, .. } => {
+                Self::auth_verify_certificate(pki, certificate)?;
+                Ok(response::Authentication::TransmitCertificate(
+                    AuthReturnValue::CertificateVerified,
+                )
+                .into())
+            }
+            request::Authentication::AuthenticationConfiguration => {
+                Ok(response::Authentication::AuthenticationConfiguration(
+                    AuthReturnValue::AuthenticationConfigurationAPCE,
+                )
+                .into())
+            }
+            request::Authentication::RequestChallengeForAuthentication { .. }
+            | request::Authentication::VerifyProofOfOwnershipUnidirectional { .. }
+            | request::Authentication::VerifyProofOfOwnershipBidirectional { .. } => {
+                Err(Code::SubFunctionNotSupported)
+            }
+        }
+    }
+
+    /// The role of authenticated client, the authentication is valid until the default session is entered.
+    #[cfg(feature = "std2020")]
+    pub(crate) async fn authenticated_role(&self, session: SessionType) -> Option<Vec<u8>> {
+        let mut auth = self.auth.lock().await;
+        Self::authenticated(&mut auth, session).map(|v| v.role.clone())
+    }
+
+    /// Verify and decrypt the secured request with the session key, return the internal request(include SID).
+    #[cfg(feature = "std2020")]
+    pub(crate) async fn secured_data_unwrap(
+        &self,
+        session: SessionType,
+        req: &request::SecuredDataTrans,
+    ) -> Result<Vec<u8>, Code> {
+        let mut auth = self.auth.lock().await;
+        let authenticated =
+            Self::authenticated(&mut auth, session).ok_or(Code::SecureDataVerificationFailed)?;
+        let key = authenticated
+            .session_key
+            .as_ref()
+            .ok_or(Code::SecureDataVerificationFailed)?;
+        let apar = req.apar;
+        if u8::from(req.signature) != SECURED_ALGORITHM
+            || !(apar.is_signed() || apar.is_encrypted())
+            || authenticated
+                .anti_replay_cnt
+                .is_some_and(|v| req.anti_replay_cnt <= v)
+        {
+            return Err(Code::SecureDataVerificationFailed);
+        }
+
+        let header = secured_header(apar.into(), SECURED_ALGORITHM, req.anti_replay_cnt);
+        let mut message = vec![req.service];
+        message.extend(&req.service_data);
+        if apar.is_signed() && !key.verify(&header, &message, &req.signature_data) {
+            return Err(Code::SecureDataVerificationFailed);
+        }
+        if apar.is_encrypted() {
+            let data = key
+                .decrypt(&header, &req.service_data)
+                .ok_or(Code::SecureDataVerificationFailed)?;
+            message.truncate(1);
+            message.extend(data);
+        }
+        authenticated.anti_replay_cnt = Some(req.anti_replay_cnt);
+
+        Ok(message)
+    }
+
+    /// Sign and encrypt the internal response(include SID) as requested by the secured request.
+    #[cfg(feature = "std2020")]
+    pub(crate) async fn secured_data_wrap(
+        &self,
+        session: SessionType,
+        req: &request::SecuredDataTrans,
+        response: Vec<u8>,
+    ) -> Result<response::SecuredDataTrans, Code> {
+        let mut auth = self.auth.lock().await;
+        let key = Self::authenticated(&mut auth, session)
+            .and_then(|v| v.session_key.as_ref())
+            .ok_or(Code::SecureDataVerificationFailed)?;
+        if response.is_empty() {
+            return Err(Code::GeneralReject);
+        }
+
+        let mut apar = AdministrativeParameter::new();
+        apar.signed_set(req.apar.is_signature_on_response())
+            .encrypted_set(req.apar.is_encrypted())
+            .pre_established_set(req.apar.is_pre_established());
+        let signature = SignatureEncryptionCalculation::try_from(SECURED_ALGORITHM)
+            .map_err(|_| Code::GeneralReject)?;
+        let counter = req.anti_replay_cnt;
+        let header = secured_header(apar.into(), SECURED_ALGORITHM, counter);
+        let negative = response[0] == Service::NRC as u8;
+        let message = if apar.is_encrypted() && !negative {
+            let mut message = vec![response[0]];
+            message.extend(
+                key.encrypt(&header, &response[1..])
+                    .ok_or(Code::GeneralReject)?,
+            );
+            message
+        } else {
+            response
+        };
+        let signature_data = if apar.is_signed() {
+            key.sign(&header, &message)
+        } else {
+            vec![]
+        };
+
+        if negative {
+            if message.len() != 3 {
+                return Err(Code::GeneralReject);
+            }
+            response::SecuredDataTransNegative::new(
+                apar,
+                signature,
+                counter,
+                message[1],
+                message[2],
+                signature_data,
+            )
+            .map(response::SecuredDataTrans::Unsuccessful)
+        } else {
+            response::SecuredDataTransPositive::new(
+                apar,
+                signature,
+                counter,
+                message[0],
+                message[1..].to_vec(),
+                signature_data,
+            )
+            .map(response::SecuredDataTrans::Successful)
+        }
+        .map_err(|_| Code::GeneralReject)
+    }
+
+    /// The authenticated client of session, the authentication is removed in the default session
+    /// if it is completed in other session.
+    #[cfg(feature = "std2020")]
+    fn authenticated(auth: &mut AuthState, session: SessionType) -> Option<&mut Authenticated> {
+        if session == SessionType::Default
+            && auth
+                .authenticated
+                .as_ref()
+                .is_some_and(|v| v.session != session)
+        {
+            auth.authenticated = None;
+        }
+
+        auth.authenticated.as_mut()
+    }
+
+    /// Verify the client certificate and return its public key and role.
+    #[cfg(feature = "std2020")]
+    fn auth_verify_certificate(
+        pki: &AuthPki,
+        certificate: iso14229_1::NotNullableData,
+    ) -> Result<(SubjectPublicKeyInfoOwned, Vec<u8>), Code> {
+        let cert = pki::parse_certificate(&auth_data(certificate))?;
+        pki::verify_certificate(&cert, &pki.anchor)?;
+        let role = match &pki.role_oid {
+            Some(oid) => pki::certificate_role(&cert, oid)
+                .ok_or(Code::CertificateVerificationFailedInvalidScope)?,
+            None => vec![],
+        };
+
+        Ok((cert.tbs_certificate.subject_public_key_info, role))
+    }
+
+    /// Process the request data(exclude SID) of ResponseOnEvent and return the positive response data.
+    pub(crate) async fn response_on_event(
+        &self,
+        session: SessionType,
+        data: &[u8],
+        now: Instant,
+    ) -> Result<Vec<u8>, Code> {
+        use crate::constants::ROE_SUPPRESS_POSITIVE;
+
+        if data.len() < 2 {
+            return Err(Code::IncorrectMessageLengthOrInvalidFormat);
+        }
+        let raw_type = data[0] & !ROE_SUPPRESS_POSITIVE;
+        let event_type =
+            iso14229_1::EventType::try_from(raw_type).map_err(|_| Code::SubFunctionNotSupported)?;
+        let window_time = data[1];
+        let data = &data[2..];
+
+        let mut roe = self.roe.lock().await;
+        match event_type.event_type() {
+            ResponseOnEventType::StopResponseOnEvent
+            | ResponseOnEventType::StartResponseOnEvent
+            | ResponseOnEventType::ClearResponseOnEvent => {
+                if !data.is_empty() {
+                    return Err(Code::IncorrectMessageLengthOrInvalidFormat);
+                }
+
+                match event_type.event_type() {
+                    ResponseOnEventType::StopResponseOnEvent => {
+                        roe.started = None;
+                        roe.events.iter_mut().for_each(|event| event.active = false);
+                    }
+                    ResponseOnEventType::StartResponseOnEvent => {
+                        if roe.events.is_empty() {
+                            return Err(Code::ConditionsNotCorrect);
+                        }
+
+                        roe.started = Some(session);
+                        for i in 0..roe.events.len() {
+                            let last = self.roe_observe(&roe.events[i]).await;
+                            let event = &mut roe.events[i];
+                            event.active = true;
+                            event.identified = 0;
+                            event.deadline = roe_window(event.window_time).map(|v| now + v);
+                            event.last = last;
+                            event.armed = true;
+                            event.next = roe_timer_rate(&event.record).map(|v| now + v);
+                        }
+                    }
+                    _ => {
+                        roe.started = None;
+                        roe.events.clear();
+                    }
+                }
+
+                Ok(vec![raw_type, 0x00, window_time])
+            }
+            ResponseOnEventType::ReportActivatedEvents => {
+                if !data.is_empty() {
+                    return Err(Code::IncorrectMessageLengthOrInvalidFormat);
+                }
+
+                let events = roe
+                    .events
+                    .iter()
+                    .filter(|event| event.active)
+                    .collect::<Vec<_>>();
+                let mut result = vec![raw_type, events.len() as u8];
+                for event in events {
+                    result.push(iso14229_1::EventType::new(event.store, event.event_type).into());
+                    result.push(event.window_time);
+                    result.extend(&event.record);
+                    result.extend(&event.service_record);
+                }
+
+                Ok(result)
+            }
+            ResponseOnEventType::OnDTCStatusChange
+            | ResponseOnEventType::OnTimerInterrupt
+            | ResponseOnEventType::OnChangeOfDataIdentifier
+            | ResponseOnEventType::OnComparisonOfValues => {
+                let record_len = match event_type.event_type() {
+                    ResponseOnEventType::OnDTCStatusChange
+                    | ResponseOnEventType::OnTimerInterrupt => 1,
+                    ResponseOnEventType::OnChangeOfDataIdentifier => 2,
+                    _ => 10,
+                };
+                if data.len() <= record_len {
+                    return Err(Code::IncorrectMessageLengthOrInvalidFormat);
+                }
+
+                let (record, service_record) = data.split_at(record_len);
+                match Service::try_from(service_record[0]) {
+                    Ok(v) if RECOMMENDED_SERVICES.contains(&v) => {}
+                    _ => return Err(Code::RequestOutOfRange),
+                }
+                match event_type.event_type() {
+                    ResponseOnEventType::OnTimerInterrupt if roe_timer_rate(record).is_none() => {
+                        return Err(Code::RequestOutOfRange);
+                    }
+                    ResponseOnEventType::OnChangeOfDataIdentifier
+                    | ResponseOnEventType::OnComparisonOfValues => {
+                        let did = DataIdentifier::from(u16::from_be_bytes([record[0], record[1]]));
+                        if !self.config.cfg.did.contains_key(&did) {
+                            return Err(Code::RequestOutOfRange);
+                        }
+                        if event_type.event_type() == ResponseOnEventType::OnComparisonOfValues
+                            && request::ComparisonLogicID::try_from(record[2]).is_err()
+                        {
+                            return Err(Code::RequestOutOfRange);
+                        }
+                    }
+                    _ => {}
+                }
+
+                // the event with same type and record is replaced
+                roe.events.retain(|event| {
+                    event.event_type != event_type.event_type() || event.record != record
+                });
+                roe.events.push(RoeEvent {
+                    event_type: event_type.event_type(),
+                    store: event_type.store_event(),
+                    window_time,
+                    record: record.to_vec(),
+                    service_record: service_record.to_vec(),
+                    active: false,
+                    identified: 0,
+                    deadline: None,
+                    last: None,
+                    armed: true,
+                    next: None,
+                });
+
+                let mut result = vec![raw_type, 0x00, window_time];
+                result.extend(record);
+                result.extend(service_record);
+                Ok(result)
+            }
+            _ => Err(Code::SubFunctionNotSupported),
+        }
+    }
+
+    /// Check the active events of ResponseOnEvent.
+    ///
+    /// The events are stopped when the session is changed after ResponseOnEvent is started.
+    pub(crate) async fn poll_response_on_event(
+        &self,
+        session: SessionType,
+        now: Instant,
+    ) -> Vec<RoeAction> {
+        let mut roe = self.roe.lock().await;
+        let Some(started) = roe.started else {
+            return vec![];
+        };
+        if started != session {
+            roe.started = None;
+            roe.events.iter_mut().for_each(|event| event.active = false);
+            return vec![];
+        }
+
+        let mut result = Vec::new();
+        for i in 0..roe.events.len() {
+            if !roe.events[i].active {
+                continue;
+            }
+
+            let observed = self.roe_observe(&roe.events[i]).await;
+            let event = &mut roe.events[i];
+            let triggered = match event.event_type {
+                ResponseOnEventType::OnTimerInterrupt => match event.next {
+                    Some(next) if now >= next => {
+                        event.next = roe_timer_rate(&event.record).map(|v| next + v);
+                        true
+                    }
+                    _ => false,
+                },
+                ResponseOnEventType::OnComparisonOfValues => {
+                    match observed
+                        .as_deref()
+                        .and_then(|v| roe_compare(&event.record, v))
+                    {
+                        Some((true, _)) if event.armed => {
+                            event.armed = false;
+                            true
+                        }
+                        Some((false, true)) => {
+                            event.armed = true;
+                            false
+                        }
+                        _ => false,
+                    }
+                }
+                _ => {
+                    let changed = observed != event.last;
+                    event.last = observed;
+                    changed
+                }
+            };
+
+            if triggered {
+                event.identified = event.identified.saturating_add(1);
+                result.push(RoeAction::Respond(event.service_record.clone()));
+            }
+
+            if event.deadline.is_some_and(|v| now >= v) {
+                event.active = false;
+                let mut data = vec![
+                    iso14229_1::EventType::new(event.store, event.event_type).into(),
+                    event.identified,
+                    event.window_time,
+                ];
+                data.extend(&event.record);
+                data.extend(&event.service_record);
+                result.push(RoeAction::WindowClosed(data));
+            }
+        }
+
+        if roe.events.iter().all(|event| !event.active) {
+            roe.started = None;
+        }
+
+        result
+    }
+
+    /// the observed value of DTC status(masked) or positive response of DID
+    async fn roe_observe(&self, event: &RoeEvent) -> Option<Vec<u8>> {
+        match event.event_type {
+            ResponseOnEventType::OnDTCStatusChange => {
+                let mask = event.record[0];
+                let result = self
+                    .dtcs
+                    .lock()
+                    .await
+                    .iter()
+                    .filter(|record| record.status & mask != 0)
+                    .flat_map(|record| {
+                        let mut data: Vec<u8> = record.dtc.into();
+                        data.push(record.status & mask);
+                        data
+                    })
+                    .collect();
+                Some(result)
+            }
+            ResponseOnEventType::OnChangeOfDataIdentifier
+            | ResponseOnEventType::OnComparisonOfValues => {
+                let did = u16::from_be_bytes([event.record[0], event.record[1]]);
+                let value = self.get_static_did(&DataIdentifier::from(did)).await?;
+                let mut result = vec![Service::ReadDID.into()];
+                result.extend(did.to_be_bytes());
+                result.extend(value);
+                Some(result)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std2020")]
+#[inline(always)]
+fn auth_data_new(data: Vec<u8>) -> Result<iso14229_1::NotNullableData, Code> {
+    iso14229_1::NotNullableData::new(data).map_err(|_| Code::ChallengeCalculationFailed)
+}
+
+/// The duration of eventWindowTime, `None` means the window is not closed by time.
+///
+/// 0x02(infinite), 0x03(current cycle) and 0x04(current and following cycle) are kept until stopped or reset,
+/// other values are in seconds.
+fn roe_window(window_time: u8) -> Option<Duration> {
+    match window_time {
+        0x02..=0x04 => None,
+        v => Some(Duration::from_secs(v as u64)),
+    }
+}
+
+/// The rate of timerSchedule(slow, medium or fast) of OnTimerInterrupt.
+fn roe_timer_rate(record: &[u8]) -> Option<Duration> {
+    use crate::constants::{ROE_TIMER_FAST_MS, ROE_TIMER_MEDIUM_MS, ROE_TIMER_SLOW_MS};
+
+    match record.first() {
+        Some(0x01) => Some(Duration::from_millis(ROE_TIMER_SLOW_MS)),
+        Some(0x02) => Some(Duration::from_millis(ROE_TIMER_MEDIUM_MS)),
+        Some(0x03) => Some(Duration::from_millis(ROE_TIMER_FAST_MS)),
+        _ => None,
+    }
+}
+
+/// Compare the value localized in the positive response of DID with the comparison parameters of record.
+///
+/// Return whether the condition is fulfilled and whether the value left the condition by hysteresis.
+fn roe_compare(record: &[u8], response: &[u8]) -> Option<(bool, bool)> {
+    let logic = request::ComparisonLogicID::try_from(record[2]).ok()?;
+    let comparison = u32::from_be_bytes([record[3], record[4], record[5], record[6]]);
+    let hysteresis = record[7];
+    let localization = request::Localization::from(u16::from_be_bytes([record[8], record[9]]));
+
+    let bits = match localization.length_value() {
+        0 => 32,
+        v => v as u32,
+    };
+    let offset = localization.offset_value() as usize;
+    let len = bits.div_ceil(8) as usize;
+    let mut raw = [0u8; 4];
+    raw[..len].copy_from_slice(response.get(offset..offset + len)?);
+    let raw = u32::from_be_bytes(raw) >> (32 - bits);
+
+    let (value, comparison) = if localization.is_sign() {
+        let shift = 32 - bits;
+        (
+            (((raw << shift) as i32) >> shift) as i64,
+            comparison as i32 as i64,
+        )
+    } else {
+        (raw as i64, comparison as i64)
+    };
+    let margin = comparison.abs() * hysteresis as i64 / 100;
+
+    Some(match logic {
+        request::ComparisonLogicID::LessThan => (value < comparison, value > comparison + margin),
+        request::ComparisonLogicID::LargerThan => (value > comparison, value < comparison - margin),
+        request::ComparisonLogicID::Equal => {
+            (value == comparison, (value - comparison).abs() > margin)
+        }
+        request::ComparisonLogicID::NotEqual => (value != comparison, value == comparison),
+    })
+}
+
+/// The fault memory of configuration.
+fn preconfigured_dtcs(config: &Config) -> Result<Vec<DtcRecord>, DoCanError> {
+    config
+        .dtcs
+        .iter()
+        .map(|dtc| DtcRecord::from_config(dtc, &config.cfg))
+        .collect()
 }
 
 #[cfg(test)]
-mod tests {
-    use super::{CommunicationControlState, Context, DtcRecord, TransferDirection};
-    use crate::server::Config;
+pub(crate) mod tests {
+    use super::{
+        CommunicationControlState, Context, DtcRecord, RoeAction, TransferDirection, TransferTarget,
+    };
+    use crate::{
+        server::{
+            did::DidAccessType, Config, DidAccessConfig, DidAccessRule, DidSource,
+            DtcSnapshotConfig, FileStorage, Routine, RoutineAccessConfig, SecurityLevelConfig,
+            ServiceAccessConfig, SessionConfig, StorageConfig, SubFunctionAccessConfig,
+        },
+        DoCanError, ScalingRecord,
+    };
     use bytes::Bytes;
     use iso14229_1::{
         request::{self, ClearDiagnosticInfo, IOCtrl},
-        response,
+        response::{self, Code},
         utils::U24,
         AddressAndLengthFormatIdentifier, CheckProgrammingDependencies, CommunicationCtrlType,
         CommunicationType, Configuration, DTCSettingType, DataFormatIdentifier, DataIdentifier,
-        IOCtrlParameter, MemoryLocation, RoutineCtrlType, RoutineId,
+        DynamicallyMemAddr, EraseMemory, IOCtrlParameter, MemoryLocation, RoutineCtrlType,
+        RoutineId, Service, SessionType,
     };
     use iso15765_2::can::Address;
     use rsutil::types::ByteOrder;
-    use std::sync::Arc;
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
     use tokio::sync::Mutex;
 
     impl Context {
@@ -583,7 +2623,7 @@ mod tests {
         }
     }
 
-    fn test_context() -> Context {
+    pub(crate) fn test_context() -> Context {
         let did = DataIdentifier::from(0x4101);
         let mut cfg = Configuration::default();
         cfg.did.insert(did, 2);
@@ -591,26 +2631,55 @@ mod tests {
         Context {
             config: Config {
                 address: Address::default(),
+                testers: vec![],
+                tester_mode: Default::default(),
                 timing: Default::default(),
+                s3_ms: 5_000,
+                response_pending: Default::default(),
                 extend_sa_level: 3,
                 program_sa_level: 5,
                 seed_len: 4,
                 sa_salt: vec![1, 2, 3, 4],
+                security_levels: Default::default(),
                 cfg,
                 did_sa_level: Default::default(),
+                sessions: Default::default(),
+                service_access: Default::default(),
+                routines: Default::default(),
+                did_access: Default::default(),
+                did_values: Default::default(),
+                did_scaling: Default::default(),
+                file_transfer: None,
+                authentication: None,
+                dtc_snapshot: None,
+                dtc_monitor: Default::default(),
+                dtcs: vec![],
+                storage: None,
                 byte_order: ByteOrder::default(),
             },
             did_st: Default::default(),
             did_dyn: Default::default(),
+            did_sources: Default::default(),
             sa_algo: Default::default(),
             sa_ctx: Default::default(),
+            sa_level_algo: Default::default(),
+            sa_attempts: Default::default(),
             memories: Default::default(),
             dtcs: Default::default(),
             dtc_setting_enabled: Arc::new(Mutex::new(true)),
             active_timing: Arc::new(Mutex::new(Default::default())),
             comm_ctrl_state: Arc::new(Mutex::new(CommunicationControlState::default())),
-            routine_results: Default::default(),
+            routines: Default::default(),
+            routine_handlers: Default::default(),
             transfer_meta: Default::default(),
+            file_transfer: Default::default(),
+            roe: Default::default(),
+            storage: Default::default(),
+            nvm_dirty: Default::default(),
+            #[cfg(feature = "std2020")]
+            pki: None,
+            #[cfg(feature = "std2020")]
+            auth: Default::default(),
             // session: Default::default(),
         }
     }
@@ -624,7 +2693,7 @@ mod tests {
         .unwrap()
     }
 
-    fn sample_dtc(dtc: u32) -> DtcRecord {
+    pub(crate) fn sample_dtc(dtc: u32) -> DtcRecord {
         DtcRecord {
             dtc: U24::new(dtc),
             status: 0x08,
@@ -633,48 +2702,641 @@ mod tests {
             fault_counter: 3,
             permanent: true,
             ext_data: vec![(0x02, vec![0xAA, 0xBB])],
+            snapshots: vec![],
+            user_memory: None,
             mirror: false,
             emissions_obd: false,
             wwh_obd: None,
+            monitor: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn clear_diagnostic_info_clears_all_for_global_group() {
+        let ctx = test_context();
+        ctx.replace_dtcs(vec![sample_dtc(0x112233), sample_dtc(0x445566)])
+            .await;
+
+        #[cfg(any(feature = "std2020"))]
+        let req = ClearDiagnosticInfo::new(U24::new(0xFF_FF_FF), None);
+        #[cfg(any(feature = "std2006", feature = "std2013"))]
+        let req = ClearDiagnosticInfo::new(U24::new(0xFF_FF_FF));
+
+        ctx.clear_diagnostic_info(req).await.unwrap();
+        assert!(ctx.dtc_records().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn clear_diagnostic_info_rejects_non_global_group() {
+        let ctx = test_context();
+
+        #[cfg(any(feature = "std2020"))]
+        let req = ClearDiagnosticInfo::new(U24::new(0x112233), None);
+        #[cfg(any(feature = "std2006", feature = "std2013"))]
+        let req = ClearDiagnosticInfo::new(U24::new(0x112233));
+
+        let err = ctx.clear_diagnostic_info(req).await.unwrap_err();
+        assert_eq!(err, response::Code::RequestOutOfRange);
+    }
+
+    #[cfg(any(feature = "std2020"))]
+    #[tokio::test]
+    async fn clear_diagnostic_info_rejects_memory_selection() {
+        let ctx = test_context();
+        let req = ClearDiagnosticInfo::new(U24::new(0xFF_FF_FF), Some(0x01));
+
+        let err = ctx.clear_diagnostic_info(req).await.unwrap_err();
+        assert_eq!(err, response::Code::RequestOutOfRange);
+    }
+
+    #[tokio::test]
+    async fn read_scaling_did_encodes_configured_records() {
+        let mut ctx = test_context();
+        let did = DataIdentifier::from(0x4101);
+        ctx.config.did_scaling.insert(
+            did,
+            vec![
+                ScalingRecord::UnsignedNumeric { len: 2 },
+                ScalingRecord::Unit { id: 0x30 },
+            ],
+        );
+
+        let data = ctx.read_scaling_did(&did).unwrap();
+        assert_eq!(data, vec![0x41, 0x01, 0x02, 0xA1, 0x30]);
+
+        let err = ctx
+            .read_scaling_did(&DataIdentifier::from(0x4102))
+            .unwrap_err();
+        assert_eq!(err, response::Code::RequestOutOfRange);
+    }
+
+    #[tokio::test]
+    async fn report_test_result_honours_dtc_setting() {
+        let mut ctx = test_context();
+        ctx.config.dtc_snapshot = Some(DtcSnapshotConfig {
+            dids: vec![DataIdentifier::from(0x4101)],
+            max_records: 1,
+        });
+        ctx.config.dtcs = serde_yaml::from_str("- dtc: 0x112233\n").unwrap();
+        let dtc = U24::new(0x112233);
+
+        ctx.set_dtc_setting(DTCSettingType::Off).await.unwrap();
+        assert!(!ctx.report_test_result(dtc, false).await);
+        assert!(ctx.dtc_records().await.is_empty());
+
+        ctx.set_dtc_setting(DTCSettingType::On).await.unwrap();
+        // the DTC isn't configured
+        assert!(!ctx.report_test_result(U24::new(0x445566), false).await);
+        assert!(ctx.dtc_records().await.is_empty());
+        assert!(ctx.report_test_result(dtc, false).await);
+        let records = ctx.dtc_records().await;
+        assert_eq!(records[0].status, 0x2F);
+        assert_eq!(records[0].snapshots.len(), 1);
+
+        ctx.start_operation_cycle().await;
+        assert!(ctx.report_test_result(dtc, true).await);
+        ctx.end_operation_cycle().await;
+        assert_eq!(ctx.dtc_records().await[0].status, 0x28);
+
+        ctx.set_dtc_setting(DTCSettingType::Off).await.unwrap();
+        ctx.start_operation_cycle().await;
+        assert_eq!(ctx.dtc_records().await[0].status, 0x28);
+    }
+
+    #[tokio::test]
+    async fn reset_restores_preconfigured_dtcs() {
+        let mut ctx = test_context();
+        ctx.config.dtcs = serde_yaml::from_str(
+            "- dtc: 0x112233\n  status: 0x09\n  snapshots:\n    0x01:\n      0x4101: [0x01, 0x02]\n",
+        )
+        .unwrap();
+        ctx.reset().await;
+
+        let records = ctx.dtc_records().await;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].status, 0x09);
+        assert_eq!(
+            records[0].snapshots,
+            vec![(0x01, vec![(DataIdentifier::from(0x4101), vec![0x01, 0x02])])]
+        );
+
+        assert!(ctx.report_test_result(U24::new(0x112233), true).await);
+        assert_ne!(ctx.dtc_records().await, records);
+        ctx.reset().await;
+        assert_eq!(ctx.dtc_records().await, records);
+    }
+
+    #[tokio::test]
+    async fn non_volatile_memory_survives_restart() {
+        let path = std::env::temp_dir().join(format!("docan-context-{}.json", std::process::id()));
+        let _ = tokio::fs::remove_file(&path).await;
+        let did = DataIdentifier::from(0x4101);
+        let nvm_context = |flush_interval_ms| {
+            let mut ctx = test_context();
+            ctx.config.storage = Some(StorageConfig {
+                path: path.clone(),
+                dids: vec![did],
+                flush_interval_ms,
+            });
+            ctx.config.dtcs.push(sample_dtc(0x112233).to_config());
+            ctx
+        };
+
+        let mut ctx = nvm_context(0);
+        ctx.set_storage(Arc::new(FileStorage::new(&path)))
+            .await
+            .unwrap();
+        assert!(ctx.set_static_did(&did, [0x12, 0x34]).await);
+        assert!(ctx.report_test_result(U24::new(0x112233), false).await);
+        ctx.set_security_algo(|_, _, _| Ok(Some(vec![0x00]))).await;
+        ctx.security_seed(0x01, SessionType::Extended, 0, Instant::now())
+            .await
+            .unwrap();
+        assert_eq!(
+            ctx.security_key(0x02, &[0xFF], Instant::now()).await,
+            Err(response::Code::InvalidKey)
+        );
+
+        let mut restarted = nvm_context(1_000);
+        restarted
+            .set_storage(Arc::new(FileStorage::new(&path)))
+            .await
+            .unwrap();
+        assert_eq!(
+            restarted.get_static_did(&did).await,
+            Some(Bytes::from_static(&[0x12, 0x34]))
+        );
+        assert_eq!(restarted.dtc_records().await, ctx.dtc_records().await);
+        assert_eq!(restarted.sa_attempts.lock().await[&0x01].count, 1);
+
+        // the downloaded memory is persisted once the transfer is exited
+        let mem_loc = sample_mem_loc(2);
+        restarted
+            .request_download(DataFormatIdentifier::new(0x00, 0x00), mem_loc)
+            .await
+            .unwrap();
+        restarted.transfer_data(1, &[0x01]).await.unwrap();
+        restarted.transfer_data(2, &[0x02]).await.unwrap();
+        assert!(!*restarted.nvm_dirty.lock().await);
+        restarted.request_transfer_exit(&[]).await.unwrap();
+        assert!(*restarted.nvm_dirty.lock().await);
+
+        // the change is flushed later when the flush interval is configured
+        assert!(restarted.set_static_did(&did, [0x56, 0x78]).await);
+        assert!(*restarted.nvm_dirty.lock().await);
+        ctx.reset().await;
+        assert_eq!(
+            ctx.get_static_did(&did).await,
+            Some(Bytes::from_static(&[0x12, 0x34]))
+        );
+
+        restarted.reset().await;
+        assert!(!*restarted.nvm_dirty.lock().await);
+        ctx.set_storage(Arc::new(FileStorage::new(&path)))
+            .await
+            .unwrap();
+        assert_eq!(
+            ctx.get_static_did(&did).await,
+            Some(Bytes::from_static(&[0x56, 0x78]))
+        );
+        assert_eq!(ctx.dtc_records().await.len(), 1);
+        assert_eq!(
+            ctx.memories.lock().await.get(&mem_loc),
+            Some(&Bytes::from_static(&[0x01, 0x02]))
+        );
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+
+    #[tokio::test]
+    async fn did_source_overrides_static_value() {
+        let mut ctx = test_context();
+        ctx.config.byte_order = ByteOrder::Big;
+        let did = DataIdentifier::from(0x4101);
+
+        ctx.set_did_source(did, DidSource::Numeric { value: 0x1234 })
+            .await
+            .unwrap();
+        assert_eq!(
+            ctx.get_static_did(&did).await,
+            Some(Bytes::from_static(&[0x12, 0x34]))
+        );
+
+        ctx.set_did_source(
+            did,
+            DidSource::Counter {
+                min: 1,
+                max: 2,
+                step: 1,
+            },
+        )
+        .await
+        .unwrap();
+        // the value is observed without stepping the counter
+        for _ in 0..2 {
+            assert_eq!(
+                ctx.get_static_did(&did).await,
+                Some(Bytes::from_static(&[0x00, 0x01]))
+            );
         }
+        for value in [1, 2, 1] {
+            assert_eq!(
+                ctx.read_static_did(&did).await,
+                Some(Bytes::from(vec![0x00, value]))
+            );
+        }
+
+        // the written value replaces the live source
+        assert!(ctx.set_static_did(&did, [0x56, 0x78]).await);
+        assert_eq!(
+            ctx.get_static_did(&did).await,
+            Some(Bytes::from_static(&[0x56, 0x78]))
+        );
+
+        assert!(matches!(
+            ctx.set_did_source(
+                DataIdentifier::from(0xF190),
+                DidSource::Numeric { value: 0 }
+            )
+            .await,
+            Err(DoCanError::InvalidDidConfig { did: 0xF190, .. })
+        ));
+        assert!(ctx
+            .set_did_source(did, DidSource::Hex { value: "12".into() })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn security_access_counts_attempts_and_delays() {
+        let mut ctx = test_context();
+        ctx.config.security_levels.insert(
+            0x01,
+            SecurityLevelConfig {
+                seed: Some(vec![0x11, 0x22]),
+                sessions: vec![0x03],
+                max_attempts: 2,
+                delay_ms: 1_000,
+                ..Default::default()
+            },
+        );
+        ctx.set_security_algo(|_, seed, salt| {
+            Ok(Some(seed.iter().zip(salt).map(|(a, b)| a ^ b).collect()))
+        })
+        .await;
+        let (session, now) = (SessionType::Extended, Instant::now());
+
+        assert_eq!(
+            ctx.security_seed(0x03, session, 0, now).await,
+            Err(response::Code::SubFunctionNotSupported)
+        );
+        assert_eq!(
+            ctx.security_seed(0x01, SessionType::Default, 0, now).await,
+            Err(response::Code::SubFunctionNotSupportedInActiveSession)
+        );
+        assert_eq!(
+            ctx.security_key(0x02, &[0x10, 0x20], now).await,
+            Err(response::Code::RequestSequenceError)
+        );
+
+        assert_eq!(
+            ctx.security_seed(0x01, session, 0, now).await,
+            Ok(vec![0x11, 0x22])
+        );
+        assert_eq!(
+            ctx.security_key(0x02, &[0x00, 0x00], now).await,
+            Err(response::Code::InvalidKey)
+        );
+        ctx.security_seed(0x01, session, 0, now).await.unwrap();
+        assert_eq!(
+            ctx.security_key(0x02, &[0x00, 0x00], now).await,
+            Err(response::Code::ExceedNumberOfAttempts)
+        );
+        assert_eq!(
+            ctx.security_seed(0x01, session, 0, now).await,
+            Err(response::Code::RequiredTimeDelayNotExpired)
+        );
+
+        let now = now + Duration::from_millis(1_000);
+        ctx.security_seed(0x01, session, 0, now).await.unwrap();
+        assert_eq!(ctx.security_key(0x02, &[0x10, 0x20], now).await, Ok(0x01));
+        assert_eq!(ctx.sa_attempts.lock().await[&0x01].count, 0);
+        // the seed is zero when the level is already unlocked
+        assert_eq!(
+            ctx.security_seed(0x01, session, 0x01, now).await,
+            Ok(vec![0x00, 0x00])
+        );
+
+        // the delay is armed at power-up when the attempts were exceeded
+        ctx.sa_attempts.lock().await.get_mut(&0x01).unwrap().count = 2;
+        ctx.arm_power_up_delay(now).await;
+        assert_eq!(
+            ctx.security_seed(0x01, session, 0, now).await,
+            Err(response::Code::RequiredTimeDelayNotExpired)
+        );
+        ctx.sa_attempts.lock().await.get_mut(&0x01).unwrap().count = 0;
+        ctx.config
+            .security_levels
+            .get_mut(&0x01)
+            .unwrap()
+            .power_up_delay = true;
+        ctx.arm_power_up_delay(now).await;
+        assert_eq!(
+            ctx.security_seed(0x01, session, 0, now + Duration::from_millis(999))
+                .await,
+            Err(response::Code::RequiredTimeDelayNotExpired)
+        );
+    }
+
+    #[test]
+    fn session_transition_is_checked_by_rules() {
+        let mut ctx = test_context();
+        ctx.config.sessions = SessionConfig::defaults();
+        let oem = SessionType::VehicleManufacturerSpecific(0x40);
+        let (default, programming, extended) = (
+            SessionType::Default,
+            SessionType::Programming,
+            SessionType::Extended,
+        );
+
+        assert_eq!(ctx.check_session_transition(default, extended), Ok(false));
+        assert_eq!(
+            ctx.check_session_transition(extended, programming),
+            Ok(false)
+        );
+        assert_eq!(
+            ctx.check_session_transition(default, programming),
+            Err(response::Code::ConditionsNotCorrect)
+        );
+        assert_eq!(
+            ctx.check_session_transition(programming, extended),
+            Err(response::Code::ConditionsNotCorrect)
+        );
+        // the programming session is exited with reset
+        assert_eq!(ctx.check_session_transition(programming, default), Ok(true));
+        assert_eq!(
+            ctx.check_session_transition(programming, programming),
+            Ok(false)
+        );
+        assert_eq!(
+            ctx.check_session_transition(extended, oem),
+            Err(response::Code::SubFunctionNotSupported)
+        );
+
+        let timing = response::SessionTiming {
+            p2: 100,
+            p2_star: 1_000,
+        };
+        ctx.config.sessions.insert(
+            0x40,
+            SessionConfig {
+                transitions: vec![0x01, 0x40],
+                reset_on_exit: false,
+                timing: Some(timing),
+            },
+        );
+        ctx.config
+            .sessions
+            .get_mut(&0x03)
+            .unwrap()
+            .transitions
+            .push(0x40);
+        assert_eq!(ctx.check_session_transition(extended, oem), Ok(false));
+        assert_eq!(ctx.check_session_transition(oem, default), Ok(false));
+        assert_eq!(ctx.session_timing(oem), timing);
+        assert_eq!(ctx.session_timing(extended), ctx.config.timing);
+    }
+
+    #[test]
+    fn response_pending_is_scheduled_by_timing() {
+        let mut ctx = test_context();
+        let timing = response::SessionTiming {
+            p2: 50,
+            p2_star: 500,
+        };
+        assert_eq!(
+            ctx.pending_interval(timing, true),
+            Duration::from_millis(40)
+        );
+        assert_eq!(
+            ctx.pending_interval(timing, false),
+            Duration::from_millis(2_500)
+        );
+        assert_eq!(ctx.service_delay(0x31), None);
+
+        ctx.config.response_pending.p2_percent = 0;
+        ctx.config.response_pending.delays.insert(0x31, 3_000);
+        ctx.config.response_pending.delays.insert(0x34, 0);
+        assert_eq!(ctx.pending_interval(timing, true), Duration::from_millis(1));
+        assert_eq!(ctx.service_delay(0x31), Some(Duration::from_millis(3_000)));
+        assert_eq!(ctx.service_delay(0x34), None);
+    }
+
+    #[tokio::test]
+    async fn tester_context_has_own_security_and_transfer_state() {
+        let ctx = test_context();
+        let tester = ctx.for_tester();
+        tester
+            .sa_ctx
+            .lock()
+            .await
+            .replace((0x01, Bytes::from_static(&[0x11, 0x22])));
+        *tester.dtc_setting_enabled.lock().await = false;
+
+        assert!(ctx.sa_ctx.lock().await.is_none());
+        assert!(!Arc::ptr_eq(&ctx.transfer_meta, &tester.transfer_meta));
+        // the data of ECU is shared
+        assert!(!*ctx.dtc_setting_enabled.lock().await);
+        assert!(Arc::ptr_eq(&ctx.did_st, &tester.did_st));
+    }
+
+    #[test]
+    fn service_access_is_checked_by_matrix() {
+        let mut ctx = test_context();
+        ctx.config.service_access = ServiceAccessConfig::defaults(3);
+        let (default, extended) = (SessionType::Default, SessionType::Extended);
+
+        // the service without rule
+        assert_eq!(
+            ctx.check_service_access(Service::ReadDID, default, 0),
+            Ok(())
+        );
+        // the routines and secured data are allowed in the default session
+        assert_eq!(
+            ctx.check_service_access(Service::RoutineCtrl, default, 0),
+            Ok(())
+        );
+        assert_eq!(
+            ctx.check_service_access(Service::SecuredDataTrans, default, 0),
+            Ok(())
+        );
+        assert_eq!(
+            ctx.check_service_access(Service::RequestDownload, default, 0),
+            Err(response::Code::ServiceNotSupportedInActiveSession)
+        );
+        assert_eq!(
+            ctx.check_service_access(Service::RequestDownload, SessionType::Programming, 0),
+            Ok(())
+        );
+        assert_eq!(
+            ctx.check_service_access(Service::WriteMemByAddr, extended, 1),
+            Err(response::Code::SecurityAccessDenied)
+        );
+        assert_eq!(
+            ctx.check_service_access(Service::WriteMemByAddr, extended, 3),
+            Ok(())
+        );
+
+        let mut rule = ServiceAccessConfig::default();
+        rule.sub_functions.insert(
+            0x01,
+            SubFunctionAccessConfig {
+                sessions: vec![0x02],
+                levels: vec![5],
+            },
+        );
+        ctx.config
+            .service_access
+            .insert(Service::ECUReset.into(), rule);
+        assert_eq!(
+            ctx.check_service_access(Service::ECUReset, default, 0),
+            Ok(())
+        );
+        assert_eq!(
+            ctx.check_sub_function_access(Service::ECUReset, 0x03, default, 0),
+            Ok(())
+        );
+        assert_eq!(
+            ctx.check_sub_function_access(Service::ECUReset, 0x01, default, 5),
+            Err(response::Code::SubFunctionNotSupportedInActiveSession)
+        );
+        assert_eq!(
+            ctx.check_sub_function_access(Service::ECUReset, 0x01, SessionType::Programming, 0),
+            Err(response::Code::SecurityAccessDenied)
+        );
+        assert_eq!(
+            ctx.check_sub_function_access(Service::ECUReset, 0x01, SessionType::Programming, 5),
+            Ok(())
+        );
     }
 
     #[tokio::test]
-    async fn clear_diagnostic_info_clears_all_for_global_group() {
-        let ctx = test_context();
-        ctx.replace_dtcs(vec![sample_dtc(0x112233), sample_dtc(0x445566)])
-            .await;
+    async fn did_access_is_checked_by_policy() {
+        let mut ctx = test_context();
+        let did = DataIdentifier::from(0x4101);
+        let (default, extended) = (SessionType::Default, SessionType::Extended);
 
-        #[cfg(any(feature = "std2020"))]
-        let req = ClearDiagnosticInfo::new(U24::new(0xFF_FF_FF), None);
-        #[cfg(any(feature = "std2006", feature = "std2013"))]
-        let req = ClearDiagnosticInfo::new(U24::new(0xFF_FF_FF));
+        // the DID without policy
+        assert_eq!(
+            ctx.check_did_access(&did, DidAccessType::Read, default, 0)
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            ctx.check_did_access(&did, DidAccessType::Write, default, 3)
+                .await,
+            Err(response::Code::ServiceNotSupportedInActiveSession)
+        );
+        assert_eq!(
+            ctx.check_did_access(&did, DidAccessType::Write, extended, 1)
+                .await,
+            Err(response::Code::SecurityAccessDenied)
+        );
+        assert_eq!(
+            ctx.check_did_access(&did, DidAccessType::IoControl, extended, 0)
+                .await,
+            Ok(())
+        );
 
-        ctx.clear_diagnostic_info(req).await.unwrap();
-        assert!(ctx.dtc_records().await.is_empty());
+        ctx.config.did_access.insert(
+            did,
+            DidAccessConfig {
+                read: Some(Default::default()),
+                write: Some(DidAccessRule {
+                    sessions: vec![0x01, 0x03],
+                    levels: vec![1, 3],
+                    roles: vec![],
+                }),
+            },
+        );
+        assert_eq!(
+            ctx.check_did_access(&did, DidAccessType::Read, extended, 0)
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            ctx.check_did_access(&did, DidAccessType::Write, default, 1)
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            ctx.check_did_access(&did, DidAccessType::IoControl, extended, 2)
+                .await,
+            Err(response::Code::SecurityAccessDenied)
+        );
+        assert_eq!(
+            ctx.check_did_access(&did, DidAccessType::Write, SessionType::Programming, 3)
+                .await,
+            Err(response::Code::RequestOutOfRange)
+        );
+
+        ctx.config.did_access.get_mut(&did).unwrap().read = None;
+        assert_eq!(
+            ctx.check_did_access(&did, DidAccessType::Read, default, 0)
+                .await,
+            Err(response::Code::RequestOutOfRange)
+        );
     }
 
     #[tokio::test]
-    async fn clear_diagnostic_info_rejects_non_global_group() {
-        let ctx = test_context();
-
-        #[cfg(any(feature = "std2020"))]
-        let req = ClearDiagnosticInfo::new(U24::new(0x112233), None);
-        #[cfg(any(feature = "std2006", feature = "std2013"))]
-        let req = ClearDiagnosticInfo::new(U24::new(0x112233));
+    async fn dynamic_did_is_defined_by_source_dids() {
+        let mut ctx = test_context();
+        let source = DataIdentifier::from(0x4101);
+        let dynamic = DataIdentifier::from(0xF300);
+        ctx.config.cfg.did.insert(dynamic, 3);
+        ctx.config.did_sa_level.insert(source, 1);
+        assert!(ctx.set_static_did(&source, [0x12, 0x34]).await);
+        let session = SessionType::Default;
+        let part = |position, mem_size| DynamicallyMemAddr {
+            did: 0x4101,
+            position,
+            mem_size,
+        };
 
-        let err = ctx.clear_diagnostic_info(req).await.unwrap_err();
-        assert_eq!(err, response::Code::RequestOutOfRange);
-    }
+        assert_eq!(ctx.read_dynamic_did(&dynamic, session, 1).await, None);
+        assert_eq!(
+            ctx.define_dynamic_did(dynamic, &[part(1, 2)], session, 0)
+                .await,
+            Err(response::Code::SecurityAccessDenied)
+        );
+        assert_eq!(
+            ctx.define_dynamic_did(dynamic, &[part(2, 2)], session, 1)
+                .await,
+            Err(response::Code::RequestOutOfRange)
+        );
+        ctx.define_dynamic_did(dynamic, &[part(2, 1)], session, 1)
+            .await
+            .unwrap();
+        ctx.define_dynamic_did(dynamic, &[part(1, 1)], session, 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            ctx.define_dynamic_did(dynamic, &[part(1, 2)], session, 1)
+                .await,
+            Err(response::Code::RequestOutOfRange)
+        );
 
-    #[cfg(any(feature = "std2020"))]
-    #[tokio::test]
-    async fn clear_diagnostic_info_rejects_memory_selection() {
-        let ctx = test_context();
-        let req = ClearDiagnosticInfo::new(U24::new(0xFF_FF_FF), Some(0x01));
+        assert_eq!(
+            ctx.read_dynamic_did(&dynamic, session, 1).await,
+            Some(Ok(Bytes::from_static(&[0x34, 0x12, 0x00])))
+        );
+        // the access of source DID is checked when reading
+        assert_eq!(
+            ctx.read_dynamic_did(&dynamic, session, 0).await,
+            Some(Err(response::Code::SecurityAccessDenied))
+        );
 
-        let err = ctx.clear_diagnostic_info(req).await.unwrap_err();
-        assert_eq!(err, response::Code::RequestOutOfRange);
+        ctx.clear_dynamic_did(None).await.unwrap();
+        assert_eq!(ctx.read_dynamic_did(&dynamic, session, 1).await, None);
     }
 
     #[tokio::test]
@@ -846,6 +3508,8 @@ mod tests {
                 RoutineCtrlType::StartRoutine,
                 CheckProgrammingDependencies,
                 &[],
+                SessionType::Programming,
+                0,
             )
             .await
             .unwrap();
@@ -858,6 +3522,8 @@ mod tests {
                 RoutineCtrlType::RequestRoutineResults,
                 CheckProgrammingDependencies,
                 &[],
+                SessionType::Programming,
+                0,
             )
             .await
             .unwrap();
@@ -875,6 +3541,8 @@ mod tests {
                 RoutineCtrlType::RequestRoutineResults,
                 CheckProgrammingDependencies,
                 &[],
+                SessionType::Programming,
+                0,
             )
             .await
             .unwrap_err();
@@ -890,13 +3558,21 @@ mod tests {
                 RoutineCtrlType::StopRoutine,
                 CheckProgrammingDependencies,
                 &[],
+                SessionType::Programming,
+                0,
             )
             .await
             .unwrap_err();
         assert_eq!(err, response::Code::SubFunctionNotSupported);
 
         let err = ctx
-            .routine_ctrl(RoutineCtrlType::StartRoutine, RoutineId(0xFF02), &[])
+            .routine_ctrl(
+                RoutineCtrlType::StartRoutine,
+                RoutineId(0xFF02),
+                &[],
+                SessionType::Programming,
+                0,
+            )
             .await
             .unwrap_err();
         assert_eq!(err, response::Code::RequestOutOfRange);
@@ -909,6 +3585,8 @@ mod tests {
             RoutineCtrlType::StartRoutine,
             CheckProgrammingDependencies,
             &[],
+            SessionType::Programming,
+            0,
         )
         .await
         .unwrap();
@@ -920,12 +3598,165 @@ mod tests {
                 RoutineCtrlType::RequestRoutineResults,
                 CheckProgrammingDependencies,
                 &[],
+                SessionType::Programming,
+                0,
             )
             .await
             .unwrap_err();
         assert_eq!(err, response::Code::RequestSequenceError);
     }
 
+    struct Calibration;
+
+    #[async_trait::async_trait]
+    impl Routine for Calibration {
+        fn check_option(&self, r#type: RoutineCtrlType, option_record: &[u8]) -> Result<(), Code> {
+            match r#type {
+                RoutineCtrlType::StartRoutine if option_record.len() != 1 => {
+                    Err(Code::IncorrectMessageLengthOrInvalidFormat)
+                }
+                _ => Ok(()),
+            }
+        }
+
+        fn stoppable(&self) -> bool {
+            true
+        }
+
+        async fn start(&self, option_record: &[u8]) -> Result<Vec<u8>, Code> {
+            Ok(vec![0x01, option_record[0]])
+        }
+
+        async fn stop(&self, _: &[u8]) -> Result<Vec<u8>, Code> {
+            Ok(vec![0x02])
+        }
+    }
+
+    #[tokio::test]
+    async fn routine_ctrl_tracks_running_state_of_user_routine() {
+        let ctx = test_context();
+        let routine_id = RoutineId(0x0203);
+        ctx.set_routine(routine_id, Arc::new(Calibration)).await;
+        let routine = |r#type, option_record: &'static [u8]| {
+            let ctx = ctx.clone();
+            async move {
+                ctx.routine_ctrl(r#type, routine_id, option_record, SessionType::Extended, 0)
+                    .await
+                    .map(|v| v.routine_status)
+            }
+        };
+
+        assert_eq!(
+            routine(RoutineCtrlType::StopRoutine, &[]).await,
+            Err(Code::RequestSequenceError)
+        );
+        assert_eq!(
+            routine(RoutineCtrlType::RequestRoutineResults, &[]).await,
+            Err(Code::RequestSequenceError)
+        );
+        assert_eq!(
+            routine(RoutineCtrlType::StartRoutine, &[]).await,
+            Err(Code::IncorrectMessageLengthOrInvalidFormat)
+        );
+        assert_eq!(
+            routine(RoutineCtrlType::StartRoutine, &[0x10]).await,
+            Ok(vec![0x01, 0x10])
+        );
+        assert_eq!(
+            routine(RoutineCtrlType::StartRoutine, &[0x10]).await,
+            Err(Code::RequestSequenceError)
+        );
+        assert_eq!(
+            routine(RoutineCtrlType::RequestRoutineResults, &[]).await,
+            Ok(vec![0x01, 0x10])
+        );
+        assert_eq!(
+            routine(RoutineCtrlType::StopRoutine, &[]).await,
+            Ok(vec![0x02])
+        );
+        assert_eq!(
+            routine(RoutineCtrlType::StopRoutine, &[]).await,
+            Err(Code::RequestSequenceError)
+        );
+        assert_eq!(
+            routine(RoutineCtrlType::RequestRoutineResults, &[]).await,
+            Ok(vec![0x02])
+        );
+    }
+
+    #[tokio::test]
+    async fn routine_ctrl_checks_access_of_routine() {
+        let mut ctx = test_context();
+        ctx.config.routines = RoutineAccessConfig::defaults(0x11);
+        let option_record = [0x44, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02];
+
+        let err = ctx
+            .routine_ctrl(
+                RoutineCtrlType::StartRoutine,
+                EraseMemory,
+                &option_record,
+                SessionType::Extended,
+                0x11,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, Code::RequestOutOfRange);
+        let err = ctx
+            .routine_ctrl(
+                RoutineCtrlType::StartRoutine,
+                EraseMemory,
+                &option_record,
+                SessionType::Programming,
+                0,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, Code::SecurityAccessDenied);
+        let err = ctx
+            .routine_ctrl(
+                RoutineCtrlType::StartRoutine,
+                EraseMemory,
+                &option_record[..8],
+                SessionType::Programming,
+                0x11,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, Code::IncorrectMessageLengthOrInvalidFormat);
+    }
+
+    #[tokio::test]
+    async fn erase_memory_routine_erases_overlapped_memories() {
+        let ctx = test_context();
+        let alfi = AddressAndLengthFormatIdentifier::new(0x04, 0x04).unwrap();
+        let memory = |address| MemoryLocation::new(alfi, address, 0x10).unwrap();
+        for address in [0x1000, 0x1010, 0x1020] {
+            ctx.memories
+                .lock()
+                .await
+                .insert(memory(address), Bytes::from_static(&[0xAA]));
+        }
+
+        // erase 0x1018..0x1020
+        let option_record = [0x44, 0x00, 0x00, 0x10, 0x18, 0x00, 0x00, 0x00, 0x08];
+        let resp = ctx
+            .routine_ctrl(
+                RoutineCtrlType::StartRoutine,
+                EraseMemory,
+                &option_record,
+                SessionType::Programming,
+                0,
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.routine_status, vec![0x00]);
+
+        let memories = ctx.memories.lock().await;
+        assert!(memories.contains_key(&memory(0x1000)));
+        assert!(!memories.contains_key(&memory(0x1010)));
+        assert!(memories.contains_key(&memory(0x1020)));
+    }
+
     #[tokio::test]
     async fn transfer_meta_records_download_and_upload() {
         let ctx = test_context();
@@ -937,7 +3768,7 @@ mod tests {
         let meta = ctx.transfer_meta.lock().await.unwrap();
         assert_eq!(meta.direction, TransferDirection::Download);
         assert_eq!(meta.dfi, dfi);
-        assert_eq!(meta.mem_loc, mem_loc);
+        assert_eq!(meta.target, TransferTarget::Memory(mem_loc));
         assert_eq!(meta.next_sequence, 1);
         assert_eq!(meta.transferred, 0);
         let _ = meta;
@@ -1004,66 +3835,297 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn transfer_data_upload_reads_memory_and_advances_state() {
-        let ctx = test_context();
-        let mem_loc = sample_mem_loc(4);
-        ctx.memories
-            .lock()
-            .await
-            .insert(mem_loc, Bytes::from_static(&[0xAA, 0xBB, 0xCC, 0xDD]));
-        ctx.request_upload(DataFormatIdentifier::new(0x00, 0x00), mem_loc)
-            .await
-            .unwrap();
+    async fn transfer_data_upload_reads_memory_and_advances_state() {
+        let ctx = test_context();
+        let mem_loc = sample_mem_loc(4);
+        ctx.memories
+            .lock()
+            .await
+            .insert(mem_loc, Bytes::from_static(&[0xAA, 0xBB, 0xCC, 0xDD]));
+        ctx.request_upload(DataFormatIdentifier::new(0x00, 0x00), mem_loc)
+            .await
+            .unwrap();
+
+        let resp = ctx.transfer_data(1, &[]).await.unwrap();
+        assert_eq!(resp.sequence, 1);
+        assert_eq!(resp.data, vec![0xAA, 0xBB, 0xCC, 0xDD]);
+
+        let meta = ctx.transfer_meta.lock().await.unwrap();
+        assert_eq!(meta.transferred, 4);
+        assert_eq!(meta.next_sequence, 2);
+    }
+
+    #[tokio::test]
+    async fn transfer_data_rejects_missing_transfer_wrong_sequence_and_invalid_direction_payload() {
+        let ctx = test_context();
+        let upload_mem = sample_mem_loc(2);
+
+        let err = ctx.transfer_data(1, &[0x11]).await.unwrap_err();
+        assert_eq!(err, response::Code::RequestSequenceError);
+
+        ctx.request_download(DataFormatIdentifier::new(0x00, 0x00), sample_mem_loc(2))
+            .await
+            .unwrap();
+        let err = ctx.transfer_data(2, &[0x11]).await.unwrap_err();
+        assert_eq!(err, response::Code::WrongBlockSequenceCounter);
+
+        ctx.memories
+            .lock()
+            .await
+            .insert(upload_mem, Bytes::from_static(&[0x10, 0x20]));
+        ctx.request_upload(DataFormatIdentifier::new(0x00, 0x00), upload_mem)
+            .await
+            .unwrap();
+        let err = ctx.transfer_data(1, &[0x11]).await.unwrap_err();
+        assert_eq!(err, response::Code::RequestSequenceError);
+    }
+
+    #[tokio::test]
+    async fn request_transfer_exit_requires_completed_transfer_and_clears_state() {
+        let ctx = test_context();
+        let mem_loc = sample_mem_loc(2);
+        ctx.request_download(DataFormatIdentifier::new(0x00, 0x00), mem_loc)
+            .await
+            .unwrap();
+
+        let err = ctx.request_transfer_exit(&[0x99]).await.unwrap_err();
+        assert_eq!(err, response::Code::RequestSequenceError);
+
+        ctx.transfer_data(1, &[0x01, 0x02]).await.unwrap();
+        let resp = ctx.request_transfer_exit(&[0x99]).await.unwrap();
+        assert_eq!(resp.data, vec![0x99]);
+        assert!(ctx.transfer_meta.lock().await.is_none());
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    pub(crate) fn file_transfer_context(name: &str) -> (Context, std::path::PathBuf) {
+        use crate::server::FileTransferConfig;
+
+        let root = std::env::temp_dir().join(format!("docan-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+
+        let mut ctx = test_context();
+        ctx.config.file_transfer = Some(FileTransferConfig {
+            root: root.clone(),
+            max_block_len: 4,
+        });
+        (ctx, root)
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[tokio::test]
+    async fn request_file_transfer_add_file_writes_through_transfer_data() {
+        let (ctx, root) = file_transfer_context("add");
+        let req = request::RequestFileTransfer::AddFile {
+            filepath: "/fw/app.bin".into(),
+            dfi: DataFormatIdentifier::default(),
+            filesize_len: 1,
+            uncompressed_size: 4,
+            compressed_size: 4,
+        };
+
+        let resp = ctx.request_file_transfer(req.clone()).await.unwrap();
+        assert_eq!(resp, vec![0x20, 0x00, 0x04, 0x00]);
+        ctx.transfer_data(1, &[0x01, 0x02]).await.unwrap();
+        ctx.transfer_data(2, &[0x03, 0x04]).await.unwrap();
+        ctx.request_transfer_exit(&[]).await.unwrap();
+        assert_eq!(
+            std::fs::read(root.join("fw/app.bin")).unwrap(),
+            vec![0x01, 0x02, 0x03, 0x04]
+        );
+        assert!(ctx.file_transfer.lock().await.is_none());
+
+        let err = ctx.request_file_transfer(req).await.unwrap_err();
+        assert_eq!(err, response::Code::RequestOutOfRange);
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[tokio::test]
+    async fn request_file_transfer_commits_file_only_on_transfer_exit() {
+        let (ctx, root) = file_transfer_context("commit");
+        std::fs::write(root.join("app.bin"), [0xAA]).unwrap();
+        let req = request::RequestFileTransfer::ReplaceFile {
+            filepath: "app.bin".into(),
+            dfi: DataFormatIdentifier::default(),
+            filesize_len: 1,
+            uncompressed_size: 4,
+            compressed_size: 4,
+        };
+
+        ctx.request_file_transfer(req.clone()).await.unwrap();
+        // the block is longer than the maxNumberOfBlockLength of response
+        let err = ctx.transfer_data(1, &[0x01, 0x02, 0x03]).await.unwrap_err();
+        assert_eq!(err, response::Code::IncorrectMessageLengthOrInvalidFormat);
+        ctx.transfer_data(1, &[0x01, 0x02]).await.unwrap();
+        assert_eq!(std::fs::read(root.join("app.bin")).unwrap(), vec![0xAA]);
+
+        ctx.reset().await;
+        assert_eq!(std::fs::read(root.join("app.bin")).unwrap(), vec![0xAA]);
+        assert!(!root.join(".app.bin.part").exists());
+
+        ctx.request_file_transfer(req).await.unwrap();
+        ctx.transfer_data(1, &[0x01, 0x02]).await.unwrap();
+        ctx.transfer_data(2, &[0x03, 0x04]).await.unwrap();
+        ctx.request_transfer_exit(&[]).await.unwrap();
+        assert_eq!(
+            std::fs::read(root.join("app.bin")).unwrap(),
+            vec![0x01, 0x02, 0x03, 0x04]
+        );
+        assert!(!root.join(".app.bin.part").exists());
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[tokio::test]
+    async fn request_transfer_exit_rejects_file_with_mismatched_crc() {
+        let (ctx, root) = file_transfer_context("crc");
+        let req = request::RequestFileTransfer::AddFile {
+            filepath: "app.bin".into(),
+            dfi: DataFormatIdentifier::default(),
+            filesize_len: 1,
+            uncompressed_size: 2,
+            compressed_size: 2,
+        };
+
+        ctx.request_file_transfer(req.clone()).await.unwrap();
+        ctx.transfer_data(1, &[0x01, 0x02]).await.unwrap();
+        let crc = crate::crc::crc32(&[0x01, 0x02]);
+        let err = ctx
+            .request_transfer_exit(&(crc ^ 1).to_be_bytes())
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::GeneralProgrammingFailure);
+        assert!(!root.join("app.bin").exists());
+        assert!(!root.join(".app.bin.part").exists());
+
+        ctx.request_file_transfer(req).await.unwrap();
+        ctx.transfer_data(1, &[0x01, 0x02]).await.unwrap();
+        ctx.request_transfer_exit(&crc.to_be_bytes()).await.unwrap();
+        assert_eq!(
+            std::fs::read(root.join("app.bin")).unwrap(),
+            vec![0x01, 0x02]
+        );
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[tokio::test]
+    async fn request_file_transfer_rejects_path_out_of_root() {
+        let (ctx, _) = file_transfer_context("sandbox");
 
-        let resp = ctx.transfer_data(1, &[]).await.unwrap();
-        assert_eq!(resp.sequence, 1);
-        assert_eq!(resp.data, vec![0xAA, 0xBB, 0xCC, 0xDD]);
+        let err = ctx
+            .request_file_transfer(request::RequestFileTransfer::DeleteFile {
+                filepath: "../outside.bin".into(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::RequestOutOfRange);
 
-        let meta = ctx.transfer_meta.lock().await.unwrap();
-        assert_eq!(meta.transferred, 4);
-        assert_eq!(meta.next_sequence, 2);
+        let err = test_context()
+            .request_file_transfer(request::RequestFileTransfer::ReadDir {
+                filepath: "/".into(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::ServiceNotSupported);
     }
 
+    #[cfg(all(unix, any(feature = "std2013", feature = "std2020")))]
     #[tokio::test]
-    async fn transfer_data_rejects_missing_transfer_wrong_sequence_and_invalid_direction_payload() {
-        let ctx = test_context();
-        let upload_mem = sample_mem_loc(2);
+    async fn request_file_transfer_rejects_dangling_symlink() {
+        let (ctx, root) = file_transfer_context("symlink");
+        let outside = root.with_extension("outside.bin");
+        let _ = std::fs::remove_file(&outside);
+        std::os::unix::fs::symlink(&outside, root.join("link.bin")).unwrap();
 
-        let err = ctx.transfer_data(1, &[0x11]).await.unwrap_err();
-        assert_eq!(err, response::Code::RequestSequenceError);
-
-        ctx.request_download(DataFormatIdentifier::new(0x00, 0x00), sample_mem_loc(2))
+        let err = ctx
+            .request_file_transfer(request::RequestFileTransfer::AddFile {
+                filepath: "/link.bin".into(),
+                dfi: DataFormatIdentifier::default(),
+                filesize_len: 1,
+                uncompressed_size: 4,
+                compressed_size: 4,
+            })
             .await
-            .unwrap();
-        let err = ctx.transfer_data(2, &[0x11]).await.unwrap_err();
-        assert_eq!(err, response::Code::WrongBlockSequenceCounter);
+            .unwrap_err();
+        assert_eq!(err, response::Code::RequestOutOfRange);
+        assert!(!outside.exists());
+    }
 
-        ctx.memories
-            .lock()
-            .await
-            .insert(upload_mem, Bytes::from_static(&[0x10, 0x20]));
-        ctx.request_upload(DataFormatIdentifier::new(0x00, 0x00), upload_mem)
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[tokio::test]
+    async fn request_file_transfer_read_file_and_dir_upload_in_blocks() {
+        let (ctx, root) = file_transfer_context("read");
+        std::fs::create_dir_all(root.join("logs")).unwrap();
+        std::fs::write(root.join("a.txt"), [0x10, 0x20, 0x30]).unwrap();
+
+        let resp = ctx
+            .request_file_transfer(request::RequestFileTransfer::ReadFile {
+                filepath: "a.txt".into(),
+                dfi: DataFormatIdentifier::default(),
+            })
             .await
             .unwrap();
-        let err = ctx.transfer_data(1, &[0x11]).await.unwrap_err();
-        assert_eq!(err, response::Code::RequestSequenceError);
+        assert_eq!(&resp[..6], &[0x20, 0x00, 0x04, 0x00, 0x00, 0x08]);
+        assert_eq!(
+            ctx.transfer_data(1, &[]).await.unwrap().data,
+            vec![0x10, 0x20]
+        );
+        assert_eq!(ctx.transfer_data(2, &[]).await.unwrap().data, vec![0x30]);
+        ctx.request_transfer_exit(&[]).await.unwrap();
+
+        ctx.request_file_transfer(request::RequestFileTransfer::ReadDir {
+            filepath: "/".into(),
+        })
+        .await
+        .unwrap();
+        let mut listing = Vec::new();
+        for sequence in 1..=6 {
+            listing.extend(ctx.transfer_data(sequence, &[]).await.unwrap().data);
+        }
+        assert_eq!(listing, b"a.txt\nlogs/");
+        ctx.request_transfer_exit(&[]).await.unwrap();
+
+        ctx.request_file_transfer(request::RequestFileTransfer::DeleteFile {
+            filepath: "a.txt".into(),
+        })
+        .await
+        .unwrap();
+        assert!(!root.join("a.txt").exists());
     }
 
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
     #[tokio::test]
-    async fn request_transfer_exit_requires_completed_transfer_and_clears_state() {
-        let ctx = test_context();
-        let mem_loc = sample_mem_loc(2);
-        ctx.request_download(DataFormatIdentifier::new(0x00, 0x00), mem_loc)
-            .await
-            .unwrap();
+    async fn request_file_transfer_resume_file_continues_from_file_position() {
+        let (ctx, root) = file_transfer_context("resume");
+        std::fs::write(root.join("app.bin"), [0x01, 0x02]).unwrap();
+        let req = request::RequestFileTransfer::ResumeFile {
+            filepath: "app.bin".into(),
+            dfi: DataFormatIdentifier::default(),
+            filesize_len: 1,
+            uncompressed_size: 4,
+            compressed_size: 4,
+        };
 
-        let err = ctx.request_transfer_exit(&[0x99]).await.unwrap_err();
-        assert_eq!(err, response::Code::RequestSequenceError);
+        let resp = ctx.request_file_transfer(req).await.unwrap();
+        assert_eq!(&resp[4..], &2u64.to_be_bytes());
+        ctx.transfer_data(1, &[0x03, 0x04]).await.unwrap();
+        ctx.request_transfer_exit(&[]).await.unwrap();
+        assert_eq!(
+            std::fs::read(root.join("app.bin")).unwrap(),
+            vec![0x01, 0x02, 0x03, 0x04]
+        );
 
-        ctx.transfer_data(1, &[0x01, 0x02]).await.unwrap();
-        let resp = ctx.request_transfer_exit(&[0x99]).await.unwrap();
-        assert_eq!(resp.data, vec![0x99]);
-        assert!(ctx.transfer_meta.lock().await.is_none());
+        let err = ctx
+            .request_file_transfer(request::RequestFileTransfer::ResumeFile {
+                filepath: "app.bin".into(),
+                dfi: DataFormatIdentifier::default(),
+                filesize_len: 1,
+                uncompressed_size: 4,
+                compressed_size: 4,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::RequestSequenceError);
     }
 
     #[cfg(any(feature = "std2006", feature = "std2013"))]
@@ -1143,4 +4205,507 @@ mod tests {
             .unwrap_err();
         assert_eq!(err, response::Code::IncorrectMessageLengthOrInvalidFormat);
     }
+
+    #[tokio::test]
+    async fn response_on_event_did_change_fires_in_started_session() {
+        let mut ctx = test_context();
+        let did = DataIdentifier::from(0x4101);
+        let session = SessionType::Extended;
+        let now = Instant::now();
+
+        // onChangeOfDataIdentifier, infinite window, DID 0x4101, ReadDID 0x4101
+        let resp = ctx
+            .response_on_event(session, &[0x03, 0x02, 0x41, 0x01, 0x22, 0x41, 0x01], now)
+            .await
+            .unwrap();
+        assert_eq!(resp, vec![0x03, 0x00, 0x02, 0x41, 0x01, 0x22, 0x41, 0x01]);
+        assert!(ctx.poll_response_on_event(session, now).await.is_empty());
+
+        ctx.response_on_event(session, &[0x05, 0x02], now)
+            .await
+            .unwrap();
+        assert!(ctx.poll_response_on_event(session, now).await.is_empty());
+
+        assert!(ctx.set_static_did(&did, [0x12, 0x34]).await);
+        let actions = ctx.poll_response_on_event(session, now).await;
+        assert_eq!(actions, vec![RoeAction::Respond(vec![0x22, 0x41, 0x01])]);
+        assert!(ctx.poll_response_on_event(session, now).await.is_empty());
+
+        let resp = ctx
+            .response_on_event(session, &[0x04, 0x02], now)
+            .await
+            .unwrap();
+        assert_eq!(
+            resp,
+            vec![0x04, 0x01, 0x03, 0x02, 0x41, 0x01, 0x22, 0x41, 0x01]
+        );
+
+        // the events are stopped when session is changed
+        assert!(ctx
+            .poll_response_on_event(SessionType::Default, now)
+            .await
+            .is_empty());
+        assert!(ctx.roe.lock().await.started.is_none());
+        assert!(ctx.set_static_did(&did, [0x56, 0x78]).await);
+        assert!(ctx.poll_response_on_event(session, now).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn response_on_event_timer_and_window() {
+        let ctx = test_context();
+        let session = SessionType::Extended;
+        let now = Instant::now();
+
+        // onTimerInterrupt fast rate, window 1s, ReadDTCInfo reportNumberOfDTCByStatusMask
+        ctx.response_on_event(session, &[0x02, 0x01, 0x03, 0x19, 0x01, 0xFF], now)
+            .await
+            .unwrap();
+        ctx.response_on_event(session, &[0x05, 0x01], now)
+            .await
+            .unwrap();
+
+        assert!(ctx.poll_response_on_event(session, now).await.is_empty());
+        let actions = ctx
+            .poll_response_on_event(session, now + Duration::from_millis(100))
+            .await;
+        assert_eq!(actions, vec![RoeAction::Respond(vec![0x19, 0x01, 0xFF])]);
+
+        let actions = ctx
+            .poll_response_on_event(session, now + Duration::from_secs(1))
+            .await;
+        assert_eq!(
+            actions,
+            vec![
+                RoeAction::Respond(vec![0x19, 0x01, 0xFF]),
+                RoeAction::WindowClosed(vec![0x02, 0x02, 0x01, 0x03, 0x19, 0x01, 0xFF]),
+            ]
+        );
+        assert!(ctx.roe.lock().await.started.is_none());
+    }
+
+    #[tokio::test]
+    async fn response_on_event_dtc_status_change_and_comparison() {
+        let mut ctx = test_context();
+        let did = DataIdentifier::from(0x4101);
+        let session = SessionType::Extended;
+        let now = Instant::now();
+
+        // onDTCStatusChange with testFailed mask
+        ctx.response_on_event(session, &[0x01, 0x02, 0x01, 0x19, 0x0E], now)
+            .await
+            .unwrap();
+        // onComparisonOfValues: DID 0x4101 larger than 0x1000, 10% hysteresis,
+        // unsigned 16 bits at offset 3 of positive response
+        ctx.response_on_event(
+            session,
+            &[
+                0x07, 0x02, 0x41, 0x01, 0x02, 0x00, 0x00, 0x10, 0x00, 0x0A, 0x40, 0x03, 0x22, 0x41,
+                0x01,
+            ],
+            now,
+        )
+        .await
+        .unwrap();
+        ctx.response_on_event(session, &[0x05, 0x02], now)
+            .await
+            .unwrap();
+
+        let mut dtc = sample_dtc(0x112233);
+        dtc.status = 0x08;
+        ctx.replace_dtcs(vec![dtc.clone()]).await;
+        // the status bits out of mask are ignored
+        assert!(ctx.poll_response_on_event(session, now).await.is_empty());
+        dtc.status = 0x09;
+        ctx.replace_dtcs(vec![dtc]).await;
+        assert_eq!(
+            ctx.poll_response_on_event(session, now).await,
+            vec![RoeAction::Respond(vec![0x19, 0x0E])]
+        );
+
+        assert!(ctx.set_static_did(&did, [0x10, 0x01]).await);
+        assert_eq!(
+            ctx.poll_response_on_event(session, now).await,
+            vec![RoeAction::Respond(vec![0x22, 0x41, 0x01])]
+        );
+        // not re-armed within hysteresis
+        assert!(ctx.set_static_did(&did, [0x0F, 0x00]).await);
+        assert!(ctx.poll_response_on_event(session, now).await.is_empty());
+        assert!(ctx.set_static_did(&did, [0x10, 0x01]).await);
+        assert!(ctx.poll_response_on_event(session, now).await.is_empty());
+        assert!(ctx.set_static_did(&did, [0x0E, 0x00]).await);
+        assert!(ctx.poll_response_on_event(session, now).await.is_empty());
+        assert!(ctx.set_static_did(&did, [0x10, 0x01]).await);
+        assert_eq!(
+            ctx.poll_response_on_event(session, now).await,
+            vec![RoeAction::Respond(vec![0x22, 0x41, 0x01])]
+        );
+    }
+
+    #[tokio::test]
+    async fn response_on_event_rejects_invalid_request() {
+        let ctx = test_context();
+        let session = SessionType::Extended;
+        let now = Instant::now();
+
+        let err = ctx
+            .response_on_event(session, &[0x05, 0x02], now)
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::ConditionsNotCorrect);
+
+        // the service is not recommended
+        let err = ctx
+            .response_on_event(session, &[0x03, 0x02, 0x41, 0x01, 0x2E, 0x41, 0x01], now)
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::RequestOutOfRange);
+
+        // DID is not configured
+        let err = ctx
+            .response_on_event(session, &[0x03, 0x02, 0xF1, 0x00, 0x22, 0xF1, 0x00], now)
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::RequestOutOfRange);
+
+        let err = ctx
+            .response_on_event(session, &[0x03, 0x02, 0x41, 0x01], now)
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::IncorrectMessageLengthOrInvalidFormat);
+
+        let err = ctx
+            .response_on_event(session, &[0x08, 0x02, 0x01], now)
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::SubFunctionNotSupported);
+    }
+
+    #[cfg(feature = "std2020")]
+    pub(crate) fn auth_context(role_oid: Option<&str>) -> Context {
+        use crate::{
+            pki::{
+                parse_certificate,
+                tests::{ECU, ECU_KEY, ROOT},
+                SigningKey,
+            },
+            server::context::AuthPki,
+        };
+        use x509_cert::der::{asn1::ObjectIdentifier, Encode};
+
+        let mut ctx = test_context();
+        ctx.pki = Some(Arc::new(AuthPki {
+            anchor: parse_certificate(ROOT).unwrap(),
+            identity: Some((
+                parse_certificate(ECU).unwrap().to_der().unwrap(),
+                SigningKey::from_pem(ECU_KEY).unwrap(),
+            )),
+            role_oid: role_oid.map(|v| ObjectIdentifier::new(v).unwrap()),
+            challenge_len: 16,
+        }));
+        ctx
+    }
+
+    #[cfg(feature = "std2020")]
+    fn auth_data(data: &[u8]) -> iso14229_1::NotNullableData {
+        iso14229_1::NotNullableData::new(data.to_vec()).unwrap()
+    }
+
+    #[cfg(feature = "std2020")]
+    #[tokio::test]
+    async fn authentication_unidirectional() {
+        use crate::pki::{
+            tests::{TESTER, TESTER_KEY},
+            SigningKey,
+        };
+
+        let ctx = auth_context(Some("1.3.6.1.4.1.55555.1.1"));
+        let session = SessionType::Extended;
+        let resp = ctx
+            .authentication(
+                session,
+                request::Authentication::VerifyCertificateUnidirectional {
+                    config: 0x00,
+                    certificate: auth_data(TESTER),
+                    challenge: Default::default(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp[0], 0x11);
+        assert_eq!(&resp[1..3], &[0x00, 0x10]);
+        assert_eq!(&resp[19..], &[0x00, 0x00]);
+        let challenge = &resp[3..19];
+
+        let proof = SigningKey::from_pem(TESTER_KEY).unwrap().sign(challenge);
+        let resp = ctx
+            .authentication(
+                session,
+                request::Authentication::ProofOfOwnership {
+                    proof_of_ownership: auth_data(&proof),
+                    ephemeral_public_key: Default::default(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp, vec![0x12, 0x00, 0x00]);
+        assert_eq!(
+            ctx.authenticated_role(session).await,
+            Some(vec![0x00, 0x03])
+        );
+
+        let resp = ctx
+            .authentication(session, request::Authentication::DeAuthenticate)
+            .await
+            .unwrap();
+        assert_eq!(resp, vec![0x10]);
+        assert_eq!(ctx.authenticated_role(session).await, None);
+    }
+
+    #[cfg(feature = "std2020")]
+    #[tokio::test]
+    async fn authentication_bidirectional() {
+        use crate::pki::{
+            parse_certificate,
+            tests::{ECU, TESTER_ED25519, TESTER_ED25519_KEY},
+            verify_signature, SigningKey,
+        };
+
+        let ctx = auth_context(None);
+        let session = SessionType::Programming;
+        let resp = ctx
+            .authentication(
+                session,
+                request::Authentication::VerifyCertificateBidirectional {
+                    config: 0x00,
+                    certificate: auth_data(TESTER_ED25519),
+                    challenge: auth_data(b"client challenge"),
+                },
+            )
+            .await
+            .unwrap();
+        let resp = response::Response::new(
+            iso14229_1::Service::Authentication,
+            Some(iso14229_1::AuthenticationTask::VerifyCertificateBidirectional.into()),
+            resp,
+            &Configuration::default(),
+        )
+        .unwrap();
+        let data: Vec<u8> = resp.raw_data().to_vec();
+        assert_eq!(data[0], 0x11);
+        let mut offset = 1;
+        let mut fields = vec![];
+        for _ in 0..4 {
+            let len = u16::from_be_bytes([data[offset], data[offset + 1]]) as usize;
+            fields.push(data[offset + 2..offset + 2 + len].to_vec());
+            offset += 2 + len;
+        }
+        let (challenge, certificate, proof) = (&fields[0], &fields[1], &fields[2]);
+        assert_eq!(challenge.len(), 16);
+        assert!(fields[3].is_empty());
+
+        let ecu = parse_certificate(ECU).unwrap();
+        assert_eq!(parse_certificate(certificate).unwrap(), ecu);
+        verify_signature(
+            &ecu.tbs_certificate.subject_public_key_info,
+            b"client challenge",
+            proof,
+        )
+        .unwrap();
+
+        let proof = SigningKey::from_pem(TESTER_ED25519_KEY)
+            .unwrap()
+            .sign(challenge);
+        let resp = ctx
+            .authentication(
+                session,
+                request::Authentication::ProofOfOwnership {
+                    proof_of_ownership: auth_data(&proof),
+                    ephemeral_public_key: Default::default(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp, vec![0x12, 0x00, 0x00]);
+        assert_eq!(ctx.authenticated_role(session).await, Some(vec![]));
+        // the authentication is kept in other non-default session
+        assert_eq!(
+            ctx.authenticated_role(SessionType::Extended).await,
+            Some(vec![])
+        );
+        // the authentication is ended in the default session
+        assert_eq!(ctx.authenticated_role(SessionType::Default).await, None);
+        assert_eq!(ctx.authenticated_role(session).await, None);
+    }
+
+    #[cfg(feature = "std2020")]
+    #[tokio::test]
+    async fn authentication_rejects_invalid_client() {
+        use crate::pki::tests::{ECU, TESTER, UNTRUSTED};
+
+        let session = SessionType::Extended;
+        let err = test_context()
+            .authentication(session, request::Authentication::DeAuthenticate)
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::SubFunctionNotSupported);
+
+        let ctx = auth_context(Some("1.3.6.1.4.1.55555.1.1"));
+        let verify = |cert: &[u8]| request::Authentication::VerifyCertificateUnidirectional {
+            config: 0x00,
+            certificate: auth_data(cert),
+            challenge: Default::default(),
+        };
+        let err = ctx
+            .authentication(session, verify(UNTRUSTED))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            response::Code::CertificateVerificationFailedInvalidChainOfTrust
+        );
+        // the role extension is absent
+        let err = ctx.authentication(session, verify(ECU)).await.unwrap_err();
+        assert_eq!(
+            err,
+            response::Code::CertificateVerificationFailedInvalidScope
+        );
+
+        let proof = request::Authentication::ProofOfOwnership {
+            proof_of_ownership: auth_data(&[0x00; 64]),
+            ephemeral_public_key: Default::default(),
+        };
+        let err = ctx
+            .authentication(session, proof.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err, response::Code::RequestSequenceError);
+
+        ctx.authentication(session, verify(TESTER)).await.unwrap();
+        let err = ctx.authentication(session, proof).await.unwrap_err();
+        assert_eq!(err, response::Code::OwnershipVerificationFailed);
+        assert_eq!(ctx.authenticated_role(session).await, None);
+    }
+
+    #[cfg(feature = "std2020")]
+    #[tokio::test]
+    async fn secured_data_with_session_key() {
+        use crate::{
+            pki::{
+                tests::{TESTER, TESTER_KEY},
+                SigningKey,
+            },
+            secured::{secured_header, EphemeralKey, SECURED_ALGORITHM},
+        };
+        use iso14229_1::{AdministrativeParameter, SignatureEncryptionCalculation};
+
+        let ctx = auth_context(None);
+        let session = SessionType::Extended;
+        let resp = ctx
+            .authentication(
+                session,
+                request::Authentication::VerifyCertificateUnidirectional {
+                    config: 0x00,
+                    certificate: auth_data(TESTER),
+                    challenge: Default::default(),
+                },
+            )
+            .await
+            .unwrap();
+        let challenge = resp[3..19].to_vec();
+        let ephemeral = EphemeralKey::new();
+        let resp = ctx
+            .authentication(
+                session,
+                request::Authentication::ProofOfOwnership {
+                    proof_of_ownership: auth_data(
+                        &SigningKey::from_pem(TESTER_KEY).unwrap().sign(&challenge),
+                    ),
+                    ephemeral_public_key: iso14229_1::NullableData::new(ephemeral.public_key())
+                        .unwrap(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(&resp[..3], &[0x12, 0x00, 0x41]);
+        let key = ephemeral.agree(&resp[3..], &challenge).unwrap();
+
+        let mut apar = AdministrativeParameter::new();
+        apar.signed_set(true)
+            .encrypted_set(true)
+            .signature_on_response_set(true);
+        let header = secured_header(
+            apar.request_set(true).into_bits(),
+            SECURED_ALGORITHM,
+            0x0001,
+        );
+        let data = key.encrypt(&header, &[0x41, 0x01]).unwrap();
+        let mut message = vec![0x22];
+        message.extend(&data);
+        let req = request::SecuredDataTrans::new(
+            apar,
+            SignatureEncryptionCalculation::SystemSupplier(SECURED_ALGORITHM),
+            0x0001,
+            0x22,
+            data,
+            key.sign(&header, &message),
+        )
+        .unwrap();
+        assert_eq!(
+            ctx.secured_data_unwrap(session, &req).await.unwrap(),
+            vec![0x22, 0x41, 0x01]
+        );
+        // the antiReplayCounter is reused
+        assert_eq!(
+            ctx.secured_data_unwrap(session, &req).await.unwrap_err(),
+            response::Code::SecureDataVerificationFailed
+        );
+
+        let resp = ctx
+            .secured_data_wrap(session, &req, vec![0x62, 0x41, 0x01, 0x12, 0x34])
+            .await
+            .unwrap();
+        let response::SecuredDataTrans::Successful(resp) = resp else {
+            panic!("unexpected negative secured response");
+        };
+        assert!(!resp.apar.is_request());
+        assert_eq!(resp.anti_replay_cnt, 0x0001);
+        let header = secured_header(resp.apar.into_bits(), SECURED_ALGORITHM, 0x0001);
+        let mut message = vec![resp.response];
+        message.extend(&resp.response_params);
+        assert!(key.verify(&header, &message, &resp.signature_data));
+        assert_eq!(
+            key.decrypt(&header, &resp.response_params),
+            Some(vec![0x41, 0x01, 0x12, 0x34])
+        );
+
+        let resp = ctx
+            .secured_data_wrap(session, &req, vec![0x7F, 0x22, 0x31])
+            .await
+            .unwrap();
+        let response::SecuredDataTrans::Unsuccessful(resp) = resp else {
+            panic!("unexpected positive secured response");
+        };
+        assert_eq!((resp.service, resp.response), (0x22, 0x31));
+        assert!(key.verify(&header, &[0x7F, 0x22, 0x31], &resp.signature_data));
+
+        // the session key is kept from the extended session to the programming session
+        assert!(ctx
+            .secured_data_wrap(SessionType::Programming, &req, vec![0x62, 0x41, 0x01])
+            .await
+            .is_ok());
+        ctx.enter_session(SessionType::Programming).await;
+        assert_eq!(
+            ctx.authenticated_role(SessionType::Programming).await,
+            Some(vec![])
+        );
+        // the session key is removed in the default session
+        ctx.enter_session(SessionType::Default).await;
+        assert_eq!(ctx.authenticated_role(SessionType::Extended).await, None);
+        assert_eq!(
+            ctx.secured_data_unwrap(SessionType::Default, &req)
+                .await
+                .unwrap_err(),
+            response::Code::SecureDataVerificationFailed
+        );
+    }
 }
@@ -0,0 +1,347 @@
+//! The initial values and data sources of DID.
+
+use iso14229_1::DataIdentifier;
+use rand::{rng, RngExt};
+use rsutil::types::ByteOrder;
+use serde::Deserialize;
+use std::{
+    f64::consts::PI,
+    time::{Duration, Instant},
+};
+
+/// The user callback of DID data source, the data length must be equal to the configured length.
+pub type DidCallback = fn(DataIdentifier) -> Option<Vec<u8>>;
+
+/// The value of DID, the data length is defined by `cfg.did`.
+///
+/// The numeric values are encoded by the `byte_order` of configuration, the values of
+/// counter, ramp, sine, random and callback are changed on each read.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DidSource {
+    /// the raw data, e.g. `"F1 90 0A"`
+    Hex {
+        value: String,
+    },
+    /// the ASCII text, padded with space
+    Ascii {
+        value: String,
+    },
+    Numeric {
+        value: i64,
+    },
+    /// increased by `step` on each read and wrapped in `[min, max]`
+    Counter {
+        #[serde(default)]
+        min: i64,
+        max: i64,
+        #[serde(default = "counter_step_default")]
+        step: i64,
+    },
+    /// increased from `min` to `max` in each period
+    Ramp {
+        min: f64,
+        max: f64,
+        period_ms: u64,
+    },
+    Sine {
+        offset: f64,
+        amplitude: f64,
+        period_ms: u64,
+    },
+    /// the uniform random value in `[min, max]`
+    Random {
+        min: i64,
+        max: i64,
+    },
+    #[serde(skip)]
+    Callback(DidCallback),
+}
+
+#[inline(always)]
+fn counter_step_default() -> i64 {
+    1
+}
+
+impl DidSource {
+    /// Check the source, return the data if the value is static.
+    pub(crate) fn initial_value(
+        &self,
+        len: usize,
+        byte_order: ByteOrder,
+    ) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            Self::Hex { value } => {
+                let data = hex::decode(value.split_whitespace().collect::<String>())
+                    .map_err(|e| e.to_string())?;
+                if data.len() != len {
+                    return Err(format!("the length of hex value is not {}", len));
+                }
+                Ok(Some(data))
+            }
+            Self::Ascii { value } => {
+                if !value.is_ascii() || value.len() > len {
+                    return Err(format!("the value is not an ASCII text of {} bytes", len));
+                }
+                let mut data = value.as_bytes().to_vec();
+                data.resize(len, b' ');
+                Ok(Some(data))
+            }
+            Self::Numeric { value } => {
+                if !numeric_fits(*value, len) {
+                    return Err(format!("the value is out of {} bytes", len));
+                }
+                Ok(Some(encode_numeric(*value, len, byte_order)))
+            }
+            Self::Counter { min, max, step } => {
+                if min > max || *step == 0 {
+                    return Err("the counter range or step is invalid".into());
+                }
+                Ok(None)
+            }
+            Self::Random { min, max } => {
+                if min > max {
+                    return Err("the random range is invalid".into());
+                }
+                Ok(None)
+            }
+            Self::Ramp { period_ms, .. } | Self::Sine { period_ms, .. } => {
+                if *period_ms == 0 {
+                    return Err("the period is zero".into());
+                }
+                Ok(None)
+            }
+            Self::Callback(_) => Ok(None),
+        }
+    }
+}
+
+/// The access of DID which is checked by the policy.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum DidAccessType {
+    /// ReadDID, ReadScalingDID and the source DID of DynamicallyDefineDID
+    Read,
+    Write,
+    IoControl,
+}
+
+/// The data source of live DID.
+#[derive(Debug, Clone)]
+pub(crate) struct DidGenerator {
+    source: DidSource,
+    counter: i64,
+    started: Instant,
+}
+
+impl DidGenerator {
+    pub(crate) fn new(source: DidSource) -> Self {
+        let counter = match &source {
+            DidSource::Counter { min, .. } => *min,
+            _ => 0,
+        };
+
+        Self {
+            source,
+            counter,
+            started: Instant::now(),
+        }
+    }
+
+    /// Get the current value without stepping the counter.
+    #[inline(always)]
+    pub(crate) fn peek(
+        &self,
+        did: DataIdentifier,
+        len: usize,
+        byte_order: ByteOrder,
+    ) -> Option<Vec<u8>> {
+        self.value_at(did, len, byte_order, self.started.elapsed())
+    }
+
+    /// Get the current value and step the counter.
+    pub(crate) fn next(
+        &mut self,
+        did: DataIdentifier,
+        len: usize,
+        byte_order: ByteOrder,
+    ) -> Option<Vec<u8>> {
+        let value = self.peek(did, len, byte_order);
+        if let DidSource::Counter { min, max, step } = &self.source {
+            let next = self.counter.saturating_add(*step);
+            self.counter = if next > *max {
+                *min
+            } else if next < *min {
+                *max
+            } else {
+                next
+            };
+        }
+        value
+    }
+
+    fn value_at(
+        &self,
+        did: DataIdentifier,
+        len: usize,
+        byte_order: ByteOrder,
+        elapsed: Duration,
+    ) -> Option<Vec<u8>> {
+        let phase =
+            |period_ms: u64| (elapsed.as_millis() % period_ms as u128) as f64 / period_ms as f64;
+
+        let value = match &self.source {
+            DidSource::Counter { .. } => self.counter,
+            DidSource::Ramp {
+                min,
+                max,
+                period_ms,
+            } => (min + (max - min) * phase(*period_ms)).round() as i64,
+            DidSource::Sine {
+                offset,
+                amplitude,
+                period_ms,
+            } => (offset + amplitude * (2. * PI * phase(*period_ms)).sin()).round() as i64,
+            DidSource::Random { min, max } => rng().random_range(*min..=*max),
+            DidSource::Callback(callback) => {
+                return callback(did).filter(|data| data.len() == len);
+            }
+            DidSource::Hex { .. } | DidSource::Ascii { .. } | DidSource::Numeric { .. } => {
+                return None;
+            }
+        };
+
+        Some(encode_numeric(value, len, byte_order))
+    }
+}
+
+#[inline(always)]
+fn numeric_fits(value: i64, len: usize) -> bool {
+    match len {
+        0 => return false,
+        8.. => return true,
+        _ => {}
+    }
+
+    let bits = len as u32 * 8;
+    value >= -(1i64 << (bits - 1)) && value < (1i64 << bits)
+}
+
+/// Encode the numeric value as a two's complement integer of `len` bytes.
+fn encode_numeric(value: i64, len: usize, byte_order: ByteOrder) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut data = if len > bytes.len() {
+        let mut data = vec![if value < 0 { 0xFF } else { 0x00 }; len - bytes.len()];
+        data.extend(bytes);
+        data
+    } else {
+        bytes[bytes.len() - len..].to_vec()
+    };
+    if byte_order.is_little() {
+        data.reverse();
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DidGenerator, DidSource};
+    use iso14229_1::DataIdentifier;
+    use rsutil::types::ByteOrder;
+    use std::time::Duration;
+
+    fn source(yaml: &str) -> DidSource {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn static_values_are_encoded() {
+        let cases = [
+            (
+                "{type: hex, value: 'F1 90 0A'}",
+                3,
+                ByteOrder::Big,
+                vec![0xF1, 0x90, 0x0A],
+            ),
+            (
+                "{type: ascii, value: 'DoCAN'}",
+                6,
+                ByteOrder::Big,
+                b"DoCAN ".to_vec(),
+            ),
+            (
+                "{type: numeric, value: 1200}",
+                2,
+                ByteOrder::Big,
+                vec![0x04, 0xB0],
+            ),
+            (
+                "{type: numeric, value: 1200}",
+                2,
+                ByteOrder::Little,
+                vec![0xB0, 0x04],
+            ),
+            (
+                "{type: numeric, value: -2}",
+                2,
+                ByteOrder::Big,
+                vec![0xFF, 0xFE],
+            ),
+        ];
+        for (yaml, len, byte_order, data) in cases {
+            assert_eq!(source(yaml).initial_value(len, byte_order), Ok(Some(data)));
+        }
+
+        for (yaml, len) in [
+            ("{type: hex, value: 'F1 90'}", 3),
+            ("{type: ascii, value: 'DoCAN'}", 4),
+            ("{type: numeric, value: 256}", 1),
+            ("{type: counter, min: 10, max: 0}", 1),
+            ("{type: sine, offset: 0, amplitude: 1, period_ms: 0}", 1),
+        ] {
+            assert!(source(yaml).initial_value(len, ByteOrder::Big).is_err());
+        }
+    }
+
+    #[test]
+    fn live_values_are_generated() {
+        let did = DataIdentifier::from(0x4101);
+        let source = source("{type: counter, min: 1, max: 3, step: 1}");
+        assert_eq!(source.initial_value(1, ByteOrder::Big), Ok(None));
+        let mut generator = DidGenerator::new(source);
+        assert_eq!(generator.peek(did, 1, ByteOrder::Big), Some(vec![1]));
+        assert_eq!(generator.peek(did, 1, ByteOrder::Big), Some(vec![1]));
+        let values = (0..4)
+            .map(|_| generator.next(did, 1, ByteOrder::Big).unwrap()[0])
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![1, 2, 3, 1]);
+
+        let generator = DidGenerator::new(super::DidSource::Ramp {
+            min: 0.,
+            max: 1000.,
+            period_ms: 1_000,
+        });
+        let value = generator.value_at(did, 2, ByteOrder::Big, Duration::from_millis(1_250));
+        assert_eq!(value, Some(vec![0x00, 0xFA]));
+
+        let generator = DidGenerator::new(super::DidSource::Sine {
+            offset: 100.,
+            amplitude: 50.,
+            period_ms: 1_000,
+        });
+        let value = generator.value_at(did, 1, ByteOrder::Big, Duration::from_millis(250));
+        assert_eq!(value, Some(vec![150]));
+
+        let mut generator = DidGenerator::new(super::DidSource::Random { min: 5, max: 6 });
+        let value = generator.next(did, 1, ByteOrder::Big).unwrap()[0];
+        assert!((5..=6).contains(&value));
+
+        let mut generator = DidGenerator::new(super::DidSource::Callback(|did| {
+            Some(u16::from(did).to_be_bytes().to_vec())
+        }));
+        assert_eq!(
+            generator.next(did, 2, ByteOrder::Big),
+            Some(vec![0x41, 0x01])
+        );
+        assert_eq!(generator.next(did, 1, ByteOrder::Big), None);
+    }
+}
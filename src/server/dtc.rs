@@ -0,0 +1,383 @@
+//! The fault monitor of DTC, the status bits are updated as ISO 14229-1 Annex D.
+
+use crate::{
+    server::{
+        context::{DtcRecord, WwhObdMeta},
+        DtcConfig, DtcMonitorConfig, WwhObdConfig,
+    },
+    DoCanError,
+};
+use iso14229_1::{response::DTCFormatIdentifier, utils::U24, Configuration, DataIdentifier};
+use serde::{Deserialize, Serialize};
+
+/// testFailed
+pub(crate) const DTC_TEST_FAILED: u8 = 0x01;
+/// testFailedThisOperationCycle
+pub(crate) const DTC_TEST_FAILED_THIS_CYCLE: u8 = 0x02;
+/// pendingDTC
+pub(crate) const DTC_PENDING: u8 = 0x04;
+/// confirmedDTC
+pub(crate) const DTC_CONFIRMED: u8 = 0x08;
+/// testNotCompletedSinceLastClear
+pub(crate) const DTC_NOT_COMPLETED_SINCE_CLEAR: u8 = 0x10;
+/// testFailedSinceLastClear
+pub(crate) const DTC_FAILED_SINCE_CLEAR: u8 = 0x20;
+/// testNotCompletedThisOperationCycle
+pub(crate) const DTC_NOT_COMPLETED_THIS_CYCLE: u8 = 0x40;
+/// warningIndicatorRequested
+pub(crate) const DTC_WARNING_INDICATOR: u8 = 0x80;
+
+/// the fault detection counter of qualified failed and passed result
+const FDC_FAILED: i16 = i8::MAX as i16;
+const FDC_PASSED: i16 = i8::MIN as i16;
+
+/// The debounce and cycle counters of DTC.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DtcMonitor {
+    /// the fault detection counter(-128~127)
+    pub(crate) fdc: i8,
+    /// the operation cycles with failed result before the DTC is confirmed
+    pub(crate) failed_cycles: u8,
+    /// the operation cycles without failed result after the DTC is confirmed
+    pub(crate) aging_cycles: u8,
+}
+
+impl DtcRecord {
+    /// The DTC which is never tested since the last clear.
+    pub(crate) fn new(dtc: U24) -> Self {
+        Self {
+            dtc,
+            status: DTC_NOT_COMPLETED_SINCE_CLEAR | DTC_NOT_COMPLETED_THIS_CYCLE,
+            severity: 0,
+            func_unit: 0,
+            fault_counter: 0,
+            permanent: false,
+            ext_data: vec![],
+            snapshots: vec![],
+            user_memory: None,
+            mirror: false,
+            emissions_obd: false,
+            wwh_obd: None,
+            monitor: Default::default(),
+        }
+    }
+
+    /// The preconfigured DTC, the data length of records are checked by `cfg`.
+    pub(crate) fn from_config(config: &DtcConfig, cfg: &Configuration) -> Result<Self, DoCanError> {
+        let error = |reason: String| DoCanError::InvalidDtcConfig {
+            dtc: config.dtc,
+            reason,
+        };
+        if config.dtc > 0xFF_FF_FF {
+            return Err(error("the DTC is out of 3 bytes".into()));
+        }
+
+        for (number, data) in &config.ext_data {
+            match cfg.dtc.get(number) {
+                Some(&len) if len == data.len() => {}
+                _ => {
+                    return Err(error(format!(
+                        "the length of extended data record 0x{:02X} is not configured as {}",
+                        number,
+                        data.len()
+                    )))
+                }
+            }
+        }
+
+        let mut snapshots = Vec::with_capacity(config.snapshots.len());
+        for (&number, records) in &config.snapshots {
+            let mut data = Vec::with_capacity(records.len());
+            for (&did, value) in records {
+                let did = DataIdentifier::from(did);
+                match cfg.did.get(&did) {
+                    Some(&len) if len == value.len() => data.push((did, value.clone())),
+                    _ => {
+                        return Err(error(format!(
+                            "the length of DID {:?} in snapshot record 0x{:02X} is not configured as {}",
+                            did,
+                            number,
+                            value.len()
+                        )))
+                    }
+                }
+            }
+            snapshots.push((number, data));
+        }
+
+        let wwh_obd = match &config.wwh_obd {
+            Some(v) => Some(WwhObdMeta {
+                func_gid: v.func_gid,
+                fid: DTCFormatIdentifier::try_from(v.fid).map_err(|e| error(e.to_string()))?,
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            dtc: U24::new(config.dtc),
+            status: config.status,
+            severity: config.severity,
+            func_unit: config.func_unit,
+            fault_counter: config.fault_counter,
+            permanent: config.permanent,
+            ext_data: config
+                .ext_data
+                .iter()
+                .map(|(number, data)| (*number, data.clone()))
+                .collect(),
+            snapshots,
+            user_memory: config.user_memory,
+            mirror: config.mirror,
+            emissions_obd: config.emissions_obd,
+            wwh_obd,
+            monitor: Default::default(),
+        })
+    }
+
+    pub(crate) fn to_config(&self) -> DtcConfig {
+        DtcConfig {
+            dtc: self.dtc.into(),
+            status: self.status,
+            severity: self.severity,
+            func_unit: self.func_unit,
+            fault_counter: self.fault_counter,
+            permanent: self.permanent,
+            ext_data: self.ext_data.iter().cloned().collect(),
+            snapshots: self
+                .snapshots
+                .iter()
+                .map(|(number, records)| {
+                    let records = records
+                        .iter()
+                        .map(|(did, data)| (u16::from(*did), data.clone()))
+                        .collect();
+                    (*number, records)
+                })
+                .collect(),
+            user_memory: self.user_memory,
+            mirror: self.mirror,
+            emissions_obd: self.emissions_obd,
+            wwh_obd: self.wwh_obd.map(|meta| WwhObdConfig {
+                func_gid: meta.func_gid,
+                fid: meta.fid.into(),
+            }),
+        }
+    }
+
+    /// Debounce the test result and update the status, return `true` if the DTC becomes confirmed.
+    pub(crate) fn test_result(&mut self, passed: bool, config: &DtcMonitorConfig) -> bool {
+        let step = config.debounce_step as i16;
+        let fdc = self.monitor.fdc as i16;
+        let fdc = if passed {
+            (fdc.min(0) - step).max(FDC_PASSED)
+        } else {
+            (fdc.max(0) + step).min(FDC_FAILED)
+        };
+        self.monitor.fdc = fdc as i8;
+        self.fault_counter = fdc.max(0) as u8;
+
+        match fdc {
+            FDC_PASSED => {
+                self.status &= !(DTC_TEST_FAILED
+                    | DTC_NOT_COMPLETED_SINCE_CLEAR
+                    | DTC_NOT_COMPLETED_THIS_CYCLE);
+                false
+            }
+            FDC_FAILED => {
+                if self.status & DTC_TEST_FAILED_THIS_CYCLE == 0 {
+                    self.monitor.failed_cycles = self.monitor.failed_cycles.saturating_add(1);
+                }
+                self.monitor.aging_cycles = 0;
+                self.status |= DTC_TEST_FAILED
+                    | DTC_TEST_FAILED_THIS_CYCLE
+                    | DTC_PENDING
+                    | DTC_FAILED_SINCE_CLEAR;
+                self.status &= !(DTC_NOT_COMPLETED_SINCE_CLEAR | DTC_NOT_COMPLETED_THIS_CYCLE);
+
+                if self.status & DTC_CONFIRMED != 0
+                    || self.monitor.failed_cycles < config.confirm_cycles
+                {
+                    return false;
+                }
+
+                self.status |= DTC_CONFIRMED;
+                if config.warning_indicator {
+                    self.status |= DTC_WARNING_INDICATOR;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The pending, warning indicator and confirmed(aging) bits are updated
+    /// if the test is completed without failed result in this operation cycle.
+    pub(crate) fn end_operation_cycle(&mut self, config: &DtcMonitorConfig) {
+        if self.status & (DTC_TEST_FAILED_THIS_CYCLE | DTC_NOT_COMPLETED_THIS_CYCLE) != 0 {
+            return;
+        }
+
+        self.status &= !(DTC_PENDING | DTC_WARNING_INDICATOR);
+        if self.status & DTC_CONFIRMED == 0 {
+            self.monitor.failed_cycles = 0;
+            return;
+        }
+
+        self.monitor.aging_cycles = self.monitor.aging_cycles.saturating_add(1);
+        if config.aging_cycles > 0 && self.monitor.aging_cycles >= config.aging_cycles {
+            self.status &= !DTC_CONFIRMED;
+            self.monitor = Default::default();
+        }
+    }
+
+    pub(crate) fn start_operation_cycle(&mut self) {
+        self.status &= !DTC_TEST_FAILED_THIS_CYCLE;
+        self.status |= DTC_NOT_COMPLETED_THIS_CYCLE;
+        self.monitor.fdc = 0;
+        self.fault_counter = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dtc_config(yaml: &str) -> DtcConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn config(debounce_step: u8, confirm_cycles: u8, aging_cycles: u8) -> DtcMonitorConfig {
+        DtcMonitorConfig {
+            debounce_step,
+            confirm_cycles,
+            aging_cycles,
+            warning_indicator: true,
+        }
+    }
+
+    #[test]
+    fn zero_debounce_step_is_rejected() {
+        assert!(serde_yaml::from_str::<DtcMonitorConfig>("debounce_step: 0").is_err());
+        let config = serde_yaml::from_str::<DtcMonitorConfig>("confirm_cycles: 2").unwrap();
+        assert_eq!(config.debounce_step, 0x80);
+    }
+
+    #[test]
+    fn failed_result_is_debounced() {
+        let config = config(0x30, 1, 0);
+        let mut record = DtcRecord::new(U24::new(0x112233));
+        assert_eq!(record.status, 0x50);
+
+        assert!(!record.test_result(false, &config));
+        assert!(!record.test_result(false, &config));
+        assert_eq!(record.status, 0x50);
+        assert_eq!(record.fault_counter, 0x60);
+        // the counter jumps to zero when the result changes
+        assert!(!record.test_result(true, &config));
+        assert_eq!(record.monitor.fdc, -0x30);
+        assert!(!record.test_result(false, &config));
+        assert!(!record.test_result(false, &config));
+        assert!(record.test_result(false, &config));
+        assert_eq!(record.fault_counter, 0x7F);
+        assert_eq!(
+            record.status,
+            DTC_TEST_FAILED
+                | DTC_TEST_FAILED_THIS_CYCLE
+                | DTC_PENDING
+                | DTC_CONFIRMED
+                | DTC_FAILED_SINCE_CLEAR
+                | DTC_WARNING_INDICATOR
+        );
+        assert!(!record.test_result(false, &config));
+
+        for _ in 0..3 {
+            record.test_result(true, &config);
+        }
+        assert_eq!(record.status & DTC_TEST_FAILED, 0);
+    }
+
+    #[test]
+    fn pending_dtc_is_confirmed_after_failed_cycles() {
+        let config = config(0x80, 2, 0);
+        let mut record = DtcRecord::new(U24::new(0x112233));
+
+        assert!(!record.test_result(false, &config));
+        assert!(!record.test_result(false, &config));
+        assert_eq!(record.status, 0x27);
+        record.end_operation_cycle(&config);
+        assert_eq!(record.status & DTC_PENDING, DTC_PENDING);
+
+        record.start_operation_cycle();
+        assert_eq!(record.status, 0x65);
+        assert!(record.test_result(false, &config));
+        assert_eq!(record.status, 0xAF);
+    }
+
+    #[test]
+    fn confirmed_dtc_is_aged_by_passed_cycles() {
+        let config = config(0x80, 1, 2);
+        let mut record = DtcRecord::new(U24::new(0x112233));
+        assert!(record.test_result(false, &config));
+        record.end_operation_cycle(&config);
+
+        // the test is not completed in this cycle
+        record.start_operation_cycle();
+        record.end_operation_cycle(&config);
+        assert_eq!(record.status, 0xED);
+
+        for cycles in 1..=2 {
+            record.start_operation_cycle();
+            record.test_result(true, &config);
+            record.end_operation_cycle(&config);
+            assert_eq!(record.monitor.aging_cycles, cycles % 2);
+        }
+        assert_eq!(record.status, DTC_FAILED_SINCE_CLEAR);
+    }
+
+    #[test]
+    fn preconfigured_dtc_is_checked_by_configuration() {
+        let mut cfg = Configuration::default();
+        cfg.dtc.insert(0x02, 2);
+        cfg.did.insert(DataIdentifier::from(0x4101), 2);
+
+        let config = dtc_config(
+            r#"
+dtc: 0x112233
+status: 0x2F
+severity: 0x20
+ext_data:
+  0x02: [0xAA, 0xBB]
+snapshots:
+  0x01:
+    0x4101: [0x01, 0x02]
+user_memory: 0x01
+wwh_obd:
+  func_gid: 0x33
+  fid: 0x04
+"#,
+        );
+        let record = DtcRecord::from_config(&config, &cfg).unwrap();
+        assert_eq!(record.status, 0x2F);
+        assert_eq!(record.ext_data, vec![(0x02, vec![0xAA, 0xBB])]);
+        assert_eq!(record.user_memory, Some(0x01));
+        assert_eq!(
+            record.wwh_obd.map(|v| v.fid),
+            Some(DTCFormatIdentifier::SAE_J2012_DA_DTCFormat_04)
+        );
+
+        let record = DtcRecord::from_config(&dtc_config("dtc: 0x445566"), &cfg).unwrap();
+        assert_eq!(record, DtcRecord::new(U24::new(0x445566)));
+
+        for yaml in [
+            "dtc: 0x1000000",
+            "dtc: 0x112233\next_data:\n  0x03: [0xAA, 0xBB]",
+            "dtc: 0x112233\nsnapshots:\n  0x01:\n    0x4101: [0x01]",
+            "dtc: 0x112233\nwwh_obd:\n  func_gid: 0x33\n  fid: 0x10",
+        ] {
+            assert!(matches!(
+                DtcRecord::from_config(&dtc_config(yaml), &cfg),
+                Err(DoCanError::InvalidDtcConfig { .. })
+            ));
+        }
+    }
+}
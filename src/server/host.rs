@@ -0,0 +1,410 @@
+//! The host of virtual ECUs which are served on one CAN device.
+
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{context::Context, DoCanServer, Server},
+    DoCanError,
+};
+use iso15765_2::can::Address;
+use rs_can::{CanDevice, CanFrame, CanResult, DeviceBuilder};
+use serde::Deserialize;
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::Display,
+    path::PathBuf,
+    sync::{Arc, Mutex as SyncMutex},
+};
+use tokio::{fs::read, sync::Mutex};
+
+/// The received frames which are not read by the handle are dropped when the queue is full.
+const SHARED_QUEUE_SIZE: usize = 1024;
+
+struct SharedState<F> {
+    /// only one handle receives from the device at the same time
+    reader: Mutex<()>,
+    /// the queues of all handles
+    queues: SyncMutex<Vec<Arc<SyncMutex<VecDeque<F>>>>>,
+}
+
+/// The handle of CAN device which is shared by the virtual ECUs and testers,
+/// each handle receives all frames of the device.
+pub struct SharedDevice<D: CanDevice> {
+    device: D,
+    /// the frames received by other handles
+    queue: Arc<SyncMutex<VecDeque<D::Frame>>>,
+    state: Arc<SharedState<D::Frame>>,
+}
+
+impl<D: CanDevice + Clone> Clone for SharedDevice<D> {
+    fn clone(&self) -> Self {
+        Self {
+            device: self.device.clone(),
+            queue: self.queue.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<D> SharedDevice<D>
+where
+    D: CanDevice + Clone,
+    D::Channel: PartialEq,
+    D::Frame: Clone,
+{
+    pub fn new(device: D) -> Self {
+        let queue: Arc<SyncMutex<VecDeque<_>>> = Default::default();
+        Self {
+            device,
+            queue: queue.clone(),
+            state: Arc::new(SharedState {
+                reader: Default::default(),
+                queues: SyncMutex::new(vec![queue]),
+            }),
+        }
+    }
+
+    /// Create a new handle of the device, the handle receives all frames from now on.
+    pub fn handle(&self) -> Self {
+        let queue: Arc<SyncMutex<VecDeque<_>>> = Default::default();
+        self.state
+            .queues
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(queue.clone());
+
+        Self {
+            device: self.device.clone(),
+            queue,
+            state: self.state.clone(),
+        }
+    }
+
+    /// Take the queued frames of channel.
+    fn take(&self, channel: &D::Channel) -> Vec<D::Frame> {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        let (res, rest): (Vec<_>, Vec<_>) = queue.drain(..).partition(|f| f.channel() == *channel);
+        *queue = rest.into();
+
+        res
+    }
+
+    /// Copy the frames which are received by this handle into the queues of other handles.
+    fn dispatch(&self, frames: &[D::Frame]) {
+        let queues = self.state.queues.lock().unwrap_or_else(|e| e.into_inner());
+        for queue in queues.iter() {
+            if Arc::ptr_eq(queue, &self.queue) {
+                continue;
+            }
+
+            let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+            for frame in frames {
+                if queue.len() >= SHARED_QUEUE_SIZE {
+                    queue.pop_front();
+                }
+                queue.push_back(frame.clone());
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<D> CanDevice for SharedDevice<D>
+where
+    D: CanDevice + Clone,
+    D::Channel: PartialEq + Send + Sync,
+    D::Frame: Clone + Send,
+{
+    type Channel = D::Channel;
+    type Frame = D::Frame;
+
+    fn new(builder: DeviceBuilder<Self::Channel>) -> CanResult<Self> {
+        D::new(builder).map(Self::new)
+    }
+
+    #[inline(always)]
+    fn opened_channels(&self) -> Vec<Self::Channel> {
+        self.device.opened_channels()
+    }
+
+    #[inline(always)]
+    async fn transmit(&self, msg: Self::Frame, timeout: Option<u32>) -> CanResult<()> {
+        self.device.transmit(msg, timeout).await
+    }
+
+    async fn receive(
+        &self,
+        channel: Self::Channel,
+        timeout: Option<u32>,
+    ) -> CanResult<Vec<Self::Frame>> {
+        let frames = self.take(&channel);
+        if !frames.is_empty() {
+            return Ok(frames);
+        }
+
+        let _reader = self.state.reader.lock().await;
+        // the frames may be received by other handle while waiting for the reader
+        let frames = self.take(&channel);
+        if !frames.is_empty() {
+            return Ok(frames);
+        }
+
+        let frames = self.device.receive(channel, timeout).await?;
+        self.dispatch(&frames);
+
+        Ok(frames)
+    }
+
+    #[inline(always)]
+    fn shutdown(&mut self) {
+        self.device.shutdown();
+    }
+}
+
+/// The definition of virtual ECU.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct EcuConfig {
+    /// the unique name of ECU
+    pub(crate) name: String,
+    /// the configuration file of server, e.g. `docan.server.yaml`
+    pub(crate) config: PathBuf,
+    /// the address of ECU, the address of configuration file is used if absent
+    #[serde(default)]
+    pub(crate) address: Option<Address>,
+}
+
+/// The virtual ECUs of host, the functional address(`fid`) may be shared by ECUs.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct HostConfig {
+    pub(crate) ecus: Vec<EcuConfig>,
+}
+
+impl HostConfig {
+    /// Check the names and physical addresses of ECUs are unique.
+    pub(crate) fn validate<'a, I>(ecus: I) -> Result<(), DoCanError>
+    where
+        I: IntoIterator<Item = (&'a str, Vec<u32>)>,
+    {
+        let mut names = HashSet::new();
+        let mut ids = HashSet::new();
+        for (name, physical_ids) in ecus {
+            if !names.insert(name) {
+                return Err(DoCanError::OtherError(format!(
+                    "ECU `{}` is defined more than once",
+                    name
+                )));
+            }
+            for id in physical_ids {
+                if !ids.insert(id) {
+                    return Err(DoCanError::OtherError(format!(
+                        "ECU `{}` got a duplicate physical address 0x{:X}",
+                        name, id
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The host of virtual ECUs, each ECU has its own session and context on the shared CAN device.
+pub struct DoCanHost<D: CanDevice, C, F> {
+    ecus: Vec<(String, DoCanServer<D, C, F>)>,
+}
+
+impl<D, C, F> DoCanHost<D, C, F>
+where
+    D: CanDevice<Channel = C, Frame = F> + Clone + Send + 'static,
+    C: Clone + Eq + Display + Send + Sync + 'static,
+    F: CanFrame<Channel = C> + Clone + Display + 'static,
+{
+    /// Load the virtual ECUs from `docan.host.yaml`.
+    pub async fn new(device: D, channel: C) -> Result<Self, DoCanError> {
+        let reader = read("docan.host.yaml")
+            .await
+            .map_err(|e| DoCanError::OtherError(format!("{:?}", e)))?;
+        let config = serde_yaml::from_slice::<HostConfig>(reader.as_slice())
+            .map_err(|e| DoCanError::OtherError(format!("{:?}", e)))?;
+
+        Self::from_config(device, channel, config).await
+    }
+
+    pub async fn from_config(
+        device: D,
+        channel: C,
+        config: HostConfig,
+    ) -> Result<Self, DoCanError> {
+        let mut contexts = Vec::with_capacity(config.ecus.len());
+        for ecu in config.ecus {
+            let mut server_config = Context::read_config(&ecu.config).await?;
+            if let Some(address) = ecu.address {
+                server_config.address = address;
+            }
+            contexts.push((ecu.name, server_config));
+        }
+        HostConfig::validate(
+            contexts
+                .iter()
+                .map(|(name, config)| (name.as_str(), config.physical_ids())),
+        )?;
+
+        let device = SharedDevice::new(device);
+        let mut ecus = Vec::with_capacity(contexts.len());
+        for (index, (name, config)) in contexts.into_iter().enumerate() {
+            let device = if index == 0 {
+                device.clone()
+            } else {
+                device.handle()
+            };
+            let context = Context::from_config(config).await?;
+            let server = DoCanServer::with_context(device, channel.clone(), context).await;
+            ecus.push((name, server));
+        }
+
+        Ok(Self { ecus })
+    }
+
+    /// The names of ECUs in definition order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.ecus.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn ecu(&self, name: &str) -> Option<&DoCanServer<D, C, F>> {
+        self.ecus.iter().find(|(v, _)| v == name).map(|(_, v)| v)
+    }
+
+    pub fn ecu_mut(&mut self, name: &str) -> Option<&mut DoCanServer<D, C, F>> {
+        self.ecus
+            .iter_mut()
+            .find(|(v, _)| v == name)
+            .map(|(_, v)| v)
+    }
+
+    pub async fn service_forever(&mut self, interval_us: u64) {
+        for (name, server) in &mut self.ecus {
+            server.service_forever(interval_us).await;
+            rsutil::info!("{} ECU `{}` started", LOG_TAG_SERVER, name);
+        }
+    }
+
+    pub async fn service_stop(&mut self) {
+        for (_, server) in &mut self.ecus {
+            server.service_stop().await;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rs_can::{CanError, CanId};
+    use socketcan_rs::SocketCanFrame;
+    use std::time::Duration;
+
+    /// The device of tests, the received frames are pushed by test and the transmitted frames are recorded.
+    #[derive(Clone, Default)]
+    pub(crate) struct MockDevice {
+        pub(crate) frames: Arc<SyncMutex<VecDeque<Vec<SocketCanFrame>>>>,
+        pub(crate) transmitted: Arc<SyncMutex<Vec<SocketCanFrame>>>,
+        /// the received frames of the other device on the same bus
+        peer: Option<Arc<SyncMutex<VecDeque<Vec<SocketCanFrame>>>>>,
+    }
+
+    #[cfg(feature = "client")]
+    impl MockDevice {
+        /// The devices on the same bus, the transmitted frame is received by the other one.
+        pub(crate) fn pair() -> (Self, Self) {
+            let (mut first, mut second) = (Self::default(), Self::default());
+            first.peer = Some(second.frames.clone());
+            second.peer = Some(first.frames.clone());
+            (first, second)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl CanDevice for MockDevice {
+        type Channel = String;
+        type Frame = SocketCanFrame;
+
+        fn new(_: DeviceBuilder<Self::Channel>) -> CanResult<Self> {
+            Err(CanError::NotSupportedError)
+        }
+
+        fn opened_channels(&self) -> Vec<Self::Channel> {
+            vec!["can0".into(), "can1".into()]
+        }
+
+        async fn transmit(&self, msg: Self::Frame, _: Option<u32>) -> CanResult<()> {
+            if let Some(peer) = &self.peer {
+                peer.lock().unwrap().push_back(vec![msg.clone()]);
+            }
+            self.transmitted.lock().unwrap().push(msg);
+            Ok(())
+        }
+
+        async fn receive(&self, _: Self::Channel, _: Option<u32>) -> CanResult<Vec<Self::Frame>> {
+            let frames = self.frames.lock().unwrap().pop_front();
+            match frames {
+                Some(frames) => Ok(frames),
+                None => {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    Ok(vec![])
+                }
+            }
+        }
+
+        fn shutdown(&mut self) {}
+    }
+
+    fn frame(channel: &str, id: u32) -> SocketCanFrame {
+        let mut frame =
+            SocketCanFrame::new_can(CanId::from_bits(id, None).unwrap(), &[0x02, 0x10, 0x01])
+                .unwrap();
+        frame.set_channel(channel.into());
+        frame
+    }
+
+    #[tokio::test]
+    async fn frames_are_received_by_all_handles() {
+        let device = MockDevice::default();
+        device
+            .frames
+            .lock()
+            .unwrap()
+            .push_back(vec![frame("can0", 0x7DF), frame("can1", 0x7E0)]);
+        let shared = SharedDevice::new(device);
+        let devices = [shared.clone(), shared.handle(), shared.handle()];
+
+        let frames = devices[0].receive("can0".into(), None).await.unwrap();
+        assert_eq!(frames.len(), 2);
+        for device in &devices[1..] {
+            let frames = device.receive("can1".into(), None).await.unwrap();
+            assert_eq!(frames.len(), 1);
+            assert_eq!(frames[0].id().as_raw(), 0x7E0);
+            let frames = device.receive("can0".into(), None).await.unwrap();
+            assert_eq!(frames.len(), 1);
+            assert_eq!(frames[0].id().as_raw(), 0x7DF);
+        }
+        assert!(shared
+            .receive("can0".into(), None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn ecu_addresses_are_unique() {
+        let engine = || ("engine", vec![0x7E8, 0x7E0]);
+        let brake = || ("brake", vec![0x7E9, 0x7E1]);
+
+        assert!(HostConfig::validate([engine(), brake()]).is_ok());
+        assert!(HostConfig::validate([engine(), ("engine", vec![0x7E9, 0x7E1])]).is_err());
+        assert!(HostConfig::validate([engine(), ("brake", vec![0x7E9, 0x7E8])]).is_err());
+        // the physical addresses of testers
+        assert!(
+            HostConfig::validate([engine(), ("brake", vec![0x7E9, 0x7E1, 0x7EA, 0x7E0])]).is_err()
+        );
+    }
+}
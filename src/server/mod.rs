@@ -1,26 +1,58 @@
 mod context;
+mod did;
+mod dtc;
+mod host;
+mod request;
+mod routine;
 mod service;
 mod session;
+mod storage;
+mod tester;
 mod util;
 
-use crate::{constants::LOG_TAG_SERVER, server::session::SessionManager, DoCanError, SecurityAlgo};
-use iso14229_1::{response::SessionTiming, Configuration, DataIdentifier};
+pub use did::{DidCallback, DidSource};
+pub use host::{DoCanHost, EcuConfig, HostConfig, SharedDevice};
+pub use routine::Routine;
+pub use session::SessionEvent;
+pub use storage::{FileStorage, Storage};
+
+use crate::{
+    constants::{LOG_TAG_SERVER, S3_SERVER_MS, SERVER_WAIT_MS},
+    server::{
+        request::{AddressListener, RequestState},
+        session::SessionManager,
+        tester::{Tester, TesterLock},
+    },
+    DoCanError, DoCanResult, ScalingRecord, SecurityAlgo,
+};
+use iso14229_1::{
+    response::SessionTiming, utils::U24, CheckProgrammingDependencies, Configuration,
+    DataIdentifier, EraseMemory, RoutineId,
+};
 use rsutil::types::ByteOrder;
-use serde::{Deserialize, Deserializer};
-use std::collections::HashMap;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
 
 use iso14229_1::{
-    request::Request,
     response::{Code, Response},
-    Iso14229Error, Service,
+    Iso14229Error, Service, SessionType,
 };
 use iso15765_2::{
     can::{Address, AddressType, CanIsoTp},
     IsoTp, IsoTpError,
 };
 use rs_can::{CanDevice, CanFrame};
-use std::{fmt::Display, sync::Arc};
-use tokio::{spawn, task::JoinHandle};
+use std::{fmt::Display, pin::pin, sync::Arc};
+use tokio::{
+    spawn,
+    sync::{broadcast, Mutex},
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
 
 pub type DidSaLevel = HashMap<DataIdentifier, u8>;
 
@@ -38,38 +70,614 @@ where
     Ok(res)
 }
 
+/// The access rule of DID, the access is granted by one of the unlocked security levels or authenticated roles.
+#[allow(unused)]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DidAccessRule {
+    /// the allowed sessions, all sessions are allowed if empty
+    pub(crate) sessions: Vec<u8>,
+    /// no security is required if both `levels` and `roles` are empty
+    pub(crate) levels: Vec<u8>,
+    /// the roles of Authentication(0x29)
+    pub(crate) roles: Vec<Vec<u8>>,
+}
+
+/// The access policy of DID, the DID is not readable or writable if the rule is absent.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct DidAccessConfig {
+    /// the rule of ReadDID and the source DID of DynamicallyDefineDID
+    #[serde(default)]
+    pub(crate) read: Option<DidAccessRule>,
+    /// the rule of WriteDID and IOCtrl
+    #[serde(default)]
+    pub(crate) write: Option<DidAccessRule>,
+}
+
+pub type DidAccess = HashMap<DataIdentifier, DidAccessConfig>;
+
+fn did_access_deserialize<'de, D>(deserializer: D) -> Result<DidAccess, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw_map: HashMap<u16, DidAccessConfig> = HashMap::deserialize(deserializer)?;
+
+    let res = raw_map
+        .into_iter()
+        .map(|(k, v)| (DataIdentifier::from(k), v))
+        .collect::<HashMap<_, _>>();
+
+    Ok(res)
+}
+
+/// The diagnostic session of DiagnosticSessionControl.
+#[allow(unused)]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// the sessions which can be entered from this session
+    pub(crate) transitions: Vec<u8>,
+    /// the server is reset when the session is exited, e.g. the programming session
+    pub(crate) reset_on_exit: bool,
+    /// the timing of session, the active timing is used if absent
+    pub(crate) timing: Option<SessionTiming>,
+}
+
+impl SessionConfig {
+    /// The built-in sessions, the programming session is entered from the extended session
+    /// and exited to the default session only.
+    pub(crate) fn defaults() -> HashMap<u8, Self> {
+        let session = |transitions: &[SessionType], reset_on_exit| Self {
+            transitions: transitions.iter().map(|&v| v.into()).collect(),
+            reset_on_exit,
+            timing: None,
+        };
+
+        HashMap::from([
+            (
+                SessionType::Default.into(),
+                session(
+                    &[
+                        SessionType::Default,
+                        SessionType::Extended,
+                        SessionType::SafetySystemDiagnostic,
+                    ],
+                    false,
+                ),
+            ),
+            (
+                SessionType::Programming.into(),
+                session(&[SessionType::Default, SessionType::Programming], true),
+            ),
+            (
+                SessionType::Extended.into(),
+                session(
+                    &[
+                        SessionType::Default,
+                        SessionType::Programming,
+                        SessionType::Extended,
+                        SessionType::SafetySystemDiagnostic,
+                    ],
+                    false,
+                ),
+            ),
+            (
+                SessionType::SafetySystemDiagnostic.into(),
+                session(
+                    &[
+                        SessionType::Default,
+                        SessionType::Extended,
+                        SessionType::SafetySystemDiagnostic,
+                    ],
+                    false,
+                ),
+            ),
+        ])
+    }
+}
+
+/// The security level of SecurityAccess.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SecurityLevelConfig {
+    /// the length of random seed, `seed_len` is used if absent
+    pub(crate) seed_len: Option<usize>,
+    /// the salt of algorithm, `sa_salt` is used if absent
+    pub(crate) salt: Option<Vec<u8>>,
+    /// the static seed for reproducible tests, the seed is random if absent
+    pub(crate) seed: Option<Vec<u8>>,
+    /// the allowed sessions, all sessions are allowed if empty
+    pub(crate) sessions: Vec<u8>,
+    /// the invalid keys before `ExceedNumberOfAttempts`, the counter is reset by a valid key
+    pub(crate) max_attempts: u8,
+    /// the delay after the attempts are exceeded
+    pub(crate) delay_ms: u64,
+    /// the delay is armed at power-up and ECU reset
+    pub(crate) power_up_delay: bool,
+}
+
+impl Default for SecurityLevelConfig {
+    fn default() -> Self {
+        Self {
+            seed_len: None,
+            salt: None,
+            seed: None,
+            sessions: vec![],
+            max_attempts: 3,
+            delay_ms: 10_000,
+            power_up_delay: false,
+        }
+    }
+}
+
+/// The access rule of sub-function.
+#[allow(unused)]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SubFunctionAccessConfig {
+    /// the allowed sessions, all sessions are allowed if empty
+    pub(crate) sessions: Vec<u8>,
+    /// the security levels, no security is required if empty
+    pub(crate) levels: Vec<u8>,
+}
+
+/// The access rule of service, it's checked before the request is dispatched.
+#[allow(unused)]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServiceAccessConfig {
+    /// the allowed sessions, all sessions are allowed if empty
+    pub(crate) sessions: Vec<u8>,
+    /// the security levels, no security is required if empty
+    pub(crate) levels: Vec<u8>,
+    /// the rules of sub-functions(without suppressPosRspMsgIndicationBit)
+    pub(crate) sub_functions: HashMap<u8, SubFunctionAccessConfig>,
+}
+
+impl ServiceAccessConfig {
+    /// The built-in rules, the service is not allowed in the default session.
+    pub(crate) fn defaults(extend_sa_level: u8) -> HashMap<u8, Self> {
+        let non_default = || Self {
+            sessions: (0x02..=0x7F).collect(),
+            ..Default::default()
+        };
+
+        let mut res = [
+            Service::CommunicationCtrl,
+            Service::ReadDataByPeriodId,
+            Service::IOCtrl,
+            Service::RequestDownload,
+            Service::RequestUpload,
+            Service::TransferData,
+            Service::RequestTransferExit,
+            #[cfg(any(feature = "std2013", feature = "std2020"))]
+            Service::RequestFileTransfer,
+            #[cfg(any(feature = "std2006", feature = "std2013"))]
+            Service::AccessTimingParam,
+            Service::CtrlDTCSetting,
+            Service::LinkCtrl,
+        ]
+        .into_iter()
+        .map(|service| (service.into(), non_default()))
+        .collect::<HashMap<_, _>>();
+        res.insert(
+            Service::WriteMemByAddr.into(),
+            Self {
+                sessions: vec![SessionType::Extended.into()],
+                levels: vec![extend_sa_level],
+                ..Default::default()
+            },
+        );
+
+        res
+    }
+}
+
+/// The access rule of routine.
+#[allow(unused)]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RoutineAccessConfig {
+    /// the allowed sessions, all sessions are allowed if empty
+    pub(crate) sessions: Vec<u8>,
+    /// the security levels, no security is required if empty
+    pub(crate) levels: Vec<u8>,
+}
+
+impl RoutineAccessConfig {
+    /// The built-in rules, the standard routines of programming are allowed in the programming session.
+    pub(crate) fn defaults(program_sa_level: u8) -> HashMap<u16, Self> {
+        HashMap::from([
+            (
+                EraseMemory.into(),
+                Self {
+                    sessions: vec![SessionType::Programming.into()],
+                    levels: vec![program_sa_level],
+                },
+            ),
+            (
+                CheckProgrammingDependencies.into(),
+                Self {
+                    sessions: vec![SessionType::Programming.into()],
+                    levels: vec![],
+                },
+            ),
+        ])
+    }
+}
+
+pub type DidValues = HashMap<DataIdentifier, DidSource>;
+
+fn did_values_deserialize<'de, D>(deserializer: D) -> Result<DidValues, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw_map: HashMap<u16, DidSource> = HashMap::deserialize(deserializer)?;
+
+    let res = raw_map
+        .into_iter()
+        .map(|(k, v)| (DataIdentifier::from(k), v))
+        .collect::<HashMap<_, _>>();
+
+    Ok(res)
+}
+
+pub type DidScaling = HashMap<DataIdentifier, Vec<ScalingRecord>>;
+
+fn did_scaling_deserialize<'de, D>(deserializer: D) -> Result<DidScaling, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw_map: HashMap<u16, Vec<ScalingRecord>> = HashMap::deserialize(deserializer)?;
+
+    let res = raw_map
+        .into_iter()
+        .map(|(k, v)| (DataIdentifier::from(k), v))
+        .collect::<HashMap<_, _>>();
+
+    Ok(res)
+}
+
+/// The sandbox of RequestFileTransfer.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileTransferConfig {
+    /// all file paths of request are resolved under this directory
+    pub(crate) root: PathBuf,
+    /// maxNumberOfBlockLength of response(include SID and blockSequenceCounter)
+    pub(crate) max_block_len: u16,
+}
+
+impl FileTransferConfig {
+    /// the max data length of one TransferData
+    #[inline(always)]
+    pub(crate) fn block_data_len(&self) -> usize {
+        (self.max_block_len as usize).saturating_sub(2).max(1)
+    }
+}
+
+/// The PKI of Authentication(APCE), the certificates are PEM or DER encoded.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthenticationConfig {
+    /// the certificates of client must be issued by this certificate
+    pub(crate) trust_anchor: PathBuf,
+    /// the certificate of server, bidirectional authentication is not supported if absent
+    #[serde(default)]
+    pub(crate) certificate: Option<PathBuf>,
+    /// the PKCS#8 PEM private key of server certificate
+    #[serde(default)]
+    pub(crate) private_key: Option<PathBuf>,
+    /// the certificate extension that contains the role of client, required if present
+    #[serde(default)]
+    pub(crate) role_oid: Option<String>,
+    #[serde(default = "challenge_len_default")]
+    pub(crate) challenge_len: usize,
+}
+
+#[inline(always)]
+fn challenge_len_default() -> usize {
+    32
+}
+
+/// The snapshot(freeze frame) records of DTC.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct DtcSnapshotConfig {
+    /// the DIDs captured in each snapshot record, the data length is defined by `cfg.did`
+    #[serde(deserialize_with = "did_list_deserialize")]
+    pub(crate) dids: Vec<DataIdentifier>,
+    /// the record 0x01 is captured at the first confirmation,
+    /// the last record is overwritten by the most recent confirmation
+    #[serde(default = "snapshot_records_default")]
+    pub(crate) max_records: u8,
+}
+
+#[inline(always)]
+fn snapshot_records_default() -> u8 {
+    2
+}
+
+fn did_list_deserialize<'de, D>(deserializer: D) -> Result<Vec<DataIdentifier>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw_list: Vec<u16> = Vec::deserialize(deserializer)?;
+
+    Ok(raw_list.into_iter().map(DataIdentifier::from).collect())
+}
+
+/// The WWH-OBD information of DTC.
+#[allow(unused)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WwhObdConfig {
+    pub(crate) func_gid: u8,
+    /// DTCFormatIdentifier
+    pub(crate) fid: u8,
+}
+
+/// The preconfigured DTC of fault memory.
+#[allow(unused)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DtcConfig {
+    pub(crate) dtc: u32,
+    #[serde(default = "dtc_status_default")]
+    pub(crate) status: u8,
+    #[serde(default)]
+    pub(crate) severity: u8,
+    #[serde(default)]
+    pub(crate) func_unit: u8,
+    #[serde(default)]
+    pub(crate) fault_counter: u8,
+    #[serde(default)]
+    pub(crate) permanent: bool,
+    /// extended data records, the data length is defined by `cfg.dtc`
+    #[serde(default)]
+    pub(crate) ext_data: BTreeMap<u8, Vec<u8>>,
+    /// snapshot records of DID data, the data length is defined by `cfg.did`
+    #[serde(default)]
+    pub(crate) snapshots: BTreeMap<u8, BTreeMap<u16, Vec<u8>>>,
+    /// the MemorySelection of user defined memory, the DTC is stored in primary memory if absent
+    #[serde(default)]
+    pub(crate) user_memory: Option<u8>,
+    #[serde(default)]
+    pub(crate) mirror: bool,
+    #[serde(default)]
+    pub(crate) emissions_obd: bool,
+    #[serde(default)]
+    pub(crate) wwh_obd: Option<WwhObdConfig>,
+}
+
+/// testNotCompletedSinceLastClear and testNotCompletedThisOperationCycle
+#[inline(always)]
+fn dtc_status_default() -> u8 {
+    0x50
+}
+
+/// The non-volatile memory of server.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct StorageConfig {
+    /// the JSON file of persisted state
+    pub(crate) path: PathBuf,
+    /// the non-volatile DIDs
+    #[serde(default, deserialize_with = "did_list_deserialize")]
+    pub(crate) dids: Vec<DataIdentifier>,
+    /// the state is flushed periodically if not 0, otherwise it's flushed on change
+    #[serde(default)]
+    pub(crate) flush_interval_ms: u64,
+}
+
+fn debounce_step_deserialize<'de, D>(deserializer: D) -> Result<u8, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // the result is never qualified if the counter doesn't move
+    match u8::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom(
+            "the debounce_step must be greater than 0",
+        )),
+        v => Ok(v),
+    }
+}
+
+/// The fault monitor of DTC.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DtcMonitorConfig {
+    /// the fault detection counter step of each test result,
+    /// the result is qualified when the counter reaches 127(failed) or -128(passed)
+    #[serde(deserialize_with = "debounce_step_deserialize")]
+    pub(crate) debounce_step: u8,
+    /// the operation cycles with failed result to confirm the DTC
+    pub(crate) confirm_cycles: u8,
+    /// the operation cycles without failed result to clear the confirmed DTC, 0 means never
+    pub(crate) aging_cycles: u8,
+    /// request the warning indicator when the DTC is confirmed
+    pub(crate) warning_indicator: bool,
+}
+
+impl Default for DtcMonitorConfig {
+    fn default() -> Self {
+        Self {
+            debounce_step: 0x80,
+            confirm_cycles: 1,
+            aging_cycles: 40,
+            warning_indicator: false,
+        }
+    }
+}
+
+/// The RequestCorrectlyReceivedResponsePending(0x78) of long-running service.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ResponsePendingConfig {
+    /// the first 0x78 is sent when the elapsed time reaches the percent of P2
+    pub(crate) p2_percent: u8,
+    /// the 0x78 is repeated in the percent of P2* until the final response
+    pub(crate) p2_star_percent: u8,
+    /// the simulated processing time(ms) of services by SID
+    pub(crate) delays: HashMap<u8, u64>,
+}
+
+impl Default for ResponsePendingConfig {
+    fn default() -> Self {
+        Self {
+            p2_percent: 80,
+            p2_star_percent: 50,
+            delays: Default::default(),
+        }
+    }
+}
+
+/// The physical address of additional tester.
+#[allow(unused)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct TesterConfig {
+    /// the response id of server
+    pub(crate) tx_id: u32,
+    /// the request id of tester
+    pub(crate) rx_id: u32,
+}
+
+/// The state of concurrent testers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TesterMode {
+    /// each tester has its own session, security level and transfer state
+    #[default]
+    Isolated,
+    /// the testers share the state, the request of other tester is rejected
+    /// with `ConditionsNotCorrect` while one tester is in non-default session
+    Exclusive,
+}
+
 #[allow(unused)]
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
+    /// the address of primary tester, the functional request is processed in the state of this tester
     pub(crate) address: Address,
+    /// the additional testers which are served concurrently, functional request is not supported
+    #[serde(default)]
+    pub(crate) testers: Vec<TesterConfig>,
+    #[serde(default)]
+    pub(crate) tester_mode: TesterMode,
+    /// the P2 and P2* of the session without timing
     pub(crate) timing: SessionTiming,
+    /// the S3 timer of non-default session
+    #[serde(default = "s3_ms_default")]
+    pub(crate) s3_ms: u64,
+    #[serde(default)]
+    pub(crate) response_pending: ResponsePendingConfig,
     /// extend session security access level
     pub(crate) extend_sa_level: u8,
     /// program session security access level
     pub(crate) program_sa_level: u8,
     pub(crate) seed_len: usize,
     pub(crate) sa_salt: Vec<u8>,
+    /// the security levels by requestSeed sub-function, all levels are supported with defaults if empty
+    #[serde(default)]
+    pub(crate) security_levels: HashMap<u8, SecurityLevelConfig>,
     pub(crate) cfg: Configuration,
     #[serde(deserialize_with = "did_sa_level_deserialize")]
     pub(crate) did_sa_level: DidSaLevel,
+    /// the diagnostic sessions and transitions, the built-in sessions are used if empty
+    #[serde(default)]
+    pub(crate) sessions: HashMap<u8, SessionConfig>,
+    /// the access rules of services by SID, the built-in rule is used if the service is absent
+    #[serde(default)]
+    pub(crate) service_access: HashMap<u8, ServiceAccessConfig>,
+    /// the access rules of routines by id, the built-in rule is used if the routine is absent
+    #[serde(default)]
+    pub(crate) routines: HashMap<u16, RoutineAccessConfig>,
+    /// the access policies of DIDs, the DID without policy is checked by `did_sa_level`
+    #[serde(default, deserialize_with = "did_access_deserialize")]
+    pub(crate) did_access: DidAccess,
+    /// the initial values and data sources of DIDs, the value is overridden by non-volatile memory
+    #[serde(default, deserialize_with = "did_values_deserialize")]
+    pub(crate) did_values: DidValues,
+    /// scaling records of service 24
+    #[serde(default, deserialize_with = "did_scaling_deserialize")]
+    pub(crate) did_scaling: DidScaling,
+    /// RequestFileTransfer is not supported if absent
+    #[serde(default)]
+    pub(crate) file_transfer: Option<FileTransferConfig>,
+    /// Authentication(0x29) is not supported if absent
+    #[serde(default)]
+    pub(crate) authentication: Option<AuthenticationConfig>,
+    /// DTC snapshot is not captured if absent
+    #[serde(default)]
+    pub(crate) dtc_snapshot: Option<DtcSnapshotConfig>,
+    #[serde(default)]
+    pub(crate) dtc_monitor: DtcMonitorConfig,
+    /// the fault memory at startup and ECU reset
+    #[serde(default)]
+    pub(crate) dtcs: Vec<DtcConfig>,
+    /// the state is not persisted if absent
+    #[serde(default)]
+    pub(crate) storage: Option<StorageConfig>,
     pub(crate) byte_order: ByteOrder,
 }
 
+#[inline(always)]
+fn s3_ms_default() -> u64 {
+    S3_SERVER_MS
+}
+
+impl Config {
+    /// The physical CAN ids of server and all testers.
+    pub(crate) fn physical_ids(&self) -> Vec<u32> {
+        let mut res = vec![self.address.tx_id, self.address.rx_id];
+        res.extend(self.testers.iter().flat_map(|v| [v.tx_id, v.rx_id]));
+
+        res
+    }
+}
+
 #[async_trait::async_trait]
 pub trait Server {
     async fn update_address(&self, address: Address);
     async fn update_security_algo(&self, algo: SecurityAlgo);
+    /// Set the algorithm of the security level(requestSeed sub-function) instead of the common algorithm.
+    async fn update_level_security_algo(&self, level: u8, algo: SecurityAlgo);
+    /// Report the test result of DTC to the fault monitor, return `false` if the DTC setting is off
+    /// or the DTC is not preconfigured in `dtcs`.
+    async fn report_test_result(&self, dtc: U24, passed: bool) -> bool;
+    async fn start_operation_cycle(&self);
+    async fn end_operation_cycle(&self);
+    /// Replace the persistence backend, the non-volatile memory is reloaded from it.
+    async fn update_storage(&self, storage: Arc<dyn Storage>) -> DoCanResult<()>;
+    /// Replace the value or data source of DID, the DID must be defined in `cfg.did`.
+    async fn update_did_source(&self, did: DataIdentifier, source: DidSource) -> DoCanResult<()>;
+    /// Register the handler of routine, the built-in routine of the same id is overridden.
+    async fn update_routine(&self, routine_id: RoutineId, routine: Arc<dyn Routine>);
+    /// Subscribe the session events, e.g. the session is exited because of S3 timeout.
+    fn session_events(&self) -> broadcast::Receiver<SessionEvent>;
     async fn service_forever(&mut self, interval_us: u64);
 
     async fn service_stop(&mut self);
 }
 
 #[derive(Clone)]
-pub struct DoCanServer<D, C, F> {
-    isotp: CanIsoTp<D, C, F>,
+pub struct DoCanServer<D: CanDevice, C, F> {
+    isotp: CanIsoTp<SharedDevice<D>, C, F>,
+    listener: AddressListener<C>,
+    /// the request in processing
+    request: RequestState,
+    /// serialize the processing of request and the serviceToRespondTo of ResponseOnEvent
+    dispatching: Arc<Mutex<()>>,
+    tester: Tester,
+    /// the servers of additional testers
+    testers: Vec<Self>,
     session: SessionManager,
     context: context::Context,
     handles: Vec<Arc<JoinHandle<()>>>,
+    /// the response is captured instead of transmitted when processing the internal request of SecuredDataTransmission
+    capture: Option<Arc<Mutex<Option<Vec<u8>>>>>,
 }
 
 impl<D, C, F> DoCanServer<D, C, F>
@@ -80,129 +688,306 @@ where
 {
     pub async fn new(device: D, channel: C) -> Result<Self, DoCanError> {
         let context = context::Context::new().await?;
-        Ok(Self {
-            isotp: CanIsoTp::new(device, channel, context.config.address, true).await,
-            session: SessionManager::new(None),
+        Ok(Self::with_context(SharedDevice::new(device), channel, context).await)
+    }
+
+    pub(crate) async fn with_context(
+        device: SharedDevice<D>,
+        channel: C,
+        context: context::Context,
+    ) -> Self {
+        let address = context.config.address;
+        let session = SessionManager::new(Duration::from_millis(context.config.s3_ms));
+        let lock = match context.config.tester_mode {
+            TesterMode::Isolated => None,
+            TesterMode::Exclusive => Some(TesterLock::default()),
+        };
+
+        let mut testers = Vec::with_capacity(context.config.testers.len());
+        for (index, tester) in context.config.testers.iter().enumerate() {
+            // the request id of tester is used as the functional id to receive physical request only
+            let address = Address {
+                tx_id: tester.tx_id,
+                rx_id: tester.rx_id,
+                fid: tester.rx_id,
+            };
+            let (session, context) = match lock {
+                Some(_) => (session.clone(), context.clone()),
+                None => (SessionManager::new(session.duration), context.for_tester()),
+            };
+            let tester = Tester {
+                index: index + 1,
+                lock: lock.clone(),
+            };
+            let server = Self::with_address(
+                device.handle(),
+                channel.clone(),
+                address,
+                tester,
+                session,
+                context,
+            )
+            .await;
+            testers.push(server);
+        }
+
+        let tester = Tester { index: 0, lock };
+        let mut server =
+            Self::with_address(device, channel, address, tester, session, context).await;
+        server.testers = testers;
+
+        server
+    }
+
+    async fn with_address(
+        device: SharedDevice<D>,
+        channel: C,
+        address: Address,
+        tester: Tester,
+        session: SessionManager,
+        context: context::Context,
+    ) -> Self {
+        let isotp = CanIsoTp::new(device, channel.clone(), address, true).await;
+        let listener = AddressListener::new(channel.clone(), address);
+        isotp
+            .register_listener(
+                format!("DoCAN-Server-{}", channel),
+                Box::new(listener.clone()),
+            )
+            .await;
+        Self {
+            isotp,
+            listener,
+            request: Default::default(),
+            dispatching: Default::default(),
+            tester,
+            testers: Default::default(),
+            session,
             context,
             handles: Default::default(),
-        })
+            capture: None,
+        }
+    }
+
+    /// Start the transport layer and request processing of tester,
+    /// the S3 timer and ResponseOnEvent are started if the session and context are owned by tester.
+    async fn start_tester(&mut self, interval_us: u64, owned: bool) {
+        self.isotp.start(interval_us).await;
+        let mut clone = self.clone();
+        let handle = spawn(async move { clone.server().await });
+        self.handles.push(Arc::new(handle));
+        if !owned {
+            return;
+        }
+
+        let session = self.session.clone();
+        let handle = spawn(async move { session.work().await });
+        self.handles.push(Arc::new(handle));
+        let mut events = self.session.subscribe();
+        let context = self.context.clone();
+        let handle = spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(SessionEvent::Timeout { session }) => {
+                        rsutil::info!("{} session: {:?} is timeout", LOG_TAG_SERVER, session);
+                        context.exit_session(session).await;
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        self.handles.push(Arc::new(handle));
+        let mut roe = self.clone();
+        let handle = spawn(async move { roe.response_on_event_forever().await });
+        self.handles.push(Arc::new(handle));
     }
 
     #[inline(always)]
-    pub fn tp_layer(&mut self) -> CanIsoTp<D, C, F> {
+    pub fn tp_layer(&mut self) -> CanIsoTp<SharedDevice<D>, C, F> {
         self.isotp.clone()
     }
 
     async fn server(&mut self) {
         loop {
-            let timing = self.context.get_active_timing().await;
-            let cfg = self.context.get_cfg().clone();
-            if let Ok(data) = self.isotp.wait_data(timing.p2_ms()).await {
+            if let Ok(data) = self.isotp.wait_data(SERVER_WAIT_MS).await {
+                let dispatching = self.dispatching.clone();
+                let _guard = dispatching.lock().await;
+                self.request = RequestState::new(self.listener.received().await);
                 // rsutil::info!("{} Received data: {}", LOG_TAG_SERVER, hex::encode(&data));
-                match data.len() {
-                    0 => {}
-                    _ => match Service::try_from(data[0]) {
-                        Ok(service) => match Request::try_from((service, &data[1..], &cfg)) {
-                            Ok(req) => {
-                                if let Err(e) = match service {
-                                    Service::SessionCtrl => {
-                                        self.session_ctrl(req, &cfg, timing.into()).await
-                                    }
-                                    Service::ECUReset => self.ecu_reset(req, &cfg).await,
-                                    Service::ClearDiagnosticInfo => {
-                                        self.clear_diagnostic_info(req, &cfg).await
-                                    }
-                                    Service::ReadDTCInfo => self.read_dtc_info(req, &cfg).await,
-                                    Service::ReadDID => self.read_did(req, &cfg).await,
-                                    Service::ReadMemByAddr => {
-                                        self.read_mem_by_addr(req, &cfg).await
-                                    }
-                                    Service::ReadScalingDID => {
-                                        self.read_scaling_did(req, &cfg).await
-                                    }
-                                    Service::SecurityAccess => {
-                                        self.security_access(req, &cfg).await
-                                    }
-                                    Service::CommunicationCtrl => {
-                                        self.communication_ctrl(req, &cfg).await
-                                    }
-                                    #[cfg(any(feature = "std2020"))]
-                                    Service::Authentication => self.authentication(req, &cfg).await,
-                                    Service::ReadDataByPeriodId => {
-                                        self.read_data_by_pid(req, &cfg).await
-                                    }
-                                    Service::DynamicalDefineDID => {
-                                        self.dynamically_define_did(req, &cfg).await
-                                    }
-                                    Service::WriteDID => self.write_did(req, &cfg).await,
-                                    Service::IOCtrl => self.io_ctrl(req, &cfg).await,
-                                    Service::RoutineCtrl => self.routine_ctrl(req, &cfg).await,
-                                    Service::RequestDownload => {
-                                        self.request_download(req, &cfg).await
-                                    }
-                                    Service::RequestUpload => self.request_upload(req, &cfg).await,
-                                    Service::TransferData => self.transfer_data(req, &cfg).await,
-                                    Service::RequestTransferExit => {
-                                        self.request_transfer_exit(req, &cfg).await
-                                    }
-                                    #[cfg(any(feature = "std2013", feature = "std2020"))]
-                                    Service::RequestFileTransfer => {
-                                        self.request_file_transfer(req, &cfg).await
-                                    }
-                                    Service::WriteMemByAddr => {
-                                        self.write_mem_by_addr(req, &cfg).await
-                                    }
-                                    Service::TesterPresent => self.tester_present(req, &cfg).await,
-                                    #[cfg(any(feature = "std2006", feature = "std2013"))]
-                                    Service::AccessTimingParam => {
-                                        self.access_timing_parameter(req, &cfg).await
-                                    }
-                                    Service::SecuredDataTrans => {
-                                        self.secured_data_trans(req, &cfg).await
-                                    }
-                                    Service::CtrlDTCSetting => {
-                                        self.ctrl_dtc_setting(req, &cfg).await
-                                    }
-                                    Service::ResponseOnEvent => {
-                                        self.response_on_event(req, &cfg).await
-                                    }
-                                    Service::LinkCtrl => self.link_ctrl(req, &cfg).await,
-                                    Service::NRC => {
-                                        self.negative_service(
-                                            Service::NRC.into(),
-                                            Code::ServiceNotSupported,
-                                        )
-                                        .await;
-                                        Ok(())
-                                    }
-                                } {
-                                    self.process_uds_error(service, e).await;
-                                }
-                            }
-                            Err(e) => {
-                                rsutil::warn!(
-                                    "{} error: {} when data: {} to request",
-                                    LOG_TAG_SERVER,
-                                    e,
-                                    hex::encode(&data)
-                                );
-                                self.process_uds_error(service, e).await;
-                            }
-                        },
-                        Err(_) => {
-                            // can't parse service
-                            self.negative_service(data[0], Code::ServiceNotSupported)
-                                .await
-                        }
-                    },
+                self.process_request(&data).await;
+            }
+        }
+    }
+
+    /// Process the request data(include SID) and transmit the response.
+    ///
+    /// The RequestCorrectlyReceivedResponsePending is transmitted before P2 is expired
+    /// and repeated within P2* until the service is finished.
+    pub(crate) async fn process_request(&mut self, data: &[u8]) {
+        let Some(&service) = data.first() else {
+            return;
+        };
+        if self.capture.is_some() {
+            // the internal request of SecuredDataTransmission is answered in the outer response
+            return self.dispatch_request(data).await;
+        }
+
+        let timing = self.context.get_active_timing().await;
+        let mut server = self.clone();
+        let mut task = pin!(async move { server.dispatch_request(data).await });
+
+        let mut wait = self.context.pending_interval(timing, true);
+        while timeout(wait, &mut task).await.is_err() {
+            self.negative_service(service, Code::RequestCorrectlyReceivedResponsePending)
+                .await;
+            wait = self.context.pending_interval(timing, false);
+        }
+    }
+
+    /// Acquire the tester lock and handle the request.
+    async fn dispatch_request(&mut self, data: &[u8]) {
+        if let Some(lock) = &self.tester.lock {
+            let session = self.session.get_session_type().await;
+            if !lock.acquire(self.tester.index, session).await {
+                return self
+                    .negative_service(data[0], Code::ConditionsNotCorrect)
+                    .await;
+            }
+        }
+
+        self.handle_request(data, true).await;
+
+        if let Some(lock) = &self.tester.lock {
+            let session = self.session.get_session_type().await;
+            lock.update(self.tester.index, session).await;
+        }
+    }
+
+    /// Process the serviceToRespondTo of ResponseOnEvent, it's serialized with the request of tester
+    /// and handled without the simulated processing time and RequestCorrectlyReceivedResponsePending.
+    pub(crate) async fn process_event_request(&mut self, data: &[u8]) {
+        let dispatching = self.dispatching.clone();
+        let _guard = dispatching.lock().await;
+        self.request = Default::default();
+        self.handle_request(data, false).await;
+    }
+
+    /// Evaluate the request and call the handler of service,
+    /// the simulated processing time is applied if `delay` is set.
+    async fn handle_request(&mut self, data: &[u8], delay: bool) {
+        let cfg = self.context.get_cfg().clone();
+        let session = self.session.get_session_type().await;
+        let sa_level = self.session.get_security_access_level().await;
+        let (req, suppress_positive) =
+            match request::evaluate_request(&self.context, data, session, sa_level, &cfg) {
+                Ok(v) => v,
+                Err(code) => return self.negative_service(data[0], code).await,
+            };
+        self.request.suppress_positive = suppress_positive;
+        // the simulated processing time of accepted request
+        if let Some(delay) = self
+            .context
+            .service_delay(data[0])
+            .filter(|_| delay && self.capture.is_none())
+        {
+            sleep(delay).await;
+        }
+
+        let service = req.service();
+        if let Err(e) = match service {
+            Service::SessionCtrl => self.session_ctrl(req, &cfg).await,
+            Service::ECUReset => self.ecu_reset(req, &cfg).await,
+            Service::ClearDiagnosticInfo => self.clear_diagnostic_info(req, &cfg).await,
+            Service::ReadDTCInfo => self.read_dtc_info(req, &cfg).await,
+            Service::ReadDID => self.read_did(req, &cfg).await,
+            Service::ReadMemByAddr => self.read_mem_by_addr(req, &cfg).await,
+            Service::ReadScalingDID => self.read_scaling_did(req, &cfg).await,
+            Service::SecurityAccess => self.security_access(req, &cfg).await,
+            Service::CommunicationCtrl => self.communication_ctrl(req, &cfg).await,
+            #[cfg(any(feature = "std2020"))]
+            Service::Authentication => self.authentication(req, &cfg).await,
+            Service::ReadDataByPeriodId => self.read_data_by_pid(req, &cfg).await,
+            Service::DynamicalDefineDID => self.dynamically_define_did(req, &cfg).await,
+            Service::WriteDID => self.write_did(req, &cfg).await,
+            Service::IOCtrl => self.io_ctrl(req, &cfg).await,
+            Service::RoutineCtrl => self.routine_ctrl(req, &cfg).await,
+            Service::RequestDownload => self.request_download(req, &cfg).await,
+            Service::RequestUpload => self.request_upload(req, &cfg).await,
+            Service::TransferData => self.transfer_data(req, &cfg).await,
+            Service::RequestTransferExit => self.request_transfer_exit(req, &cfg).await,
+            #[cfg(any(feature = "std2013", feature = "std2020"))]
+            Service::RequestFileTransfer => self.request_file_transfer(req, &cfg).await,
+            Service::WriteMemByAddr => self.write_mem_by_addr(req, &cfg).await,
+            Service::TesterPresent => self.tester_present(req, &cfg).await,
+            #[cfg(any(feature = "std2006", feature = "std2013"))]
+            Service::AccessTimingParam => self.access_timing_parameter(req, &cfg).await,
+            Service::SecuredDataTrans => self.secured_data_trans(req, &cfg).await,
+            Service::CtrlDTCSetting => self.ctrl_dtc_setting(req, &cfg).await,
+            Service::ResponseOnEvent => self.response_on_event(req, &cfg).await,
+            Service::LinkCtrl => self.link_ctrl(req, &cfg).await,
+            Service::NRC => {
+                self.negative_service(Service::NRC.into(), Code::ServiceNotSupported)
+                    .await;
+                Ok(())
+            }
+        } {
+            self.process_uds_error(service, e).await;
+        }
+    }
+
+    /// Process the internal request data(include SID) of SecuredDataTransmission and return the response data.
+    #[cfg(feature = "std2020")]
+    pub(crate) async fn process_secured_request(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let capture = Arc::new(Mutex::new(None));
+        let mut server = self.clone();
+        server.capture = Some(capture.clone());
+        Box::pin(server.process_request(data)).await;
+
+        let result = capture.lock().await.take();
+        result
+    }
+
+    async fn transmit(&self, data: Vec<u8>) -> Result<(), IsoTpError> {
+        match &self.capture {
+            Some(capture) => {
+                let _ = capture.lock().await.replace(data);
+                Ok(())
+            }
+            None => {
+                // the final response is never interleaved or followed by the response pending
+                let pending = RequestState::is_pending(&data);
+                let mut responded = if pending {
+                    // the final response is being transmitted by the service
+                    let Ok(responded) = self.request.responded.try_lock() else {
+                        return Ok(());
+                    };
+                    responded
+                } else {
+                    self.request.responded.lock().await
+                };
+                if pending && *responded {
+                    return Ok(());
+                }
+                if !self.request.should_transmit(&data) {
+                    rsutil::debug!(
+                        "{} response: {} is suppressed",
+                        LOG_TAG_SERVER,
+                        hex::encode(&data)
+                    );
+                    return Ok(());
                 }
+
+                *responded |= !pending;
+                // the response is always transmitted by physical address
+                self.isotp.transmit(AddressType::Physical, data).await
             }
         }
     }
 
     async fn negative_service(&self, service: u8, code: Code) {
         let data = vec![Service::NRC.into(), service, code.into()];
-        if let Err(e) = self.isotp.transmit(AddressType::Physical, data).await {
+        if let Err(e) = self.transmit(data).await {
             rsutil::error!(
                 "{} can't transmit negative response, because of: {}",
                 LOG_TAG_SERVER,
@@ -212,20 +997,8 @@ where
     }
 
     async fn process_uds_error(&self, service: Service, e: Iso14229Error) {
-        let code = match e {
-            // Iso14229Error::InvalidParam(_) => {}
-            // Iso14229Error::InvalidData(_) => {}
-            Iso14229Error::InvalidDataLength { .. } => Code::IncorrectMessageLengthOrInvalidFormat,
-            // Iso14229Error::DidNotSupported(_) => {}
-            // Iso14229Error::InvalidDynamicallyDefinedDID(_) => {}
-            // Iso14229Error::InvalidSessionData(_) => {}
-            // Iso14229Error::ReservedError(_) => {}
-            // Iso14229Error::SubFunctionError(_) => {}
-            Iso14229Error::ServiceError(_) => Code::ConditionsNotCorrect,
-            // Iso14229Error::OtherError(_) => {}
-            // Iso14229Error::NotImplement => {}
-            _ => Code::GeneralReject, // TODO
-        };
+        rsutil::warn!("{} service: {} error: {}", LOG_TAG_SERVER, service, e);
+        let code = request::error_code(&e);
         self.transmit_response(Response::new_negative(service, code), true)
             .await;
     }
@@ -233,7 +1006,7 @@ where
     pub(crate) async fn transmit_response(&self, resp: Response, flag: bool) {
         let service = resp.service();
         let data: Vec<_> = resp.into();
-        if let Err(e) = self.isotp.transmit(AddressType::Physical, data).await {
+        if let Err(e) = self.transmit(data).await {
             rsutil::warn!("{} transmit error: {:?}", LOG_TAG_SERVER, e);
             if !flag {
                 // resend negative response is no-need
@@ -273,6 +1046,7 @@ where
     #[inline(always)]
     async fn update_address(&self, address: Address) {
         self.isotp.update_address(address).await;
+        *self.listener.address.lock().await = address;
     }
 
     #[inline(always)]
@@ -280,21 +1054,798 @@ where
         self.context.set_security_algo(algo).await;
     }
 
+    async fn update_level_security_algo(&self, level: u8, algo: SecurityAlgo) {
+        self.context.set_level_security_algo(level, algo).await;
+    }
+
+    #[inline(always)]
+    async fn report_test_result(&self, dtc: U24, passed: bool) -> bool {
+        self.context.report_test_result(dtc, passed).await
+    }
+
+    #[inline(always)]
+    async fn start_operation_cycle(&self) {
+        self.context.start_operation_cycle().await;
+    }
+
+    #[inline(always)]
+    async fn end_operation_cycle(&self) {
+        self.context.end_operation_cycle().await;
+    }
+
+    #[inline(always)]
+    async fn update_storage(&self, storage: Arc<dyn Storage>) -> DoCanResult<()> {
+        self.context.set_storage(storage).await
+    }
+
+    async fn update_did_source(&self, did: DataIdentifier, source: DidSource) -> DoCanResult<()> {
+        self.context.set_did_source(did, source).await
+    }
+
+    #[inline(always)]
+    async fn update_routine(&self, routine_id: RoutineId, routine: Arc<dyn Routine>) {
+        self.context.set_routine(routine_id, routine).await;
+    }
+
+    #[inline(always)]
+    fn session_events(&self) -> broadcast::Receiver<SessionEvent> {
+        self.session.subscribe()
+    }
+
     async fn service_forever(&mut self, interval_us: u64) {
-        self.isotp.start(interval_us).await;
-        let mut clone = self.clone();
-        let session = self.session.clone();
-        let handle = spawn(async move { session.work().await });
-        self.handles.push(Arc::new(handle));
-        let handle = spawn(async move { clone.server().await });
-        self.handles.push(Arc::new(handle));
+        self.start_tester(interval_us, true).await;
+        let owned = self.tester.lock.is_none();
+        for tester in &mut self.testers {
+            tester.start_tester(interval_us, owned).await;
+        }
+        if let Some(interval_ms) = self
+            .context
+            .config
+            .storage
+            .as_ref()
+            .map(|v| v.flush_interval_ms)
+            .filter(|v| *v > 0)
+        {
+            let context = self.context.clone();
+            let handle = spawn(async move { context.nvm_flush_forever(interval_ms).await });
+            self.handles.push(Arc::new(handle));
+        }
     }
 
     async fn service_stop(&mut self) {
+        for tester in &mut self.testers {
+            tester.isotp.stop().await;
+            tester.handles.iter().for_each(|v| v.abort());
+        }
         self.isotp.stop().await;
         for handle in &self.handles {
             handle.abort();
         }
+        self.context.flush_pending_nvm().await;
         rsutil::info!("{} stopped", LOG_TAG_SERVER);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{context::tests::test_context, host::tests::MockDevice, DoCanServer, SharedDevice};
+    use iso14229_1::{response::SessionTiming, SessionType};
+    use iso15765_2::IsoTp;
+    use rs_can::CanFrame;
+    use std::time::{Duration, Instant};
+    use tokio::time::sleep;
+    #[cfg(feature = "client")]
+    use {
+        crate::{DoCanClient, Server},
+        socketcan_rs::SocketCanFrame,
+    };
+
+    #[tokio::test]
+    async fn response_pending_is_sent_until_slow_service_is_finished() {
+        let mut ctx = test_context();
+        ctx.config.timing = SessionTiming {
+            p2: 50,
+            p2_star: 20,
+        };
+        ctx.config.response_pending.delays.insert(0x3E, 190);
+        ctx.set_session_timing(SessionType::Default).await;
+        let device = MockDevice::default();
+        let mut server =
+            DoCanServer::with_context(SharedDevice::new(device.clone()), "can0".into(), ctx).await;
+        server.isotp.start(100).await;
+
+        // 0x78 is sent at 40ms(80% of P2) and repeated in 100ms(50% of P2*)
+        server.process_request(&[0x3E, 0x00]).await;
+        // the rejected request is not delayed
+        let start = Instant::now();
+        server.process_request(&[0x3E, 0x05]).await;
+        assert!(start.elapsed() < Duration::from_millis(40));
+        sleep(Duration::from_millis(20)).await;
+        server.isotp.stop().await;
+
+        let responses = device
+            .transmitted
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|v| v.data()[1..=v.data()[0] as usize].to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            responses,
+            vec![
+                vec![0x7F, 0x3E, 0x78],
+                vec![0x7F, 0x3E, 0x78],
+                vec![0x7E, 0x00],
+                vec![0x7F, 0x3E, 0x12],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn event_request_is_serialized_with_tester_request() {
+        let mut ctx = test_context();
+        ctx.config.timing = SessionTiming {
+            p2: 50,
+            p2_star: 20,
+        };
+        ctx.config.response_pending.delays.insert(0x3E, 190);
+        ctx.set_session_timing(SessionType::Default).await;
+        let device = MockDevice::default();
+        let mut server =
+            DoCanServer::with_context(SharedDevice::new(device.clone()), "can0".into(), ctx).await;
+        server.isotp.start(100).await;
+
+        // the event request waits for the request of tester
+        let guard = server.dispatching.clone().lock_owned().await;
+        let mut clone = server.clone();
+        let handle = tokio::spawn(async move { clone.process_event_request(&[0x3E, 0x00]).await });
+        sleep(Duration::from_millis(20)).await;
+        assert!(device.transmitted.lock().unwrap().is_empty());
+
+        // the service delay and response pending are not applied to the event request
+        let start = Instant::now();
+        drop(guard);
+        handle.await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(40));
+        sleep(Duration::from_millis(20)).await;
+        server.isotp.stop().await;
+
+        let responses = device
+            .transmitted
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|v| v.data()[1..=v.data()[0] as usize].to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(responses, vec![vec![0x7E, 0x00]]);
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[tokio::test]
+    async fn file_transfer_rejects_reserved_mode_of_operation() {
+        let device = MockDevice::default();
+        let mut server = DoCanServer::with_context(
+            SharedDevice::new(device.clone()),
+            "can0".into(),
+            test_context(),
+        )
+        .await;
+        server.isotp.start(100).await;
+
+        // 0x81 isn't AddFile with suppressPosRspMsgIndicationBit
+        server
+            .process_request(&[0x38, 0x81, 0x00, 0x01, 0x61, 0x00, 0x01, 0x01, 0x01])
+            .await;
+        server
+            .process_request(&[0x38, 0x07, 0x00, 0x01, 0x61])
+            .await;
+        sleep(Duration::from_millis(20)).await;
+        server.isotp.stop().await;
+
+        let responses = device
+            .transmitted
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|v| v.data()[1..=v.data()[0] as usize].to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            responses,
+            vec![vec![0x7F, 0x38, 0x31], vec![0x7F, 0x38, 0x31]]
+        );
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[tokio::test]
+    async fn file_transfer_delete_file_responds_mode_of_operation_only() {
+        let (ctx, root) = super::context::tests::file_transfer_context("delete");
+        std::fs::write(root.join("a"), [0x01]).unwrap();
+        let device = MockDevice::default();
+        let mut server =
+            DoCanServer::with_context(SharedDevice::new(device.clone()), "can0".into(), ctx).await;
+        server.isotp.start(100).await;
+
+        server
+            .process_request(&[0x38, 0x02, 0x00, 0x01, 0x61])
+            .await;
+        sleep(Duration::from_millis(20)).await;
+        server.isotp.stop().await;
+
+        let responses = device
+            .transmitted
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|v| v.data()[1..=v.data()[0] as usize].to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(responses, vec![vec![0x78, 0x02]]);
+        assert!(!root.join("a").exists());
+    }
+
+    /// The server which is paired with the tester.
+    #[cfg(feature = "client")]
+    struct PairedServer {
+        server: DoCanServer<MockDevice, String, SocketCanFrame>,
+        stop: tokio::sync::oneshot::Sender<()>,
+        requests: tokio::task::JoinHandle<()>,
+    }
+
+    #[cfg(feature = "client")]
+    impl PairedServer {
+        async fn stop(mut self) {
+            let _ = self.stop.send(());
+            let _ = self.requests.await;
+            self.server.service_stop().await;
+        }
+    }
+
+    /// Start the server with the context, return the device of tester which is paired with it.
+    ///
+    /// The multi-frame transmission of ISO-TP waits the flow control without yielding,
+    /// so the requests are processed on a blocking thread to not starve the tasks of runtime.
+    #[cfg(feature = "client")]
+    async fn paired_server(mut ctx: super::context::Context) -> (PairedServer, MockDevice) {
+        use iso15765_2::can::Address;
+
+        ctx.config.address = Address {
+            tx_id: 0x7E8,
+            rx_id: 0x7E0,
+            fid: 0x7DF,
+        };
+        let (device, tester) = MockDevice::pair();
+        let mut server =
+            DoCanServer::with_context(SharedDevice::new(device), "can0".into(), ctx).await;
+        server.service_forever(100).await;
+        // the request loop is the first task of server
+        server.handles.remove(0).abort();
+
+        let (stop, stopped) = tokio::sync::oneshot::channel();
+        let mut clone = server.clone();
+        let handle = tokio::runtime::Handle::current();
+        let requests = tokio::task::spawn_blocking(move || {
+            handle.block_on(async move {
+                tokio::select! {
+                    _ = clone.server() => {}
+                    _ = stopped => {}
+                }
+            })
+        });
+
+        (
+            PairedServer {
+                server,
+                stop,
+                requests,
+            },
+            tester,
+        )
+    }
+
+    #[cfg(feature = "client")]
+    async fn tester_client(tester: MockDevice) -> DoCanClient<MockDevice, String, SocketCanFrame> {
+        use iso15765_2::can::Address;
+        use rsutil::types::ByteOrder;
+
+        // the P2 is extended since the tests are run in parallel
+        let mut client = DoCanClient::new(
+            tester,
+            "can0".to_string(),
+            Address::default(),
+            ByteOrder::default(),
+            Some(500),
+        )
+        .await;
+        client.tp_layer().start(100).await;
+
+        client
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn client_unlocks_security_access_with_static_seed() {
+        use crate::{SecurityAlgo, SecurityLevelConfig, SecurityUnlock};
+
+        let key = |_: u8, seed: &[u8], _: &[u8]| Ok(Some(seed.iter().map(|v| !v).collect()));
+        let wrong_key = |_: u8, seed: &[u8], _: &[u8]| Ok(Some(seed.to_vec()));
+        let mut ctx = test_context();
+        ctx.config.security_levels.insert(
+            0x01,
+            SecurityLevelConfig {
+                seed: Some(vec![0x11, 0x22, 0x33, 0x44]),
+                max_attempts: 2,
+                delay_ms: 200,
+                ..Default::default()
+            },
+        );
+        ctx.set_security_algo(key).await;
+        let (server, tester) = paired_server(ctx).await;
+        let client = |algo: Option<SecurityAlgo>| {
+            let tester = tester.clone();
+            async move {
+                let client = tester_client(tester).await;
+                if let Some(algo) = algo {
+                    client.update_security_algo(algo).await;
+                }
+                client
+            }
+        };
+
+        // the invalid key is not sent again
+        let mut wrong = client(Some(wrong_key)).await;
+        let unlock = wrong.try_unlock_security_access(0x01, vec![], vec![], Duration::ZERO);
+        assert_eq!(unlock.await.unwrap(), SecurityUnlock::InvalidKey);
+        let unlock = wrong.try_unlock_security_access(0x01, vec![], vec![], Duration::ZERO);
+        assert_eq!(unlock.await.unwrap(), SecurityUnlock::AttemptsExceeded);
+        wrong.tp_layer().stop().await;
+
+        // the requestSeed is repeated until the delay is expired
+        let mut valid = client(Some(key)).await;
+        let unlock = valid.try_unlock_security_access(0x01, vec![], vec![], Duration::ZERO);
+        assert!(matches!(
+            unlock.await.unwrap(),
+            SecurityUnlock::DelayNotExpired { .. }
+        ));
+        let unlock = valid.try_unlock_security_access(0x01, vec![], vec![], Duration::from_secs(1));
+        match unlock.await.unwrap() {
+            SecurityUnlock::Unlocked { waited } => assert!(waited >= Duration::from_millis(100)),
+            v => panic!("unexpected result: {:?}", v),
+        }
+        valid.tp_layer().stop().await;
+
+        // the seed is zero and the algorithm is not required
+        let mut unlocked = client(None).await;
+        let unlock = unlocked.try_unlock_security_access(0x01, vec![], vec![], Duration::ZERO);
+        assert_eq!(unlock.await.unwrap(), SecurityUnlock::AlreadyUnlocked);
+        unlocked.tp_layer().stop().await;
+        server.stop().await;
+    }
+
+    #[cfg(all(feature = "client", any(feature = "std2013", feature = "std2020")))]
+    // the multi-frame transmission of ISO-TP waits the flow control without yielding
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_transfers_files_with_server() {
+        let (ctx, root) = super::context::tests::file_transfer_context("client");
+        let (server, tester) = paired_server(ctx).await;
+        let mut client = tester_client(tester).await;
+        let local = root.with_extension("local");
+        std::fs::create_dir_all(&local).unwrap();
+        let content = vec![0x01, 0x02, 0x03, 0x04, 0x05];
+        std::fs::write(local.join("app.bin"), &content).unwrap();
+
+        let mut progress = Vec::new();
+        let crc = client
+            .upload_file(local.join("app.bin"), "fw/app.bin", false, |v, total| {
+                progress.push((v, total))
+            })
+            .await
+            .unwrap();
+        assert_eq!(crc, crate::crc::crc32(&content));
+        assert_eq!(progress, vec![(2, 5), (4, 5), (5, 5)]);
+        assert_eq!(std::fs::read(root.join("fw/app.bin")).unwrap(), content);
+
+        // the interrupted upload is resumed from the file position of server
+        std::fs::write(root.join("fw/app.bin"), &content[..3]).unwrap();
+        let mut progress = Vec::new();
+        client
+            .resume_upload_file(local.join("app.bin"), "fw/app.bin", |v, total| {
+                progress.push((v, total))
+            })
+            .await
+            .unwrap();
+        assert_eq!(progress, vec![(5, 5)]);
+        assert_eq!(std::fs::read(root.join("fw/app.bin")).unwrap(), content);
+
+        let mut progress = Vec::new();
+        let crc = client
+            .download_file(
+                "fw/app.bin",
+                local.join("copy.bin"),
+                Some(crc),
+                |v, total| progress.push((v, total)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(crc, crate::crc::crc32(&content));
+        assert_eq!(progress, vec![(2, 5), (4, 5), (5, 5)]);
+        assert_eq!(std::fs::read(local.join("copy.bin")).unwrap(), content);
+
+        std::fs::write(root.join("readme"), b"").unwrap();
+        assert_eq!(
+            client.list_dir("/").await.unwrap(),
+            vec!["fw/".to_string(), "readme".to_string()]
+        );
+
+        client.tp_layer().stop().await;
+        server.stop().await;
+    }
+
+    #[cfg(all(feature = "client", any(feature = "std2013", feature = "std2020")))]
+    // the multi-frame transmission of ISO-TP waits the flow control without yielding
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_file_transfer_reports_crc_and_size_mismatch() {
+        use crate::DoCanError;
+        use iso14229_1::{response::Code, Service};
+
+        let (ctx, root) = super::context::tests::file_transfer_context("client-err");
+        let context = ctx.clone();
+        let (server, tester) = paired_server(ctx).await;
+        let mut client = tester_client(tester).await;
+        let local = root.with_extension("local");
+        std::fs::create_dir_all(&local).unwrap();
+        let content = vec![0x01, 0x02, 0x03, 0x04];
+        std::fs::write(local.join("app.bin"), &content).unwrap();
+
+        // the resumed prefix is different from the local file
+        std::fs::write(root.join("app.bin"), [0xFF, 0xFF]).unwrap();
+        let err = client
+            .resume_upload_file(local.join("app.bin"), "app.bin", |_, _| {})
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DoCanError::NRCError {
+                    service: Service::RequestTransferExit,
+                    code: Code::GeneralProgrammingFailure,
+                }
+            ),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            std::fs::read(root.join("app.bin")).unwrap(),
+            vec![0xFF, 0xFF]
+        );
+
+        std::fs::write(root.join("app.bin"), &content).unwrap();
+        let err = client
+            .download_file("app.bin", local.join("copy.bin"), Some(0), |_, _| {})
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DoCanError::UnexpectedCrc { expect: 0, actual } if actual == crate::crc::crc32(&content)
+            ),
+            "{:?}",
+            err
+        );
+
+        // the server transfers more data than the size of its response
+        std::fs::write(root.join("app.bin"), &content[..3]).unwrap();
+        let err = client
+            .download_file("app.bin", local.join("copy.bin"), None, |_, _| {
+                if let Ok(mut file) = context.file_transfer.try_lock() {
+                    if let Some(file) = file.as_mut() {
+                        file.content = vec![0x01; 8].into();
+                    }
+                }
+                if let Ok(mut meta) = context.transfer_meta.try_lock() {
+                    if let Some(meta) = meta.as_mut() {
+                        meta.max_num_of_block_len = 8;
+                    }
+                }
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DoCanError::UnexpectedFileSize {
+                    expect: 3,
+                    actual: 4
+                }
+            ),
+            "{:?}",
+            err
+        );
+
+        client.tp_layer().stop().await;
+        server.stop().await;
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_listens_event_responses_between_requests() {
+        use super::context::tests::sample_dtc;
+        use crate::EventResponse;
+        use iso14229_1::{
+            request::{self, EventTypeParameter},
+            response,
+            utils::U24,
+            DTCReportType, Service,
+        };
+        use tokio_stream::StreamExt;
+
+        let ctx = test_context();
+        ctx.replace_dtcs(vec![sample_dtc(0x112233)]).await;
+        let (server, tester) = paired_server(ctx).await;
+        let context = server.server.context.clone();
+        let mut client = tester_client(tester).await;
+
+        // ReadDTCInfo reportDTCByStatusMask when the testFailed bit is changed
+        let mut events = client
+            .listen_response_on_event(request::ResponseOnEvent {
+                window_time: 0x02,
+                param: EventTypeParameter::OnDTCStatusChange {
+                    test_failed: 0x01,
+                    service: Service::ReadDTCInfo,
+                    sub_func: 0x02,
+                    dtc_status_mask: 0x01,
+                },
+            })
+            .await
+            .unwrap();
+        client
+            .response_on_event(request::ResponseOnEvent {
+                window_time: 0x02,
+                param: EventTypeParameter::StartResponseOnEvent,
+            })
+            .await
+            .unwrap();
+
+        for passed in [false, true] {
+            // the event response is transmitted while the request of same service is pending
+            let guard = server.server.dispatching.clone().lock_owned().await;
+            assert!(context.report_test_result(U24::new(0x112233), passed).await);
+            sleep(Duration::from_millis(50)).await;
+            let request = tokio::spawn(async move {
+                let resp = client
+                    .read_dtc_info(
+                        DTCReportType::ReportNumberOfDTCByStatusMask,
+                        request::DTCInfo::ReportNumberOfDTCByStatusMask(0xFF),
+                    )
+                    .await;
+                (client, resp)
+            });
+            sleep(Duration::from_millis(50)).await;
+            drop(guard);
+
+            let (returned, resp) = request.await.unwrap();
+            client = returned;
+            assert!(matches!(
+                resp.unwrap(),
+                response::DTCInfo::ReportNumberOfDTCByStatusMask { count: 1, .. }
+            ));
+            let event = tokio::time::timeout(Duration::from_secs(1), events.next())
+                .await
+                .unwrap()
+                .unwrap();
+            match event {
+                EventResponse::Respond(resp) => {
+                    assert_eq!(resp.sub_function().map(|v| v.origin()), Some(0x02));
+                    let records = resp.data::<response::DTCInfo>(&Default::default());
+                    assert!(matches!(
+                        records.unwrap(),
+                        response::DTCInfo::ReportDTCByStatusMask { records, .. }
+                            if records.len() == usize::from(!passed)
+                    ));
+                }
+                v => panic!("unexpected event: {:?}", v),
+            }
+        }
+
+        client.stop_listen_response_on_event().await;
+        client.tp_layer().stop().await;
+        server.stop().await;
+    }
+
+    #[cfg(feature = "client")]
+    // the multi-frame transmission of ISO-TP waits the flow control without yielding
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_receives_response_transmitted_beyond_p2() {
+        use iso14229_1::DataIdentifier;
+
+        // the response pending isn't transmitted while the long response is being transmitted
+        let mut ctx = test_context();
+        let did = DataIdentifier::from(0x4200);
+        ctx.config.cfg.did.insert(did, 64);
+        ctx.did_st.lock().await.insert(did, vec![0x55; 64].into());
+        let (server, tester) = paired_server(ctx).await;
+        let mut client = tester_client(tester).await;
+        client.add_data_identifier(did, 64).await;
+
+        let resp = client.read_data_by_identifier(did, vec![]).await.unwrap();
+        assert_eq!(resp.data.did, did);
+        assert_eq!(resp.data.data, vec![0x55; 64]);
+
+        client.tp_layer().stop().await;
+        server.stop().await;
+    }
+
+    #[cfg(all(feature = "client", feature = "std2020"))]
+    fn pki_file(name: &str) -> String {
+        format!("{}/examples/pki/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[cfg(all(feature = "client", feature = "std2020"))]
+    // the multi-frame transmission of ISO-TP waits the flow control without yielding
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_authenticates_with_server() {
+        use super::context::tests::auth_context;
+        use iso14229_1::{Service, SessionType};
+        use iso15765_2::can::AddressType;
+
+        let (server, tester) = paired_server(auth_context(None)).await;
+        let mut client = tester_client(tester).await;
+        // the authentication is ended in the default session
+        client
+            .session_ctrl(SessionType::Extended, false, AddressType::Physical)
+            .await
+            .unwrap();
+
+        let session_keyinfo = client
+            .authenticate_unidirectional(pki_file("tester.pem"), pki_file("tester.key"))
+            .await
+            .unwrap();
+        assert!(!session_keyinfo.is_empty());
+        let resp = client
+            .secured_request(Service::TesterPresent, vec![0x00], true)
+            .await
+            .unwrap();
+        assert_eq!(resp.service(), Service::TesterPresent);
+
+        let session_keyinfo = client
+            .authenticate_bidirectional(
+                pki_file("tester_ed25519.pem"),
+                pki_file("tester_ed25519.key"),
+                pki_file("root.pem"),
+            )
+            .await
+            .unwrap();
+        assert!(!session_keyinfo.is_empty());
+        let resp = client
+            .secured_request(Service::TesterPresent, vec![0x00], false)
+            .await
+            .unwrap();
+        assert_eq!(resp.service(), Service::TesterPresent);
+
+        client.tp_layer().stop().await;
+        server.stop().await;
+    }
+
+    #[cfg(all(feature = "client", feature = "std2020"))]
+    // the multi-frame transmission of ISO-TP waits the flow control without yielding
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_authentication_rejects_invalid_server() {
+        use super::context::{tests::auth_context, AuthPki};
+        use crate::{
+            pki::{
+                parse_certificate,
+                tests::{ECU, ROOT, TESTER_KEY},
+                SigningKey,
+            },
+            DoCanError,
+        };
+        use std::sync::Arc;
+        use x509_cert::der::Encode;
+
+        // the certificate of server isn't issued by the trust anchor of client
+        let (server, tester) = paired_server(auth_context(None)).await;
+        let mut client = tester_client(tester).await;
+        let err = client
+            .authenticate_bidirectional(
+                pki_file("tester.pem"),
+                pki_file("tester.key"),
+                pki_file("untrusted.pem"),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, DoCanError::UntrustedCertificate(_)),
+            "{:?}",
+            err
+        );
+        let err = client
+            .secured_request(iso14229_1::Service::TesterPresent, vec![0x00], true)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DoCanError::NoSessionKey), "{:?}", err);
+        client.tp_layer().stop().await;
+        server.stop().await;
+
+        // the challenge of client is signed by the key which isn't owned by the certificate of server
+        let mut ctx = auth_context(None);
+        ctx.pki = Some(Arc::new(AuthPki {
+            anchor: parse_certificate(ROOT).unwrap(),
+            identity: Some((
+                parse_certificate(ECU).unwrap().to_der().unwrap(),
+                SigningKey::from_pem(TESTER_KEY).unwrap(),
+            )),
+            role_oid: None,
+            challenge_len: 16,
+        }));
+        let (server, tester) = paired_server(ctx).await;
+        let mut client = tester_client(tester).await;
+        let err = client
+            .authenticate_bidirectional(
+                pki_file("tester.pem"),
+                pki_file("tester.key"),
+                pki_file("root.pem"),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, DoCanError::InvalidProofOfOwnership),
+            "{:?}",
+            err
+        );
+        client.tp_layer().stop().await;
+        server.stop().await;
+    }
+
+    #[cfg(all(feature = "client", feature = "std2020"))]
+    // the multi-frame transmission of ISO-TP waits the flow control without yielding
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_authentication_rejects_unexpected_return_value() {
+        use crate::DoCanError;
+        use iso15765_2::can::{Address, AddressType, CanIsoTp};
+
+        // the response is scripted since the server never returns an unexpected value
+        let (device, tester) = MockDevice::pair();
+        let address = Address {
+            tx_id: 0x7E8,
+            rx_id: 0x7E0,
+            fid: 0x7DF,
+        };
+        let mut ecu =
+            CanIsoTp::new(SharedDevice::new(device), "can0".to_string(), address, true).await;
+        ecu.start(100).await;
+        let responder = ecu.clone();
+        let runtime = tokio::runtime::Handle::current();
+        let handle = tokio::task::spawn_blocking(move || {
+            runtime.block_on(async move {
+                responder.wait_data(1_000).await.unwrap();
+                // CertificateVerified instead of CertificateVerifiedOrOwnershipVerificationNecessary
+                responder
+                    .transmit(
+                        AddressType::Physical,
+                        vec![0x69, 0x01, 0x13, 0x00, 0x01, 0xAA, 0x00, 0x00],
+                    )
+                    .await
+                    .unwrap();
+            })
+        });
+
+        let mut client = tester_client(tester).await;
+        let err = client
+            .authenticate_unidirectional(pki_file("tester.pem"), pki_file("tester.key"))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DoCanError::UnexpectedAuthReturnValue {
+                    expect: 0x11,
+                    actual: 0x13
+                }
+            ),
+            "{:?}",
+            err
+        );
+
+        handle.await.unwrap();
+        client.tp_layer().stop().await;
+        ecu.stop().await;
+    }
+}
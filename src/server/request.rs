@@ -0,0 +1,457 @@
+//! The evaluation, addressing and response rules of the request in processing.
+
+use crate::{constants::LOG_TAG_SERVER, server::context::Context};
+#[cfg(feature = "std2020")]
+use iso14229_1::AuthenticationTask;
+#[cfg(any(feature = "std2006", feature = "std2013"))]
+use iso14229_1::TimingParameterAccessType;
+use iso14229_1::{
+    request::Request, response::Code, CommunicationCtrlType, Configuration, DTCReportType,
+    DTCSettingType, DefinitionType, ECUResetType, EventType, Iso14229Error, LinkCtrlType,
+    RoutineCtrlType, SecurityAccessLevel, Service, SessionType, TesterPresentType,
+    SUPPRESS_POSITIVE,
+};
+use iso15765_2::can::{Address, AddressType};
+use rs_can::{CanFrame, CanId, CanListener};
+use std::{
+    any::Any,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+};
+use tokio::sync::Mutex;
+
+/// The negative response codes which are not transmitted for functionally addressed request.
+const FUNCTIONAL_SUPPRESSED_CODES: [Code; 5] = [
+    Code::ServiceNotSupported,
+    Code::SubFunctionNotSupported,
+    Code::RequestOutOfRange,
+    Code::SubFunctionNotSupportedInActiveSession,
+    Code::ServiceNotSupportedInActiveSession,
+];
+
+/// Record the address type of the latest single frame or first frame which is received by server.
+#[derive(Clone)]
+pub(crate) struct AddressListener<C> {
+    channel: C,
+    pub(crate) address: Arc<Mutex<Address>>,
+    received: Arc<Mutex<AddressType>>,
+}
+
+impl<C> AddressListener<C> {
+    pub(crate) fn new(channel: C, address: Address) -> Self {
+        Self {
+            channel,
+            address: Arc::new(Mutex::new(address)),
+            received: Default::default(),
+        }
+    }
+
+    /// The address type of the latest received request.
+    #[inline(always)]
+    pub(crate) async fn received(&self) -> AddressType {
+        *self.received.lock().await
+    }
+}
+
+#[async_trait::async_trait]
+impl<C, F> CanListener<C, F> for AddressListener<C>
+where
+    C: Clone + Eq + Display + Send + Sync + 'static,
+    F: CanFrame<Channel = C> + Clone + Display + 'static,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn on_frame_transmitted(&self, _: C, _: CanId) {}
+
+    async fn on_frame_received(&self, frames: Weak<Vec<F>>) {
+        let Some(frames) = frames.upgrade() else {
+            return;
+        };
+        let address = *self.address.lock().await;
+        for frame in frames.iter() {
+            if frame.channel() != self.channel {
+                continue;
+            }
+            // only the single frame and first frame start a request
+            if frame.data().first().is_none_or(|v| v >> 4 > 1) {
+                continue;
+            }
+
+            // the functional id of additional tester is the same as the physical id
+            let frame_id = frame.id().as_raw();
+            if frame_id == address.rx_id {
+                *self.received.lock().await = AddressType::Physical;
+            } else if frame_id == address.fid {
+                *self.received.lock().await = AddressType::Functional;
+            }
+        }
+    }
+}
+
+/// The state of request which decides the response is transmitted or not.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RequestState {
+    pub(crate) addr_type: AddressType,
+    pub(crate) suppress_positive: bool,
+    /// the RequestCorrectlyReceivedResponsePending is transmitted
+    pub(crate) pending: Arc<AtomicBool>,
+    /// the final response is transmitted(locked while transmitting),
+    /// the RequestCorrectlyReceivedResponsePending isn't transmitted after it
+    pub(crate) responded: Arc<Mutex<bool>>,
+}
+
+impl RequestState {
+    pub(crate) fn new(addr_type: AddressType) -> Self {
+        Self {
+            addr_type,
+            ..Default::default()
+        }
+    }
+
+    /// Check the response data(include SID) is transmitted or not.
+    ///
+    /// The positive response is transmitted after RequestCorrectlyReceivedResponsePending
+    /// even the suppressPosRspMsgIndicationBit is set.
+    pub(crate) fn should_transmit(&self, data: &[u8]) -> bool {
+        let nrc: u8 = Service::NRC.into();
+        match data {
+            [sid, _, code, ..] if *sid == nrc => {
+                let code = Code::from(*code);
+                if code == Code::RequestCorrectlyReceivedResponsePending {
+                    self.pending.store(true, Ordering::Release);
+                }
+                self.addr_type != AddressType::Functional
+                    || !FUNCTIONAL_SUPPRESSED_CODES.contains(&code)
+            }
+            _ => !self.suppress_positive || self.pending.load(Ordering::Acquire),
+        }
+    }
+
+    /// Check the response data(include SID) is RequestCorrectlyReceivedResponsePending.
+    #[inline]
+    pub(crate) fn is_pending(data: &[u8]) -> bool {
+        let nrc: u8 = Service::NRC.into();
+        matches!(data, [sid, _, code] if *sid == nrc
+            && Code::from(*code) == Code::RequestCorrectlyReceivedResponsePending)
+    }
+}
+
+/// Check the sub-function(without suppressPosRspMsgIndicationBit) is defined for the service,
+/// return `None` if the service has no sub-function.
+pub(crate) fn sub_function_supported(service: Service, sub_func: u8) -> Option<bool> {
+    let supported = match service {
+        Service::SessionCtrl => SessionType::try_from(sub_func).is_ok(),
+        Service::ECUReset => ECUResetType::try_from(sub_func).is_ok(),
+        Service::ReadDTCInfo => DTCReportType::try_from(sub_func).is_ok(),
+        Service::SecurityAccess => SecurityAccessLevel::try_from(sub_func).is_ok(),
+        Service::CommunicationCtrl => CommunicationCtrlType::try_from(sub_func).is_ok(),
+        #[cfg(feature = "std2020")]
+        Service::Authentication => AuthenticationTask::try_from(sub_func).is_ok(),
+        Service::DynamicalDefineDID => DefinitionType::try_from(sub_func).is_ok(),
+        Service::RoutineCtrl => RoutineCtrlType::try_from(sub_func).is_ok(),
+        // the storageState(bit 6) is a part of eventType
+        Service::ResponseOnEvent => EventType::try_from(sub_func).is_ok(),
+        Service::TesterPresent => TesterPresentType::try_from(sub_func).is_ok(),
+        #[cfg(any(feature = "std2006", feature = "std2013"))]
+        Service::AccessTimingParam => TimingParameterAccessType::try_from(sub_func).is_ok(),
+        Service::CtrlDTCSetting => DTCSettingType::try_from(sub_func).is_ok(),
+        Service::LinkCtrl => LinkCtrlType::try_from(sub_func).is_ok(),
+        _ => return None,
+    };
+
+    Some(supported)
+}
+
+/// The negative response code of the request which can't be parsed.
+pub(crate) fn error_code(e: &Iso14229Error) -> Code {
+    match e {
+        Iso14229Error::InvalidDataLength { .. }
+        | Iso14229Error::InvalidData(_)
+        | Iso14229Error::InvalidSessionData(_)
+        | Iso14229Error::SubFunctionError(_) => Code::IncorrectMessageLengthOrInvalidFormat,
+        Iso14229Error::InvalidParam(_)
+        | Iso14229Error::ReservedError(_)
+        | Iso14229Error::DidNotSupported(_)
+        | Iso14229Error::InvalidDynamicallyDefinedDID(_) => Code::RequestOutOfRange,
+        Iso14229Error::ServiceError(_) => Code::ConditionsNotCorrect,
+        Iso14229Error::NotImplement => Code::ServiceNotSupported,
+        _ => Code::GeneralReject,
+    }
+}
+
+/// Evaluate the request data(include SID) by the NRC order of ISO 14229-1:
+///
+/// service supported(0x11) → service in active session(0x7F) → minimum length(0x13)
+/// → sub-function supported(0x12) → sub-function in active session(0x7E)
+/// → security of service and sub-function(0x33) → length and format of request(0x13, 0x31).
+///
+/// The conditions(0x22), sequence(0x24) and range(0x31) of request data are checked by the service.
+/// Return the request without suppressPosRspMsgIndicationBit and whether the bit is set.
+pub(crate) fn evaluate_request(
+    context: &Context,
+    data: &[u8],
+    session: SessionType,
+    sa_level: u8,
+    cfg: &Configuration,
+) -> Result<(Request, bool), Code> {
+    let service = data
+        .first()
+        .and_then(|v| Service::try_from(*v).ok())
+        .filter(|v| *v != Service::NRC)
+        .ok_or(Code::ServiceNotSupported)?;
+
+    let service_access = context.check_service_access(service, session, sa_level);
+    if let Err(Code::ServiceNotSupportedInActiveSession) = service_access {
+        return Err(Code::ServiceNotSupportedInActiveSession);
+    }
+
+    let mut data = data[1..].to_vec();
+    let mut suppress_positive = false;
+    let mut sub_func_access = Ok(());
+    let sub_func = data.first().map(|v| v & !SUPPRESS_POSITIVE);
+    match sub_function_supported(service, sub_func.unwrap_or_default()) {
+        // the service without sub-function
+        None => {}
+        Some(_) if sub_func.is_none() => return Err(Code::IncorrectMessageLengthOrInvalidFormat),
+        Some(false) => return Err(Code::SubFunctionNotSupported),
+        Some(true) => {
+            let sub_func = sub_func.unwrap_or_default();
+            sub_func_access =
+                context.check_sub_function_access(service, sub_func, session, sa_level);
+            if let Err(Code::SubFunctionNotSupportedInActiveSession) = sub_func_access {
+                return Err(Code::SubFunctionNotSupportedInActiveSession);
+            }
+            suppress_positive = data[0] != sub_func;
+            data[0] = sub_func;
+        }
+    }
+
+    service_access?;
+    sub_func_access?;
+
+    match Request::try_from((service, data.as_slice(), cfg)) {
+        Ok(req) => Ok((req, suppress_positive)),
+        Err(e) => {
+            rsutil::warn!(
+                "{} error: {} when data: {} to request",
+                LOG_TAG_SERVER,
+                e,
+                hex::encode(&data)
+            );
+            Err(error_code(&e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate_request, RequestState};
+    use crate::server::{
+        context::tests::test_context, ServiceAccessConfig, SubFunctionAccessConfig,
+    };
+    use iso14229_1::{response::Code, Service, SessionType};
+    use iso15765_2::can::AddressType;
+
+    fn negative(code: Code) -> Vec<u8> {
+        vec![Service::NRC.into(), Service::ReadDID.into(), code.into()]
+    }
+
+    #[test]
+    fn functional_negative_responses_are_suppressed() {
+        let physical = RequestState::new(AddressType::Physical);
+        let functional = RequestState::new(AddressType::Functional);
+        for code in [
+            Code::ServiceNotSupported,
+            Code::SubFunctionNotSupported,
+            Code::RequestOutOfRange,
+            Code::SubFunctionNotSupportedInActiveSession,
+            Code::ServiceNotSupportedInActiveSession,
+        ] {
+            assert!(physical.should_transmit(&negative(code)));
+            assert!(!functional.should_transmit(&negative(code)));
+        }
+        assert!(functional.should_transmit(&negative(Code::ConditionsNotCorrect)));
+        assert!(functional.should_transmit(&negative(Code::SecurityAccessDenied)));
+    }
+
+    #[test]
+    fn response_pending_is_detected() {
+        assert!(RequestState::is_pending(&negative(
+            Code::RequestCorrectlyReceivedResponsePending
+        )));
+        assert!(!RequestState::is_pending(&negative(
+            Code::BusyRepeatRequest
+        )));
+        assert!(!RequestState::is_pending(&[0x62, 0x7F, 0x78]));
+    }
+
+    #[test]
+    fn suppressed_positive_response_is_sent_after_pending() {
+        let positive = [0x71, 0x01, 0xFF, 0x00];
+        let mut state = RequestState::new(AddressType::Functional);
+        assert!(state.should_transmit(&positive));
+
+        state.suppress_positive = true;
+        assert!(!state.should_transmit(&positive));
+        assert!(state.should_transmit(&negative(Code::RequestCorrectlyReceivedResponsePending)));
+        assert!(state.should_transmit(&positive));
+    }
+
+    /// the request data and the sub-function with suppressPosRspMsgIndicationBit or NRC
+    type Case = (&'static [u8], Result<(Option<u8>, bool), Code>);
+
+    #[test]
+    fn request_is_evaluated_per_service() {
+        let ctx = test_context();
+        let cfg = ctx.get_cfg().clone();
+        let evaluate = |data: &[u8]| {
+            evaluate_request(&ctx, data, SessionType::Default, 0, &cfg)
+                .map(|(req, suppress)| (req.sub_function().map(u8::from), suppress))
+        };
+
+        let cases: &[Case] = &[
+            (&[0xBA], Err(Code::ServiceNotSupported)),
+            (&[0x7F, 0x10, 0x11], Err(Code::ServiceNotSupported)),
+            (&[0x10], Err(Code::IncorrectMessageLengthOrInvalidFormat)),
+            (&[0x10, 0x00], Err(Code::SubFunctionNotSupported)),
+            (
+                &[0x10, 0x03, 0x00],
+                Err(Code::IncorrectMessageLengthOrInvalidFormat),
+            ),
+            (&[0x10, 0x83], Ok((Some(0x03), true))),
+            (&[0x11, 0x00], Err(Code::SubFunctionNotSupported)),
+            (
+                &[0x11, 0x01, 0x00],
+                Err(Code::IncorrectMessageLengthOrInvalidFormat),
+            ),
+            (
+                &[0x14, 0xFF, 0xFF],
+                Err(Code::IncorrectMessageLengthOrInvalidFormat),
+            ),
+            (&[0x14, 0xFF, 0xFF, 0xFF], Ok((None, false))),
+            (&[0x19], Err(Code::IncorrectMessageLengthOrInvalidFormat)),
+            (&[0x19, 0x00], Err(Code::SubFunctionNotSupported)),
+            (
+                &[0x19, 0x02],
+                Err(Code::IncorrectMessageLengthOrInvalidFormat),
+            ),
+            (
+                &[0x22, 0xF1],
+                Err(Code::IncorrectMessageLengthOrInvalidFormat),
+            ),
+            (&[0x22, 0x41, 0x01], Ok((None, false))),
+            (&[0x27, 0x00], Err(Code::SubFunctionNotSupported)),
+            (&[0x27, 0x01], Ok((Some(0x01), false))),
+            (
+                &[0x28, 0x00],
+                Err(Code::IncorrectMessageLengthOrInvalidFormat),
+            ),
+            (
+                &[0x2E, 0x41, 0x01, 0x00],
+                Err(Code::IncorrectMessageLengthOrInvalidFormat),
+            ),
+            (&[0x2E, 0xF1, 0x90, 0x00], Err(Code::RequestOutOfRange)),
+            (
+                &[0x31, 0x00, 0xFF, 0x00],
+                Err(Code::SubFunctionNotSupported),
+            ),
+            (
+                &[0x31, 0x01, 0xFF],
+                Err(Code::IncorrectMessageLengthOrInvalidFormat),
+            ),
+            (&[0x31, 0x81, 0xFF, 0x00], Ok((Some(0x01), true))),
+            (&[0x3E], Err(Code::IncorrectMessageLengthOrInvalidFormat)),
+            (&[0x3E, 0x01], Err(Code::SubFunctionNotSupported)),
+            (&[0x3E, 0x80], Ok((Some(0x00), true))),
+            (&[0x85, 0x00], Err(Code::SubFunctionNotSupported)),
+            (&[0x85, 0x02], Ok((Some(0x02), false))),
+            (&[0x86, 0x0A, 0x00], Err(Code::SubFunctionNotSupported)),
+            (&[0x86, 0x85, 0x00], Ok((None, true))),
+            (&[0x86, 0x45, 0x00], Ok((None, false))),
+            (&[0x87, 0x00], Err(Code::SubFunctionNotSupported)),
+        ];
+        for (data, expect) in cases {
+            assert_eq!(&evaluate(data), expect, "request: {}", hex::encode(data));
+        }
+        // the modeOfOperation has no suppressPosRspMsgIndicationBit
+        #[cfg(any(feature = "std2013", feature = "std2020"))]
+        assert_eq!(
+            evaluate(&[0x38, 0x82, 0x00, 0x01, 0x61]),
+            Ok((Some(0x82), false))
+        );
+    }
+
+    #[test]
+    fn request_is_evaluated_in_nrc_order() {
+        let mut ctx = test_context();
+        let cfg = ctx.get_cfg().clone();
+        ctx.config.service_access.insert(
+            Service::RoutineCtrl.into(),
+            ServiceAccessConfig {
+                sessions: vec![0x03],
+                levels: vec![],
+                sub_functions: [(
+                    0x01,
+                    SubFunctionAccessConfig {
+                        sessions: vec![0x03],
+                        levels: vec![0x03],
+                    },
+                )]
+                .into(),
+            },
+        );
+        ctx.config.service_access.insert(
+            Service::ReadMemByAddr.into(),
+            ServiceAccessConfig {
+                sessions: vec![],
+                levels: vec![0x03],
+                sub_functions: Default::default(),
+            },
+        );
+        let (default, extended) = (SessionType::Default, SessionType::Extended);
+        let evaluate = |data: &[u8], session, sa_level| {
+            evaluate_request(&ctx, data, session, sa_level, &cfg).map(|_| ())
+        };
+
+        // the session is checked before the length
+        assert_eq!(
+            evaluate(&[0x31], default, 0),
+            Err(Code::ServiceNotSupportedInActiveSession)
+        );
+        // the minimum length is checked before the sub-function
+        assert_eq!(
+            evaluate(&[0x31], extended, 0),
+            Err(Code::IncorrectMessageLengthOrInvalidFormat)
+        );
+        // the sub-function is checked before the security
+        assert_eq!(
+            evaluate(&[0x31, 0x00, 0xFF, 0x00], extended, 0),
+            Err(Code::SubFunctionNotSupported)
+        );
+        // the security is checked before the length of whole request
+        assert_eq!(
+            evaluate(&[0x31, 0x01], extended, 0),
+            Err(Code::SecurityAccessDenied)
+        );
+        assert_eq!(
+            evaluate(&[0x31, 0x01], extended, 3),
+            Err(Code::IncorrectMessageLengthOrInvalidFormat)
+        );
+        assert_eq!(evaluate(&[0x31, 0x02, 0xFF, 0x00], extended, 0), Ok(()));
+        assert_eq!(evaluate(&[0x31, 0x01, 0xFF, 0x00], extended, 3), Ok(()));
+        // the security of service without sub-function
+        assert_eq!(
+            evaluate(&[0x23, 0x12], default, 0),
+            Err(Code::SecurityAccessDenied)
+        );
+        assert_eq!(
+            evaluate(&[0x23, 0x12], default, 3),
+            Err(Code::IncorrectMessageLengthOrInvalidFormat)
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! The routines of RoutineControl.
+
+use iso14229_1::{response::Code, RoutineCtrlType};
+
+/// The user handler of routine, the handler overrides the built-in routine of the same id.
+///
+/// The running state is tracked by server, the StopRoutine and RequestRoutineResults
+/// are rejected with `RequestSequenceError` before the routine is started.
+#[async_trait::async_trait]
+pub trait Routine: Send + Sync {
+    /// Check the option record of StartRoutine and StopRoutine before the handler is called.
+    fn check_option(&self, _type: RoutineCtrlType, _option_record: &[u8]) -> Result<(), Code> {
+        Ok(())
+    }
+    /// The routine is running until it's stopped, otherwise it's completed when it's started
+    /// and StopRoutine is not supported.
+    fn stoppable(&self) -> bool {
+        false
+    }
+    /// Start the routine, return the routineStatusRecord.
+    async fn start(&self, option_record: &[u8]) -> Result<Vec<u8>, Code>;
+    /// Stop the running routine, return the routineStatusRecord.
+    async fn stop(&self, _option_record: &[u8]) -> Result<Vec<u8>, Code> {
+        Ok(vec![])
+    }
+    /// Request the results, the routineStatusRecord of the last start or stop is responded by default.
+    async fn results(&self, last: &[u8]) -> Result<Vec<u8>, Code> {
+        Ok(last.to_vec())
+    }
+}
+
+/// The state of routine since it's started.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RoutineState {
+    pub(crate) running: bool,
+    /// the routineStatusRecord of the last start or stop
+    pub(crate) record: Vec<u8>,
+}
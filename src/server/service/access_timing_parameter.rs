@@ -1,6 +1,9 @@
 //! response of Service 83
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{self, Request},
     response::{Code, Response},
@@ -22,61 +25,51 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = if self.session.get_session_type().await == Default::default() {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            match req.sub_function() {
-                Some(sf) => match req.data::<request::AccessTimingParameter>(cfg) {
-                    Ok(data) => match sf.function::<TimingParameterAccessType>() {
-                        Ok(r#type) => {
-                            match self
-                                .context
-                                .access_timing_parameter(r#type, &data.data)
-                                .await
-                            {
-                                Ok(v) => {
-                                    if sf.is_suppress_positive() {
-                                        return Ok(());
-                                    } else {
-                                        Response::new(
-                                            service,
-                                            Some(r#type.into()),
-                                            Vec::<u8>::from(v),
-                                            cfg,
-                                        )?
-                                    }
-                                }
-                                Err(code) => Response::new_negative(service, code),
-                            }
-                        }
-                        Err(e) => {
-                            rsutil::warn!(
-                                "{} can't parse sub-function on service: {}, because of: {}",
-                                LOG_TAG_SERVER,
+        let resp = match req.sub_function() {
+            Some(sf) => match req.data::<request::AccessTimingParameter>(cfg) {
+                Ok(data) => match sf.function::<TimingParameterAccessType>() {
+                    Ok(r#type) => {
+                        match self
+                            .context
+                            .access_timing_parameter(r#type, &data.data)
+                            .await
+                        {
+                            Ok(v) => Response::new(
                                 service,
-                                e
-                            );
-                            Response::new_negative(service, Code::SubFunctionNotSupported)
+                                Some(r#type.into()),
+                                Vec::<u8>::from(v),
+                                cfg,
+                            )?,
+                            Err(code) => Response::new_negative(service, code),
                         }
-                    },
+                    }
                     Err(e) => {
                         rsutil::warn!(
-                            "{} can't parse data on service: {}, because of: {}",
+                            "{} can't parse sub-function on service: {}, because of: {}",
                             LOG_TAG_SERVER,
                             service,
                             e
                         );
-                        Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                        Response::new_negative(service, Code::SubFunctionNotSupported)
                     }
                 },
-                None => {
+                Err(e) => {
                     rsutil::warn!(
-                        "{} can't get sub-function on service: {}",
+                        "{} can't parse data on service: {}, because of: {}",
                         LOG_TAG_SERVER,
-                        service
+                        service,
+                        e
                     );
-                    Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                    Response::new_negative(service, error_code(&e))
                 }
+            },
+            None => {
+                rsutil::warn!(
+                    "{} can't get sub-function on service: {}",
+                    LOG_TAG_SERVER,
+                    service
+                );
+                Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
             }
         };
 
@@ -1,9 +1,12 @@
 //! response of Service 14
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{self, Request},
-    response::{Code, Response},
+    response::Response,
     Configuration, Iso14229Error,
 };
 use rs_can::{CanDevice, CanFrame};
@@ -29,12 +32,12 @@ where
             },
             Err(e) => {
                 rsutil::warn!(
-                    "{} can't parse sub-function on service: {}, because of: {}",
+                    "{} can't parse data on service: {}, because of: {}",
                     LOG_TAG_SERVER,
                     service,
                     e
                 );
-                Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                Response::new_negative(service, error_code(&e))
             }
         };
 
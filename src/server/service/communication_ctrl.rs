@@ -1,6 +1,9 @@
 //! response of Service 28
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{self, Request},
     response::{Code, Response},
@@ -22,53 +25,40 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = if self.session.get_session_type().await == Default::default() {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            match req.sub_function() {
-                Some(sf) => match sf.function::<CommunicationCtrlType>() {
-                    Ok(r#type) => match req.data::<request::CommunicationCtrl>(cfg) {
-                        Ok(ctrl) => match self.context.communication_ctrl(r#type, &ctrl).await {
-                            Ok(()) => {
-                                if sf.is_suppress_positive() {
-                                    return Ok(());
-                                } else {
-                                    Response::new(service, Some(r#type.into()), vec![], cfg)?
-                                }
-                            }
-                            Err(code) => Response::new_negative(service, code),
-                        },
-                        Err(e) => {
-                            rsutil::warn!(
-                                "{} can't parse data on service: {}, because of: {}",
-                                LOG_TAG_SERVER,
-                                service,
-                                e
-                            );
-                            Response::new_negative(
-                                service,
-                                Code::IncorrectMessageLengthOrInvalidFormat,
-                            )
-                        }
+        let resp = match req.sub_function() {
+            Some(sf) => match sf.function::<CommunicationCtrlType>() {
+                Ok(r#type) => match req.data::<request::CommunicationCtrl>(cfg) {
+                    Ok(ctrl) => match self.context.communication_ctrl(r#type, &ctrl).await {
+                        Ok(()) => Response::new(service, Some(r#type.into()), vec![], cfg)?,
+                        Err(code) => Response::new_negative(service, code),
                     },
                     Err(e) => {
                         rsutil::warn!(
-                            "{} can't parse sub-function on service: {}, because of: {}",
+                            "{} can't parse data on service: {}, because of: {}",
                             LOG_TAG_SERVER,
                             service,
                             e
                         );
-                        Response::new_negative(service, Code::SubFunctionNotSupported)
+                        Response::new_negative(service, error_code(&e))
                     }
                 },
-                None => {
+                Err(e) => {
                     rsutil::warn!(
-                        "{} can't get sub-function on service: {}",
+                        "{} can't parse sub-function on service: {}, because of: {}",
                         LOG_TAG_SERVER,
-                        service
+                        service,
+                        e
                     );
-                    Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                    Response::new_negative(service, Code::SubFunctionNotSupported)
                 }
+            },
+            None => {
+                rsutil::warn!(
+                    "{} can't get sub-function on service: {}",
+                    LOG_TAG_SERVER,
+                    service
+                );
+                Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
             }
         };
 
@@ -1,6 +1,9 @@
 //! response of Service 85
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{self, Request},
     response::{Code, Response},
@@ -22,58 +25,46 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = if self.session.get_session_type().await == Default::default() {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            match req.sub_function() {
-                Some(sf) => match req.data::<request::CtrlDTCSetting>(cfg) {
-                    Ok(data) => match sf.function::<DTCSettingType>() {
-                        Ok(r#type) => {
-                            rsutil::info!(
-                                "{} receive request on service: {}, with sub-function: {:?}, and data: {:?}",
-                                LOG_TAG_SERVER,
-                                service,
-                                r#type,
-                                data
-                            );
-                            match self.context.set_dtc_setting(r#type).await {
-                                Ok(applied) => {
-                                    if sf.is_suppress_positive() {
-                                        return Ok(());
-                                    } else {
-                                        Response::new(service, Some(applied.into()), vec![], cfg)?
-                                    }
-                                }
-                                Err(code) => Response::new_negative(service, code),
-                            }
-                        }
-                        Err(e) => {
-                            rsutil::warn!(
-                                "{} Failed to parse sub-function: {:?}",
-                                LOG_TAG_SERVER,
-                                e
-                            );
-                            Response::new_negative(service, Code::SubFunctionNotSupported)
-                        }
-                    },
-                    Err(e) => {
-                        rsutil::warn!(
-                            "{} can't parse data on service: {}, because of: {}",
+        let resp = match req.sub_function() {
+            Some(sf) => match req.data::<request::CtrlDTCSetting>(cfg) {
+                Ok(data) => match sf.function::<DTCSettingType>() {
+                    Ok(r#type) => {
+                        rsutil::info!(
+                            "{} receive request on service: {}, with sub-function: {:?}, and data: {:?}",
                             LOG_TAG_SERVER,
                             service,
-                            e
+                            r#type,
+                            data
                         );
-                        Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                        match self.context.set_dtc_setting(r#type).await {
+                            Ok(applied) => {
+                                Response::new(service, Some(applied.into()), vec![], cfg)?
+                            }
+                            Err(code) => Response::new_negative(service, code),
+                        }
+                    }
+                    Err(e) => {
+                        rsutil::warn!("{} Failed to parse sub-function: {:?}", LOG_TAG_SERVER, e);
+                        Response::new_negative(service, Code::SubFunctionNotSupported)
                     }
                 },
-                None => {
+                Err(e) => {
                     rsutil::warn!(
-                        "{} can't get sub-function on service: {}",
+                        "{} can't parse data on service: {}, because of: {}",
                         LOG_TAG_SERVER,
-                        service
+                        service,
+                        e
                     );
-                    Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                    Response::new_negative(service, error_code(&e))
                 }
+            },
+            None => {
+                rsutil::warn!(
+                    "{} can't get sub-function on service: {}",
+                    LOG_TAG_SERVER,
+                    service
+                );
+                Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
             }
         };
 
@@ -1,10 +1,13 @@
 //! response of Service 2C
 
-use crate::server::DoCanServer;
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
-    request::Request,
+    request::{DynamicallyDefineDID, Request},
     response::{Code, Response},
-    Configuration, Iso14229Error,
+    Configuration, DataIdentifier, DefinitionType, Iso14229Error,
 };
 use rs_can::{CanDevice, CanFrame};
 use std::fmt::Display;
@@ -18,11 +21,61 @@ where
     pub(crate) async fn dynamically_define_did(
         &self,
         req: Request,
-        _cfg: &Configuration,
+        cfg: &Configuration,
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
+        let Some(sf) = req.sub_function() else {
+            self.transmit_response(
+                Response::new_negative(service, Code::SubFunctionNotSupported),
+                true,
+            )
+            .await;
+            return Ok(());
+        };
+
+        let resp = match req.data::<DynamicallyDefineDID>(cfg) {
+            Ok(ctx) => {
+                let session = self.session.get_session_type().await;
+                let sa_level = self.session.get_security_access_level().await;
+                let result = match ctx {
+                    DynamicallyDefineDID::DefineByIdentifier {
+                        did,
+                        source,
+                        others,
+                    } => {
+                        let mut sources = vec![source];
+                        sources.extend(others);
+                        let did = DataIdentifier::from(u16::from(did));
+                        self.context
+                            .define_dynamic_did(did, &sources, session, sa_level)
+                            .await
+                            .map(|_| Some(did))
+                    }
+                    DynamicallyDefineDID::DefineByMemoryAddress { .. } => {
+                        Err(Code::SubFunctionNotSupported)
+                    }
+                    DynamicallyDefineDID::ClearDynamicallyDefinedDataIdentifier(did) => {
+                        let did = did.map(|v| DataIdentifier::from(u16::from(v)));
+                        self.context.clear_dynamic_did(did).await.map(|_| did)
+                    }
+                };
 
-        let resp = Response::new_negative(service, Code::ServiceNotSupported);
+                match result {
+                    Ok(did) => {
+                        let data = did
+                            .map(|v| u16::from(v).to_be_bytes().to_vec())
+                            .unwrap_or_default();
+                        let r#type: DefinitionType = sf.function()?;
+                        Response::new(service, Some(r#type.into()), data, cfg)?
+                    }
+                    Err(code) => Response::new_negative(service, code),
+                }
+            }
+            Err(e) => {
+                rsutil::warn!("{} Failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                Response::new_negative(service, error_code(&e))
+            }
+        };
 
         self.transmit_response(resp, true).await;
 
@@ -22,27 +22,21 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
         let resp = match req.sub_function() {
-            Some(sf) => {
-                match sf.function::<ECUResetType>() {
-                    Ok(r#type) => {
-                        self.context.reset().await;
-                        self.session.reset().await;
-                        if sf.is_suppress_positive() {
-                            return Ok(()); // suppress positive
-                        } else {
-                            let data = match r#type {
-                                ECUResetType::EnableRapidPowerShutDown => vec![1],
-                                _ => vec![],
-                            };
-                            Response::new(service, Some(r#type.into()), data, cfg)?
-                        }
-                    }
-                    Err(e) => {
-                        rsutil::warn!("{} Failed to parse sub-function: {:?}", LOG_TAG_SERVER, e);
-                        Response::new_negative(service, Code::SubFunctionNotSupported)
-                    }
+            Some(sf) => match sf.function::<ECUResetType>() {
+                Ok(r#type) => {
+                    self.context.reset().await;
+                    self.session.reset().await;
+                    let data = match r#type {
+                        ECUResetType::EnableRapidPowerShutDown => vec![1],
+                        _ => vec![],
+                    };
+                    Response::new(service, Some(r#type.into()), data, cfg)?
                 }
-            }
+                Err(e) => {
+                    rsutil::warn!("{} Failed to parse sub-function: {:?}", LOG_TAG_SERVER, e);
+                    Response::new_negative(service, Code::SubFunctionNotSupported)
+                }
+            },
             None => Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat),
         };
 
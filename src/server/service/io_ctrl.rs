@@ -1,10 +1,13 @@
 //! response of Service 2F
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{did::DidAccessType, request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{self, Request},
-    response::{Code, Response},
-    Configuration, Iso14229Error, SessionType,
+    response::Response,
+    Configuration, Iso14229Error,
 };
 use rs_can::{CanDevice, CanFrame};
 use std::fmt::Display;
@@ -22,18 +25,26 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = if self.session.get_session_type().await == SessionType::Default {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            match req.data::<request::IOCtrl>(cfg) {
-                Ok(ctx) => match self.context.io_ctrl(&ctx).await {
+        let resp = match req.data::<request::IOCtrl>(cfg) {
+            Ok(ctx) => match self
+                .context
+                .check_did_access(
+                    &ctx.did,
+                    DidAccessType::IoControl,
+                    self.session.get_session_type().await,
+                    self.session.get_security_access_level().await,
+                )
+                .await
+            {
+                Ok(_) => match self.context.io_ctrl(&ctx).await {
                     Ok(data) => Response::new(service, None, Vec::<u8>::from(data), cfg)?,
                     Err(code) => Response::new_negative(service, code),
                 },
-                Err(e) => {
-                    rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
-                    Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
-                }
+                Err(code) => Response::new_negative(service, code),
+            },
+            Err(e) => {
+                rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                Response::new_negative(service, error_code(&e))
             }
         };
 
@@ -1,10 +1,13 @@
 //! response of Service 87
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{self, Request},
     response::{Code, Response},
-    Configuration, Iso14229Error, LinkCtrlType, SessionType,
+    Configuration, Iso14229Error, LinkCtrlType,
 };
 use rs_can::{CanDevice, CanFrame};
 use std::fmt::Display;
@@ -22,67 +25,48 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = if self.session.get_session_type().await == SessionType::Default {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            match req.sub_function() {
-                Some(sf) => match req.data::<request::LinkCtrl>(cfg) {
-                    Ok(data) => match sf.function::<LinkCtrlType>() {
-                        Ok(r#type) => {
-                            rsutil::info!(
-                                "{} LinkCtrl request: {:?}, sub-function: {:?}",
-                                LOG_TAG_SERVER,
-                                data,
-                                r#type
-                            );
-                            match r#type {
-                                LinkCtrlType::VerifyModeTransitionWithFixedParameter
-                                | LinkCtrlType::VerifyModeTransitionWithSpecificParameter => {
-                                    self.session.arm_link_control_verify().await;
-                                    if sf.is_suppress_positive() {
-                                        return Ok(());
-                                    } else {
-                                        Response::new(service, Some(sf.into()), vec![], cfg)?
-                                    }
-                                }
-                                LinkCtrlType::TransitionMode => {
-                                    if !self.session.consume_link_control_verify().await {
-                                        Response::new_negative(service, Code::RequestSequenceError)
-                                    } else if sf.is_suppress_positive() {
-                                        return Ok(());
-                                    } else {
-                                        Response::new(
-                                            service,
-                                            Some(LinkCtrlType::TransitionMode.into()),
-                                            vec![],
-                                            cfg,
-                                        )?
-                                    }
+        let resp = match req.sub_function() {
+            Some(sf) => match req.data::<request::LinkCtrl>(cfg) {
+                Ok(data) => match sf.function::<LinkCtrlType>() {
+                    Ok(r#type) => {
+                        rsutil::info!(
+                            "{} LinkCtrl request: {:?}, sub-function: {:?}",
+                            LOG_TAG_SERVER,
+                            data,
+                            r#type
+                        );
+                        match r#type {
+                            LinkCtrlType::VerifyModeTransitionWithFixedParameter
+                            | LinkCtrlType::VerifyModeTransitionWithSpecificParameter => {
+                                self.session.arm_link_control_verify().await;
+                                Response::new(service, Some(sf.into()), vec![], cfg)?
+                            }
+                            LinkCtrlType::TransitionMode => {
+                                if !self.session.consume_link_control_verify().await {
+                                    Response::new_negative(service, Code::RequestSequenceError)
+                                } else {
+                                    Response::new(
+                                        service,
+                                        Some(LinkCtrlType::TransitionMode.into()),
+                                        vec![],
+                                        cfg,
+                                    )?
                                 }
-                                _ => Response::new_negative(service, Code::SubFunctionNotSupported),
                             }
+                            _ => Response::new_negative(service, Code::SubFunctionNotSupported),
                         }
-                        Err(e) => {
-                            rsutil::warn!(
-                                "{} failed to parse request data: {:?}",
-                                LOG_TAG_SERVER,
-                                e
-                            );
-                            Response::new_negative(
-                                service,
-                                Code::IncorrectMessageLengthOrInvalidFormat,
-                            )
-                        }
-                    },
+                    }
                     Err(e) => {
                         rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
-                        Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                        Response::new_negative(service, error_code(&e))
                     }
                 },
-                None => {
-                    Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                Err(e) => {
+                    rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                    Response::new_negative(service, error_code(&e))
                 }
-            }
+            },
+            None => Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat),
         };
 
         self.transmit_response(resp, true).await;
@@ -1,10 +1,13 @@
 //! response of Service 2A
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{self, Request},
     response::{Code, Response},
-    Configuration, Iso14229Error, SessionType,
+    Configuration, Iso14229Error,
 };
 use rs_can::{CanDevice, CanFrame};
 use std::fmt::Display;
@@ -22,19 +25,15 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = if self.session.get_session_type().await == SessionType::Default {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            match req.data::<request::ReadDataByPeriodId>(cfg) {
-                Ok(_) => {
-                    // let mode = ctx.mode;
-                    // let did = ctx.did;
-                    Response::new_negative(service, Code::ServiceNotSupported)
-                }
-                Err(e) => {
-                    rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
-                    Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
-                }
+        let resp = match req.data::<request::ReadDataByPeriodId>(cfg) {
+            Ok(_) => {
+                // let mode = ctx.mode;
+                // let did = ctx.did;
+                Response::new_negative(service, Code::ServiceNotSupported)
+            }
+            Err(e) => {
+                rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                Response::new_negative(service, error_code(&e))
             }
         };
 
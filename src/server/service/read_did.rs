@@ -1,6 +1,9 @@
 //! response of Service 22
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{did::DidAccessType, request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{ReadDID, Request},
     response::{Code, Response},
@@ -29,44 +32,45 @@ where
                 if list.is_empty() {
                     Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
                 } else {
+                    let session = self.session.get_session_type().await;
+                    let sa_level = self.session.get_security_access_level().await;
                     let mut data = Vec::with_capacity(list.len());
                     for did in list {
-                        match self.context.get_static_did(&did).await {
-                            Some(val) => match self.context.get_static_did_sa_level(&did) {
-                                Some(v) => {
-                                    if self.session.get_security_access_level().await != v {
-                                        return Ok(self
-                                            .transmit_response(
-                                                Response::new_negative(
-                                                    service,
-                                                    Code::SecurityAccessDenied,
-                                                ),
-                                                true,
-                                            )
-                                            .await);
-                                    }
+                        let value =
+                            match self.context.read_dynamic_did(&did, session, sa_level).await {
+                                Some(value) => value,
+                                None => match self
+                                    .context
+                                    .check_did_access(&did, DidAccessType::Read, session, sa_level)
+                                    .await
+                                {
+                                    Ok(_) => self
+                                        .context
+                                        .read_static_did(&did)
+                                        .await
+                                        .ok_or(Code::RequestOutOfRange),
+                                    Err(code) => Err(code),
+                                },
+                            };
 
-                                    let did_val: u16 = did.into();
-                                    data.extend_from_slice(did_val.to_be_bytes().as_slice());
-                                    data.extend_from_slice(val.as_ref());
-                                }
-                                None => {
-                                    let did_val: u16 = did.into();
-                                    data.extend_from_slice(did_val.to_be_bytes().as_slice());
-                                    data.extend_from_slice(val.as_ref());
-                                }
-                            },
-                            None => {
+                        match value {
+                            Ok(val) => {
+                                let did_val: u16 = did.into();
+                                data.extend_from_slice(did_val.to_be_bytes().as_slice());
+                                data.extend_from_slice(val.as_ref());
+                            }
+                            // the DID which is not supported in active session is skipped
+                            Err(Code::RequestOutOfRange)
+                                if self.context.get_cfg().did.contains_key(&did) => {}
+                            Err(code) => {
                                 rsutil::warn!(
-                                    "{} DID: {:?} is not configured",
+                                    "{} DID: {:?} can't be read: {:?}",
                                     LOG_TAG_SERVER,
-                                    did
+                                    did,
+                                    code
                                 );
                                 return Ok(self
-                                    .transmit_response(
-                                        Response::new_negative(service, Code::RequestOutOfRange),
-                                        true,
-                                    )
+                                    .transmit_response(Response::new_negative(service, code), true)
                                     .await);
                             }
                         }
@@ -81,7 +85,7 @@ where
             }
             Err(e) => {
                 rsutil::warn!("{} Failed to parse request data: {:?}", LOG_TAG_SERVER, e);
-                Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                Response::new_negative(service, error_code(&e))
             }
         };
 
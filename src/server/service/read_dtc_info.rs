@@ -1,9 +1,10 @@
 //! response of Service 19
 
-use crate::server::{context::DtcRecord, DoCanServer};
+use crate::server::{context::DtcRecord, request::error_code, DoCanServer};
 use iso14229_1::{
     request::{self, Request},
     response::{self, Code, Response},
+    utils::U24,
     Configuration, Iso14229Error,
 };
 use rs_can::{CanDevice, CanFrame};
@@ -49,6 +50,44 @@ fn last_matching(records: &[DtcRecord], mask: u8) -> Option<response::DTCAndStat
     })
 }
 
+/// The DTC of primary memory(`None`) or user defined memory.
+fn memory_record(
+    records: &[DtcRecord],
+    mask_record: U24,
+    user_memory: Option<u8>,
+) -> Result<&DtcRecord, Code> {
+    records
+        .iter()
+        .find(|record| record.dtc == mask_record && record.user_memory == user_memory)
+        .ok_or(Code::RequestOutOfRange)
+}
+
+/// The snapshot records of DTC, all records are selected when `record_num` is 0xFF.
+fn selected_snapshots(
+    record: &DtcRecord,
+    record_num: u8,
+) -> Result<Vec<(u8, Vec<response::DTCSnapshotRecord>)>, Code> {
+    if record_num == 0x00 {
+        return Err(Code::RequestOutOfRange);
+    }
+
+    Ok(record
+        .snapshots
+        .iter()
+        .filter(|(number, _)| record_num == 0xFF || *number == record_num)
+        .map(|(number, data)| {
+            let data = data
+                .iter()
+                .map(|(did, data)| response::DTCSnapshotRecord {
+                    did: *did,
+                    data: data.clone(),
+                })
+                .collect();
+            (*number, data)
+        })
+        .collect())
+}
+
 fn validated_ext_data_records(
     record: &DtcRecord,
     extra_num: u8,
@@ -147,23 +186,106 @@ fn build_read_dtc_response(
                 records: status_records(&filtered),
             })
         }
-        // request::DTCInfo::ReportDTCSnapshotIdentification => {
-        //     todo!()
-        // }
-        // request::DTCInfo::ReportDTCSnapshotRecordByDTCNumber {
-        //     mask_record: _,
-        //     record_num: _,
-        // } => {
-        //     todo!()
-        // }
-        // #[cfg(feature = "std2006")]
-        // request::DTCInfo::ReportDTCSnapshotRecordByRecordNumber { record_num: _ } => {
-        //     todo!()
-        // }
-        // #[cfg(any(feature = "std2013", feature = "std2020"))]
-        // request::DTCInfo::ReportDTCStoredDataByRecordNumber { stored_num: _ } => {
-        //     todo!()
-        // }
+        request::DTCInfo::ReportDTCSnapshotIdentification => {
+            let records = records
+                .iter()
+                .filter(|record| record.user_memory.is_none())
+                .flat_map(|record| {
+                    record
+                        .snapshots
+                        .iter()
+                        .map(|(number, _)| response::DTCSnapshotIdentification {
+                            dtc: record.dtc,
+                            number: *number,
+                        })
+                })
+                .collect();
+
+            Ok(response::DTCInfo::ReportDTCSnapshotIdentification { records })
+        }
+        request::DTCInfo::ReportDTCSnapshotRecordByDTCNumber {
+            mask_record,
+            record_num,
+        } => {
+            let record = memory_record(records, mask_record, None)?;
+            let records = selected_snapshots(record, record_num)?
+                .into_iter()
+                .map(|(number, records)| response::DTCSnapshotRecordByDTCNumber {
+                    number,
+                    number_of_identifier: records.len() as u8,
+                    records,
+                })
+                .collect();
+
+            Ok(response::DTCInfo::ReportDTCSnapshotRecordByDTCNumber {
+                status_record: response::DTCAndStatusRecord {
+                    dtc: record.dtc,
+                    status: record.status,
+                },
+                records,
+            })
+        }
+        #[cfg(feature = "std2006")]
+        request::DTCInfo::ReportDTCSnapshotRecordByRecordNumber { record_num } => {
+            if record_num == 0xFF {
+                return Err(Code::RequestOutOfRange);
+            }
+
+            let mut snapshots = Vec::new();
+            for record in records.iter().filter(|record| record.user_memory.is_none()) {
+                for (_, data) in selected_snapshots(record, record_num)? {
+                    snapshots.push(response::DTCSnapshotRecordByRecordNumber {
+                        status_record: Some(response::DTCAndStatusRecord {
+                            dtc: record.dtc,
+                            status: record.status,
+                        }),
+                        number_of_identifier: Some(data.len() as u8),
+                        records: data,
+                    });
+                }
+            }
+
+            Ok(response::DTCInfo::ReportDTCSnapshotRecordByRecordNumber {
+                number: record_num,
+                records: snapshots,
+            })
+        }
+        #[cfg(any(feature = "std2013", feature = "std2020"))]
+        request::DTCInfo::ReportDTCStoredDataByRecordNumber { stored_num } => {
+            // the stored data records are the snapshot records of primary memory
+            let mut stored = Vec::new();
+            for record in records.iter().filter(|record| record.user_memory.is_none()) {
+                for (number, data) in selected_snapshots(record, stored_num)? {
+                    stored.push(response::ReportDTCStoredDataByRecord {
+                        number,
+                        record: Some(response::DTCAndStatusRecord {
+                            dtc: record.dtc,
+                            status: record.status,
+                        }),
+                        number_of_identifier: Some(data.len() as u8),
+                        records: data
+                            .into_iter()
+                            .map(|v| response::DTCStoredDataRecord {
+                                did: v.did,
+                                data: v.data,
+                            })
+                            .collect(),
+                    });
+                }
+            }
+            stored.sort_by_key(|v| v.number);
+
+            if stored.is_empty() && stored_num != 0xFF {
+                stored.push(response::ReportDTCStoredDataByRecord {
+                    number: stored_num,
+                    record: None,
+                    number_of_identifier: None,
+                    records: vec![],
+                });
+            }
+
+            Ok(response::DTCInfo::ReportDTCStoredDataByRecordNumber { records: stored })
+        }
         request::DTCInfo::ReportDTCExtDataRecordByDTCNumber {
             mask_record,
             extra_num,
@@ -428,14 +550,33 @@ fn build_read_dtc_response(
         // } => {
         //     todo!()
         // }
-        // #[cfg(any(feature = "std2013", feature = "std2020"))]
-        // request::DTCInfo::ReportUserDefMemoryDTCSnapshotRecordByDTCNumber {
-        //     mask_record: _,
-        //     record_num: _,
-        //     mem_selection: _,
-        // } => {
-        //     todo!()
-        // }
+        #[cfg(any(feature = "std2013", feature = "std2020"))]
+        request::DTCInfo::ReportUserDefMemoryDTCSnapshotRecordByDTCNumber {
+            mask_record,
+            record_num,
+            mem_selection,
+        } => {
+            let record = memory_record(records, mask_record, Some(mem_selection))?;
+            let records = selected_snapshots(record, record_num)?
+                .into_iter()
+                .map(|(number, records)| response::UserDefDTCSnapshotRecord {
+                    number,
+                    number_of_identifier: records.len() as u8,
+                    records,
+                })
+                .collect();
+
+            Ok(
+                response::DTCInfo::ReportUserDefMemoryDTCSnapshotRecordByDTCNumber {
+                    mem_selection,
+                    status_record: response::DTCAndStatusRecord {
+                        dtc: record.dtc,
+                        status: record.status,
+                    },
+                    records,
+                },
+            )
+        }
         // #[cfg(any(feature = "std2013", feature = "std2020"))]
         // request::DTCInfo::ReportUserDefMemoryDTCExtDataRecordByDTCNumber {
         //     mask_record: _,
@@ -562,6 +703,7 @@ fn build_read_dtc_response(
         // } => {
         //     todo!()
         // }
+        #[allow(unreachable_patterns)]
         _ => Err(Code::SubFunctionNotSupported),
     }
 }
@@ -584,9 +726,6 @@ where
                 Some(sf) => {
                     match build_read_dtc_response(ctx, &self.context.dtc_records().await, cfg) {
                         Ok(data) => {
-                            if sf.is_suppress_positive() {
-                                return Ok(());
-                            }
                             Response::new(service, Some(u8::from(sf)), Vec::<u8>::from(data), cfg)?
                         }
                         Err(code) => Response::new_negative(service, code),
@@ -596,7 +735,7 @@ where
                     Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
                 }
             },
-            Err(_) => Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat),
+            Err(e) => Response::new_negative(service, error_code(&e)),
         };
 
         self.transmit_response(resp, true).await;
@@ -609,7 +748,9 @@ where
 mod tests {
     use super::{build_read_dtc_response, DtcRecord};
     use crate::server::context::Context;
-    use iso14229_1::{request, response, response::Code, utils::U24, Configuration};
+    use iso14229_1::{
+        request, response, response::Code, utils::U24, Configuration, DataIdentifier, Service,
+    };
 
     fn sample_record(dtc: u32, status: u8) -> DtcRecord {
         DtcRecord {
@@ -620,12 +761,32 @@ mod tests {
             fault_counter: 2,
             permanent: true,
             ext_data: vec![(0x02, vec![0xAA, 0xBB])],
+            snapshots: vec![],
+            user_memory: None,
             mirror: false,
             emissions_obd: false,
             wwh_obd: None,
+            monitor: Default::default(),
         }
     }
 
+    fn snapshot_record(dtc: u32, user_memory: Option<u8>) -> DtcRecord {
+        let did = DataIdentifier::from(0x4101);
+        let mut record = sample_record(dtc, 0x08);
+        record.snapshots = vec![
+            (0x01, vec![(did, vec![0x01, 0x02])]),
+            (0x02, vec![(did, vec![0x03, 0x04])]),
+        ];
+        record.user_memory = user_memory;
+        record
+    }
+
+    fn snapshot_cfg() -> Configuration {
+        let mut cfg = Configuration::default();
+        cfg.did.insert(DataIdentifier::from(0x4101), 2);
+        cfg
+    }
+
     #[tokio::test]
     async fn read_clear_read_dtc_flow() {
         let ctx = Context::new().await.unwrap();
@@ -819,9 +980,12 @@ mod tests {
             fault_counter: 2,
             permanent: true,
             ext_data: vec![(0x02, vec![0xAA, 0xBB])],
+            snapshots: vec![],
+            user_memory: None,
             mirror: true,
             emissions_obd: true,
             wwh_obd: None,
+            monitor: Default::default(),
         }];
 
         let mirror_resp = build_read_dtc_response(
@@ -869,9 +1033,12 @@ mod tests {
                 fault_counter: 1,
                 permanent: false,
                 ext_data: vec![(0x02, vec![0xCC, 0xDD])],
+                snapshots: vec![],
+                user_memory: None,
                 mirror: false,
                 emissions_obd: false,
                 wwh_obd: None,
+                monitor: Default::default(),
             },
         ];
 
@@ -965,9 +1132,12 @@ mod tests {
                 fault_counter: 1,
                 permanent: false,
                 ext_data: vec![(0x04, vec![0x01, 0x02, 0x03, 0x04])],
+                snapshots: vec![],
+                user_memory: None,
                 mirror: false,
                 emissions_obd: false,
                 wwh_obd: None,
+                monitor: Default::default(),
             },
         ];
 
@@ -1017,4 +1187,238 @@ mod tests {
             _ => panic!("unexpected response variant"),
         }
     }
+
+    #[test]
+    fn snapshot_identification_lists_primary_memory_records() {
+        let records = vec![
+            snapshot_record(0x112233, None),
+            snapshot_record(0x445566, Some(0x01)),
+            sample_record(0x778899, 0x08),
+        ];
+        let resp = build_read_dtc_response(
+            request::DTCInfo::ReportDTCSnapshotIdentification,
+            &records,
+            &snapshot_cfg(),
+        )
+        .unwrap();
+
+        match resp {
+            response::DTCInfo::ReportDTCSnapshotIdentification { records } => {
+                assert_eq!(
+                    records,
+                    vec![
+                        response::DTCSnapshotIdentification {
+                            dtc: U24::new(0x112233),
+                            number: 0x01,
+                        },
+                        response::DTCSnapshotIdentification {
+                            dtc: U24::new(0x112233),
+                            number: 0x02,
+                        },
+                    ]
+                );
+            }
+            _ => panic!("unexpected response variant"),
+        }
+    }
+
+    #[test]
+    fn snapshot_record_by_dtc_number_is_decodable() {
+        let cfg = snapshot_cfg();
+        let records = vec![snapshot_record(0x112233, None)];
+        let resp = build_read_dtc_response(
+            request::DTCInfo::ReportDTCSnapshotRecordByDTCNumber {
+                mask_record: U24::new(0x112233),
+                record_num: 0xFF,
+            },
+            &records,
+            &cfg,
+        )
+        .unwrap();
+
+        let encoded = response::Response::new(
+            Service::ReadDTCInfo,
+            Some(0x04),
+            Vec::<u8>::from(resp.clone()),
+            &cfg,
+        )
+        .unwrap();
+        assert_eq!(encoded.data::<response::DTCInfo>(&cfg).unwrap(), resp);
+
+        match resp {
+            response::DTCInfo::ReportDTCSnapshotRecordByDTCNumber {
+                status_record,
+                records,
+            } => {
+                assert_eq!(status_record.dtc, U24::new(0x112233));
+                assert_eq!(records.len(), 2);
+                assert_eq!(records[1].number, 0x02);
+                assert_eq!(records[1].number_of_identifier, 1);
+                assert_eq!(records[1].records[0].data, vec![0x03, 0x04]);
+            }
+            _ => panic!("unexpected response variant"),
+        }
+
+        let resp = build_read_dtc_response(
+            request::DTCInfo::ReportDTCSnapshotRecordByDTCNumber {
+                mask_record: U24::new(0x112233),
+                record_num: 0x03,
+            },
+            &records,
+            &cfg,
+        )
+        .unwrap();
+        match resp {
+            response::DTCInfo::ReportDTCSnapshotRecordByDTCNumber { records, .. } => {
+                assert!(records.is_empty());
+            }
+            _ => panic!("unexpected response variant"),
+        }
+    }
+
+    #[test]
+    fn snapshot_record_by_dtc_number_rejects_unknown_dtc_and_record_zero() {
+        let records = vec![snapshot_record(0x112233, Some(0x01))];
+        for (dtc, record_num) in [(0x112233, 0x01), (0x445566, 0x01)] {
+            let err = build_read_dtc_response(
+                request::DTCInfo::ReportDTCSnapshotRecordByDTCNumber {
+                    mask_record: U24::new(dtc),
+                    record_num,
+                },
+                &records,
+                &snapshot_cfg(),
+            )
+            .unwrap_err();
+            assert_eq!(err, Code::RequestOutOfRange);
+        }
+
+        let records = vec![snapshot_record(0x112233, None)];
+        let err = build_read_dtc_response(
+            request::DTCInfo::ReportDTCSnapshotRecordByDTCNumber {
+                mask_record: U24::new(0x112233),
+                record_num: 0x00,
+            },
+            &records,
+            &snapshot_cfg(),
+        )
+        .unwrap_err();
+        assert_eq!(err, Code::RequestOutOfRange);
+    }
+
+    #[cfg(feature = "std2006")]
+    #[test]
+    fn snapshot_record_by_record_number_returns_matching_records() {
+        let records = vec![
+            snapshot_record(0x112233, None),
+            snapshot_record(0x445566, None),
+        ];
+        let resp = build_read_dtc_response(
+            request::DTCInfo::ReportDTCSnapshotRecordByRecordNumber { record_num: 0x02 },
+            &records,
+            &snapshot_cfg(),
+        )
+        .unwrap();
+
+        match resp {
+            response::DTCInfo::ReportDTCSnapshotRecordByRecordNumber { number, records } => {
+                assert_eq!(number, 0x02);
+                assert_eq!(records.len(), 2);
+                assert_eq!(records[1].records[0].data, vec![0x03, 0x04]);
+            }
+            _ => panic!("unexpected response variant"),
+        }
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[test]
+    fn stored_data_by_record_number_returns_snapshot_records() {
+        let cfg = snapshot_cfg();
+        let records = vec![
+            snapshot_record(0x112233, None),
+            snapshot_record(0x445566, None),
+        ];
+        let resp = build_read_dtc_response(
+            request::DTCInfo::ReportDTCStoredDataByRecordNumber { stored_num: 0xFF },
+            &records,
+            &cfg,
+        )
+        .unwrap();
+
+        let encoded = response::Response::new(
+            Service::ReadDTCInfo,
+            Some(0x05),
+            Vec::<u8>::from(resp.clone()),
+            &cfg,
+        )
+        .unwrap();
+        assert_eq!(encoded.data::<response::DTCInfo>(&cfg).unwrap(), resp);
+
+        match resp {
+            response::DTCInfo::ReportDTCStoredDataByRecordNumber { records } => {
+                let numbers = records.iter().map(|v| v.number).collect::<Vec<_>>();
+                assert_eq!(numbers, vec![0x01, 0x01, 0x02, 0x02]);
+                assert_eq!(records[2].records[0].data, vec![0x03, 0x04]);
+            }
+            _ => panic!("unexpected response variant"),
+        }
+
+        let resp = build_read_dtc_response(
+            request::DTCInfo::ReportDTCStoredDataByRecordNumber { stored_num: 0x03 },
+            &records,
+            &cfg,
+        )
+        .unwrap();
+        match resp {
+            response::DTCInfo::ReportDTCStoredDataByRecordNumber { records } => {
+                assert_eq!(records.len(), 1);
+                assert_eq!(records[0].number, 0x03);
+                assert_eq!(records[0].record, None);
+            }
+            _ => panic!("unexpected response variant"),
+        }
+    }
+
+    #[cfg(any(feature = "std2013", feature = "std2020"))]
+    #[test]
+    fn user_def_memory_snapshot_record_selects_memory() {
+        let records = vec![
+            snapshot_record(0x112233, None),
+            snapshot_record(0x112233, Some(0x01)),
+        ];
+        let resp = build_read_dtc_response(
+            request::DTCInfo::ReportUserDefMemoryDTCSnapshotRecordByDTCNumber {
+                mask_record: U24::new(0x112233),
+                record_num: 0x01,
+                mem_selection: 0x01,
+            },
+            &records,
+            &snapshot_cfg(),
+        )
+        .unwrap();
+
+        match resp {
+            response::DTCInfo::ReportUserDefMemoryDTCSnapshotRecordByDTCNumber {
+                mem_selection,
+                records,
+                ..
+            } => {
+                assert_eq!(mem_selection, 0x01);
+                assert_eq!(records.len(), 1);
+                assert_eq!(records[0].records[0].data, vec![0x01, 0x02]);
+            }
+            _ => panic!("unexpected response variant"),
+        }
+
+        let err = build_read_dtc_response(
+            request::DTCInfo::ReportUserDefMemoryDTCSnapshotRecordByDTCNumber {
+                mask_record: U24::new(0x112233),
+                record_num: 0x01,
+                mem_selection: 0x02,
+            },
+            &records,
+            &snapshot_cfg(),
+        )
+        .unwrap_err();
+        assert_eq!(err, Code::RequestOutOfRange);
+    }
 }
@@ -1,9 +1,12 @@
 //! response of Service 24
 
-use crate::server::DoCanServer;
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{did::DidAccessType, request::error_code, DoCanServer},
+};
 use iso14229_1::{
-    request::Request,
-    response::{Code, Response},
+    request::{ReadScalingDID, Request},
+    response::Response,
     Configuration, Iso14229Error,
 };
 use rs_can::{CanDevice, CanFrame};
@@ -18,11 +21,39 @@ where
     pub(crate) async fn read_scaling_did(
         &self,
         req: Request,
-        _cfg: &Configuration,
+        cfg: &Configuration,
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = Response::new_negative(service, Code::ServiceNotSupported);
+        let resp = match req.data::<ReadScalingDID>(cfg) {
+            Ok(ctx) => {
+                let did = ctx.0;
+                let session = self.session.get_session_type().await;
+                let sa_level = self.session.get_security_access_level().await;
+                match self
+                    .context
+                    .check_did_access(&did, DidAccessType::Read, session, sa_level)
+                    .await
+                {
+                    Err(code) => Response::new_negative(service, code),
+                    Ok(_) => match self.context.read_scaling_did(&did) {
+                        Ok(data) => Response::new(service, None, data, cfg)?,
+                        Err(code) => {
+                            rsutil::warn!(
+                                "{} DID: {:?} has no scaling record",
+                                LOG_TAG_SERVER,
+                                did
+                            );
+                            Response::new_negative(service, code)
+                        }
+                    },
+                }
+            }
+            Err(e) => {
+                rsutil::warn!("{} Failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                Response::new_negative(service, error_code(&e))
+            }
+        };
 
         self.transmit_response(resp, true).await;
 
@@ -1,10 +1,13 @@
 //! response of Service 38
 
-use crate::server::DoCanServer;
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
-    request::Request,
+    request::{self, Request},
     response::{Code, Response},
-    Configuration, Iso14229Error,
+    Configuration, Iso14229Error, ModeOfOperation,
 };
 use rs_can::{CanDevice, CanFrame};
 use std::fmt::Display;
@@ -18,14 +21,45 @@ where
     pub(crate) async fn request_file_transfer(
         &self,
         req: Request,
-        _cfg: &Configuration,
+        cfg: &Configuration,
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = if self.session.get_session_type().await == Default::default() {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            Response::new_negative(service, Code::ServiceNotSupported)
+        let resp = match req.sub_function() {
+            // the modeOfOperation is a parameter, the bit 7 isn't suppressPosRspMsgIndicationBit
+            Some(sf) if sf.is_suppress_positive() => {
+                Response::new_negative(service, Code::RequestOutOfRange)
+            }
+            Some(sf) => match sf.function::<ModeOfOperation>() {
+                Ok(mode) => match req.data::<request::RequestFileTransfer>(cfg) {
+                    Ok(ctx) => match self.context.request_file_transfer(ctx).await {
+                        // the positive response of DeleteFile is SID and modeOfOperation only,
+                        // which the codec can't construct
+                        Ok(_) if mode == ModeOfOperation::DeleteFile => {
+                            let data = vec![service as u8 | 0x40, mode.into()];
+                            if let Err(e) = self.transmit(data).await {
+                                rsutil::warn!("{} transmit error: {:?}", LOG_TAG_SERVER, e);
+                            }
+                            return Ok(());
+                        }
+                        Ok(data) => Response::new(service, Some(mode.into()), data, cfg)?,
+                        Err(code) => Response::new_negative(service, code),
+                    },
+                    Err(e) => {
+                        rsutil::warn!("{} Failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                        Response::new_negative(service, error_code(&e))
+                    }
+                },
+                Err(e) => {
+                    rsutil::warn!(
+                        "{} Failed to parse modeOfOperation: {:?}",
+                        LOG_TAG_SERVER,
+                        e
+                    );
+                    Response::new_negative(service, Code::RequestOutOfRange)
+                }
+            },
+            None => Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat),
         };
 
         self.transmit_response(resp, true).await;
@@ -1,9 +1,12 @@
 //! response of Service 37
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{self, Request},
-    response::{Code, Response},
+    response::Response,
     Configuration, Iso14229Error,
 };
 use rs_can::{CanDevice, CanFrame};
@@ -22,18 +25,14 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = if self.session.get_session_type().await == Default::default() {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            match req.data::<request::RequestTransferExit>(cfg) {
-                Ok(ctx) => match self.context.request_transfer_exit(&ctx.data).await {
-                    Ok(data) => Response::new(service, None, Vec::<u8>::from(data), cfg)?,
-                    Err(code) => Response::new_negative(service, code),
-                },
-                Err(e) => {
-                    rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
-                    Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
-                }
+        let resp = match req.data::<request::RequestTransferExit>(cfg) {
+            Ok(ctx) => match self.context.request_transfer_exit(&ctx.data).await {
+                Ok(data) => Response::new(service, None, Vec::<u8>::from(data), cfg)?,
+                Err(code) => Response::new_negative(service, code),
+            },
+            Err(e) => {
+                rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                Response::new_negative(service, error_code(&e))
             }
         };
 
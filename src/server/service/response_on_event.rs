@@ -1,13 +1,16 @@
 //! response of Service 86
 
-use crate::server::DoCanServer;
-use iso14229_1::{
-    request::Request,
-    response::{Code, Response},
-    Configuration, Iso14229Error,
+use crate::{
+    constants::{LOG_TAG_SERVER, ROE_INTERVAL_MS},
+    server::{context::RoeAction, DoCanServer},
 };
+use iso14229_1::{request::Request, response::Response, Configuration, Iso14229Error, Service};
 use rs_can::{CanDevice, CanFrame};
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+use tokio::time::interval;
 
 impl<D, C, F> DoCanServer<D, C, F>
 where
@@ -18,14 +21,61 @@ where
     pub(crate) async fn response_on_event(
         &self,
         req: Request,
-        _cfg: &Configuration,
+        cfg: &Configuration,
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
+        let data = req.raw_data();
+        let session = self.session.get_session_type().await;
 
-        let resp = Response::new_negative(service, Code::ServiceNotSupported);
+        let resp = match self
+            .context
+            .response_on_event(session, data, Instant::now())
+            .await
+        {
+            Ok(resp_data) => Response::new(service, None, resp_data, cfg)?,
+            Err(code) => Response::new_negative(service, code),
+        };
 
         self.transmit_response(resp, true).await;
 
         Ok(())
     }
+
+    /// The event engine of ResponseOnEvent, the serviceToRespondToRecord is executed when the event fires.
+    pub(crate) async fn response_on_event_forever(&mut self) {
+        let mut interval = interval(Duration::from_millis(ROE_INTERVAL_MS));
+
+        loop {
+            interval.tick().await;
+
+            let session = self.session.get_session_type().await;
+            for action in self
+                .context
+                .poll_response_on_event(session, Instant::now())
+                .await
+            {
+                match action {
+                    RoeAction::Respond(data) => {
+                        rsutil::debug!(
+                            "{} ResponseOnEvent respond: {}",
+                            LOG_TAG_SERVER,
+                            hex::encode(&data)
+                        );
+                        self.process_event_request(&data).await;
+                    }
+                    RoeAction::WindowClosed(data) => {
+                        let cfg = self.context.get_cfg().clone();
+                        match Response::new(Service::ResponseOnEvent, None, data, &cfg) {
+                            Ok(resp) => self.transmit_response(resp, false).await,
+                            Err(e) => rsutil::warn!(
+                                "{} can't make ResponseOnEvent response: {:?}",
+                                LOG_TAG_SERVER,
+                                e
+                            ),
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
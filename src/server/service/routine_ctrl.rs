@@ -1,10 +1,13 @@
 //! response of Service 31
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{Request, RoutineCtrl},
     response::{Code, Response},
-    Configuration, Iso14229Error, RoutineCtrlType, SessionType,
+    Configuration, Iso14229Error, RoutineCtrlType,
 };
 use rs_can::{CanDevice, CanFrame};
 use std::fmt::Display;
@@ -22,48 +25,40 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
 
-        let resp = if self.session.get_session_type().await == SessionType::Default {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            match req.data::<RoutineCtrl>(cfg) {
-                Ok(val) => match req.sub_function() {
-                    Some(sf) => match sf.function::<RoutineCtrlType>() {
-                        Ok(r#type) => match self
-                            .context
-                            .routine_ctrl(r#type, val.routine_id, &val.option_record)
-                            .await
-                        {
-                            Ok(result) => {
-                                if sf.is_suppress_positive() {
-                                    return Ok(());
-                                } else {
-                                    Response::new(
-                                        service,
-                                        Some(r#type.into()),
-                                        Vec::<u8>::from(result),
-                                        cfg,
-                                    )?
-                                }
-                            }
-                            Err(code) => Response::new_negative(service, code),
-                        },
-                        Err(e) => {
-                            rsutil::warn!(
-                                "{} Failed to parse sub-function: {:?}",
-                                LOG_TAG_SERVER,
-                                e
-                            );
-                            Response::new_negative(service, Code::SubFunctionNotSupported)
-                        }
+        let resp = match req.data::<RoutineCtrl>(cfg) {
+            Ok(val) => match req.sub_function() {
+                Some(sf) => match sf.function::<RoutineCtrlType>() {
+                    Ok(r#type) => match self
+                        .context
+                        .routine_ctrl(
+                            r#type,
+                            val.routine_id,
+                            &val.option_record,
+                            self.session.get_session_type().await,
+                            self.session.get_security_access_level().await,
+                        )
+                        .await
+                    {
+                        Ok(result) => Response::new(
+                            service,
+                            Some(r#type.into()),
+                            Vec::<u8>::from(result),
+                            cfg,
+                        )?,
+                        Err(code) => Response::new_negative(service, code),
                     },
-                    None => {
-                        Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
+                    Err(e) => {
+                        rsutil::warn!("{} Failed to parse sub-function: {:?}", LOG_TAG_SERVER, e);
+                        Response::new_negative(service, Code::SubFunctionNotSupported)
                     }
                 },
-                Err(e) => {
-                    rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                None => {
                     Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
                 }
+            },
+            Err(e) => {
+                rsutil::warn!("{} failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                Response::new_negative(service, error_code(&e))
             }
         };
 
@@ -1,11 +1,7 @@
 //! response of Service 84
 
 use crate::server::DoCanServer;
-use iso14229_1::{
-    request::Request,
-    response::{Code, Response},
-    Configuration, Iso14229Error,
-};
+use iso14229_1::{request::Request, response::Response, Configuration, Iso14229Error};
 use rs_can::{CanDevice, CanFrame};
 use std::fmt::Display;
 
@@ -18,18 +14,66 @@ where
     pub(crate) async fn secured_data_trans(
         &self,
         req: Request,
-        _cfg: &Configuration,
+        cfg: &Configuration,
     ) -> Result<(), Iso14229Error> {
-        let service = req.service();
-
-        let resp = if self.session.get_session_type().await == Default::default() {
-            Response::new_negative(service, Code::ServiceNotSupportedInActiveSession)
-        } else {
-            Response::new_negative(service, Code::ServiceNotSupported)
+        let resp = match self.secured_response(req, cfg).await? {
+            Some(resp) => resp,
+            None => return Ok(()),
         };
 
         self.transmit_response(resp, true).await;
 
         Ok(())
     }
+
+    /// Unwrap the secured request, dispatch the internal request and wrap its response.
+    #[cfg(feature = "std2020")]
+    async fn secured_response(
+        &self,
+        req: Request,
+        cfg: &Configuration,
+    ) -> Result<Option<Response>, Iso14229Error> {
+        use crate::{constants::LOG_TAG_SERVER, server::request::error_code};
+        use iso14229_1::request;
+
+        let service = req.service();
+        let session = self.session.get_session_type().await;
+        let ctx = match req.data::<request::SecuredDataTrans>(cfg) {
+            Ok(v) => v,
+            Err(e) => {
+                rsutil::warn!("{} Failed to parse request data: {:?}", LOG_TAG_SERVER, e);
+                return Ok(Some(Response::new_negative(service, error_code(&e))));
+            }
+        };
+
+        let data = match self.context.secured_data_unwrap(session, &ctx).await {
+            Ok(v) => v,
+            Err(code) => return Ok(Some(Response::new_negative(service, code))),
+        };
+        // the positive response of internal request is suppressed
+        let Some(data) = self.process_secured_request(&data).await else {
+            return Ok(None);
+        };
+
+        let resp = match self.context.secured_data_wrap(session, &ctx, data).await {
+            Ok(data) => Response::new::<Vec<_>>(service, None, data.into(), cfg)?,
+            Err(code) => Response::new_negative(service, code),
+        };
+
+        Ok(Some(resp))
+    }
+
+    #[cfg(not(feature = "std2020"))]
+    async fn secured_response(
+        &self,
+        req: Request,
+        _cfg: &Configuration,
+    ) -> Result<Option<Response>, Iso14229Error> {
+        use iso14229_1::response::Code;
+
+        Ok(Some(Response::new_negative(
+            req.service(),
+            Code::ServiceNotSupported,
+        )))
+    }
 }
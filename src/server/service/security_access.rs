@@ -1,17 +1,13 @@
 //! response of Service 27
 
-use crate::{
-    constants::LOG_TAG_SERVER,
-    server::{util, DoCanServer},
-};
-use bytes::Bytes;
+use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
 use iso14229_1::{
     request::Request,
     response::{Code, Response},
     Configuration, Iso14229Error, SecurityAccessLevel,
 };
 use rs_can::{CanDevice, CanFrame};
-use std::fmt::Display;
+use std::{fmt::Display, time::Instant};
 
 impl<D, C, F> DoCanServer<D, C, F>
 where
@@ -28,72 +24,34 @@ where
         let resp = match req.sub_function() {
             Some(sf) => match sf.function::<SecurityAccessLevel>() {
                 Ok(v) => {
-                    let mut guard = self.context.sa_ctx.lock().await;
+                    let level: u8 = v.into();
+                    let now = Instant::now();
                     if v.is_request_seed() {
-                        let data = util::gen_seed(self.context.config.seed_len);
-                        let resp = Response::new(service, Some(v.into()), &data, cfg)?;
-                        let _ = guard.replace((v.into(), Bytes::from(data)));
-
-                        resp
+                        let session = self.session.get_session_type().await;
+                        let unlocked = self.session.get_security_access_level().await;
+                        match self
+                            .context
+                            .security_seed(level, session, unlocked, now)
+                            .await
+                        {
+                            Ok(seed) => Response::new(service, Some(level), seed, cfg)?,
+                            Err(code) => Response::new_negative(service, code),
+                        }
                     } else {
-                        match guard.take() {
-                            Some(ctx) => {
-                                let level: u8 = v.into();
-                                if level - 1 != ctx.0 {
-                                    Response::new_negative(service, Code::ConditionsNotCorrect)
-                                } else {
-                                    match self.context.get_security_algo().await {
-                                        Some(algo) => {
-                                            let level = ctx.0;
-                                            let response_level: u8 = v.into();
-                                            let seed = ctx.1.as_ref();
-                                            let salt = self.context.get_security_salt();
-                                            match algo(level, seed, salt.as_ref()) {
-                                                Ok(v) => match v {
-                                                    Some(v) => {
-                                                        if req.raw_data() != v.as_slice() {
-                                                            Response::new_negative(
-                                                                service,
-                                                                Code::InvalidKey,
-                                                            )
-                                                        } else {
-                                                            self.session
-                                                                .set_security_access_level(level)
-                                                                .await;
-                                                            Response::new(
-                                                                service,
-                                                                Some(response_level),
-                                                                vec![],
-                                                                cfg,
-                                                            )?
-                                                        }
-                                                    }
-                                                    None => Response::new_negative(
-                                                        service,
-                                                        Code::SecurityAccessDenied,
-                                                    ),
-                                                },
-                                                Err(e) => {
-                                                    rsutil::warn!(
-                                                        "{} error: {} when calculator sa key",
-                                                        LOG_TAG_SERVER,
-                                                        e
-                                                    );
-                                                    Response::new_negative(
-                                                        service,
-                                                        Code::GeneralReject,
-                                                    )
-                                                }
-                                            }
-                                        }
-                                        None => Response::new_negative(
-                                            service,
-                                            Code::ConditionsNotCorrect,
-                                        ),
-                                    }
-                                }
+                        match self.context.security_key(level, req.raw_data(), now).await {
+                            Ok(unlocked) => {
+                                self.session.set_security_access_level(unlocked).await;
+                                Response::new(service, Some(level), vec![], cfg)?
+                            }
+                            Err(code) => {
+                                rsutil::warn!(
+                                    "{} SecurityAccess level: {} is rejected: {:?}",
+                                    LOG_TAG_SERVER,
+                                    level,
+                                    code
+                                );
+                                Response::new_negative(service, code)
                             }
-                            None => Response::new_negative(service, Code::SubFunctionNotSupported),
                         }
                     }
                 }
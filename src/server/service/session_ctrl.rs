@@ -19,31 +19,41 @@ where
         &mut self,
         req: Request,
         cfg: &Configuration,
-        data: Vec<u8>,
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
         let resp = match req.sub_function() {
-            Some(sf) => {
-                match sf.function::<SessionType>() {
-                    Ok(r#type) => {
-                        self.session.change(r#type).await;
-                        if r#type != Default::default() {
-                            self.session.keep().await;
-                        }
+            Some(sf) => match sf.function::<SessionType>() {
+                Ok(r#type) => {
+                    let from = self.session.get_session_type().await;
+                    match self.context.check_session_transition(from, r#type) {
+                        Ok(reset) => {
+                            if reset {
+                                rsutil::info!(
+                                    "{} session: {:?} is exited with reset",
+                                    LOG_TAG_SERVER,
+                                    from
+                                );
+                                self.context.reset().await;
+                                self.session.reset().await;
+                            }
+                            self.session.change(r#type).await;
+                            if r#type != Default::default() {
+                                self.session.keep().await;
+                            }
+                            self.context.enter_session(r#type).await;
 
-                        if sf.is_suppress_positive() {
-                            return Ok(()); // suppress positive
-                        } else {
-                            Response::new(service, Some(r#type.into()), data, cfg)?
+                            let timing: Vec<_> = self.context.get_active_timing().await.into();
+                            Response::new(service, Some(r#type.into()), timing, cfg)?
                         }
+                        Err(code) => Response::new_negative(service, code),
                     }
-                    Err(e) => {
-                        rsutil::warn!("{} failed to parse sub-function: {:?}", LOG_TAG_SERVER, e);
-                        Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat)
-                    }
                 }
-            }
-            None => Response::new_negative(service, Code::GeneralReject),
+                Err(e) => {
+                    rsutil::warn!("{} failed to parse sub-function: {:?}", LOG_TAG_SERVER, e);
+                    Response::new_negative(service, Code::SubFunctionNotSupported)
+                }
+            },
+            None => Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat),
         };
 
         self.transmit_response(resp, true).await;
@@ -22,32 +22,19 @@ where
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
         let resp = match req.sub_function() {
-            Some(sf) => {
-                if sf.is_suppress_positive() {
+            Some(sf) => match sf.function::<TesterPresentType>() {
+                Ok(r#type) => {
                     if self.session.get_session_type().await != Default::default() {
                         self.session.keep().await;
                     }
-                    return Ok(()); // suppress positive
-                } else {
-                    match sf.function::<TesterPresentType>() {
-                        Ok(r#type) => {
-                            if self.session.get_session_type().await != Default::default() {
-                                self.session.keep().await;
-                            }
-                            Response::new(service, Some(r#type.into()), vec![], cfg)?
-                        }
-                        Err(e) => {
-                            rsutil::warn!(
-                                "{} Failed to parse sub-function: {:?}",
-                                LOG_TAG_SERVER,
-                                e
-                            );
-                            Response::new_negative(service, Code::SubFunctionNotSupported)
-                        }
-                    }
+                    Response::new(service, Some(r#type.into()), vec![], cfg)?
+                }
+                Err(e) => {
+                    rsutil::warn!("{} Failed to parse sub-function: {:?}", LOG_TAG_SERVER, e);
+                    Response::new_negative(service, Code::SubFunctionNotSupported)
                 }
-            }
-            None => Response::new_negative(service, Code::GeneralReject),
+            },
+            None => Response::new_negative(service, Code::IncorrectMessageLengthOrInvalidFormat),
         };
 
         self.transmit_response(resp, true).await;
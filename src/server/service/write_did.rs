@@ -1,10 +1,13 @@
 //! response of Service 2E
 
-use crate::{constants::LOG_TAG_SERVER, server::DoCanServer};
+use crate::{
+    constants::LOG_TAG_SERVER,
+    server::{did::DidAccessType, request::error_code, DoCanServer},
+};
 use iso14229_1::{
     request::{Request, WriteDID},
     response::{Code, Response},
-    Configuration, Iso14229Error, SessionType,
+    Configuration, Iso14229Error,
 };
 use rs_can::{CanDevice, CanFrame};
 use std::fmt::Display;
@@ -21,38 +24,35 @@ where
         cfg: &Configuration,
     ) -> Result<(), Iso14229Error> {
         let service = req.service();
-        let resp = match self.session.get_session_type().await {
-            SessionType::Extended => {
+        let resp = match req.data::<WriteDID>(cfg) {
+            Ok(ctx) => {
+                let did = ctx.0.did;
+                let session = self.session.get_session_type().await;
                 let sa_level = self.session.get_security_access_level().await;
-                if self.context.config.extend_sa_level != sa_level {
-                    // security access denied
-                    Response::new_negative(service, Code::SecurityAccessDenied)
-                } else {
-                    match req.data::<WriteDID>(cfg) {
-                        Ok(ctx) => {
-                            let did = ctx.0.did;
-                            if self.context.set_static_did(&did, ctx.0.data).await {
-                                let data: u16 = did.into();
-                                Response::try_from((service, data.to_be_bytes(), cfg))?
-                            } else {
-                                Response::new_negative(service, Code::GeneralReject)
-                            }
-                        }
-                        Err(e) => {
-                            rsutil::warn!(
-                                "{} can't parse did context from data: {}",
-                                LOG_TAG_SERVER,
-                                e
-                            );
-                            Response::new_negative(
-                                service,
-                                Code::IncorrectMessageLengthOrInvalidFormat,
-                            )
+                match self
+                    .context
+                    .check_did_access(&did, DidAccessType::Write, session, sa_level)
+                    .await
+                {
+                    Ok(_) => {
+                        if self.context.set_static_did(&did, ctx.0.data).await {
+                            let data: u16 = did.into();
+                            Response::try_from((service, data.to_be_bytes(), cfg))?
+                        } else {
+                            Response::new_negative(service, Code::GeneralReject)
                         }
                     }
+                    Err(code) => Response::new_negative(service, code),
                 }
             }
-            _ => Response::new_negative(service, Code::ServiceNotSupportedInActiveSession),
+            Err(e) => {
+                rsutil::warn!(
+                    "{} can't parse did context from data: {}",
+                    LOG_TAG_SERVER,
+                    e
+                );
+                Response::new_negative(service, error_code(&e))
+            }
         };
 
         self.transmit_response(resp, true).await;
@@ -3,10 +3,29 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
-use tokio::{sync::Mutex, time::interval};
+use tokio::{
+    sync::{broadcast, Mutex, Notify},
+    time::timeout_at,
+};
+
+/// The capacity of session event channel, the oldest events are dropped for the slow receiver.
+const SESSION_EVENT_CAPACITY: usize = 16;
+
+/// The event of diagnostic session.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SessionEvent {
+    Changed {
+        from: SessionType,
+        to: SessionType,
+    },
+    /// the non-default session is exited because the S3 timer is expired
+    Timeout {
+        session: SessionType,
+    },
+}
 
 /// Session manager.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub(crate) struct SessionManager {
     /// current session type
     pub(crate) r#type: Arc<Mutex<SessionType>>,
@@ -16,16 +35,29 @@ pub(crate) struct SessionManager {
     pub(crate) duration: Duration,
     pub(crate) sa_level: Arc<Mutex<u8>>,
     pub(crate) link_ctrl_verified: Arc<Mutex<bool>>,
+    /// wake up the S3 timer when the start timestamp is changed
+    pub(crate) notify: Arc<Notify>,
+    pub(crate) events: broadcast::Sender<SessionEvent>,
 }
 
 impl SessionManager {
-    pub fn new(second: Option<u64>) -> Self {
+    pub fn new(s3: Duration) -> Self {
         Self {
-            duration: Duration::from_secs(second.unwrap_or(5)),
-            ..Default::default()
+            r#type: Default::default(),
+            start: Default::default(),
+            duration: s3,
+            sa_level: Default::default(),
+            link_ctrl_verified: Default::default(),
+            notify: Default::default(),
+            events: broadcast::channel(SESSION_EVENT_CAPACITY).0,
         }
     }
 
+    #[inline(always)]
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
     pub async fn reset(&self) {
         self.change(Default::default()).await;
         let _ = self.start.lock().await.take();
@@ -36,19 +68,26 @@ impl SessionManager {
     #[inline(always)]
     pub async fn change(&self, r#type: SessionType) {
         let mut guard = self.r#type.lock().await;
-        if *guard != r#type {
+        let from = *guard;
+        if from != r#type {
             self.set_security_access_level(Default::default()).await;
             self.clear_link_control_verify().await;
             if r#type == Default::default() {
                 let _ = self.start.lock().await.take();
+                self.notify.notify_one();
             }
         }
         *guard = r#type;
+        drop(guard);
+        if from != r#type {
+            let _ = self.events.send(SessionEvent::Changed { from, to: r#type });
+        }
     }
     /// Keep session or start non-default session manager
     #[inline(always)]
     pub async fn keep(&self) {
         self.start.lock().await.replace(Instant::now());
+        self.notify.notify_one();
     }
     /// get current session type
     #[inline(always)]
@@ -83,21 +122,128 @@ impl SessionManager {
         *guard = false;
         verified
     }
-    /// enable task
+    /// The S3 timer, the non-default session is exited when the deadline is expired.
     pub async fn work(&self) {
-        let mut interval = interval(self.duration);
-
         loop {
-            interval.tick().await;
+            let notified = self.notify.notified();
+            let start = *self.start.lock().await;
+            let Some(start) = start else {
+                notified.await;
+                continue;
+            };
+
+            // wait for the deadline, the timer is restarted when notified
+            if timeout_at((start + self.duration).into(), notified)
+                .await
+                .is_ok()
+            {
+                continue;
+            }
 
             let mut guard = self.start.lock().await;
-            if let Some(non_def_start) = guard.clone() {
-                if non_def_start.elapsed() >= self.duration {
-                    let _ = guard.take();
-                    self.set_session_type(Default::default()).await;
-                    self.set_security_access_level(Default::default()).await;
-                }
+            if guard.is_some_and(|v| v.elapsed() >= self.duration) {
+                let _ = guard.take();
+                drop(guard);
+                let session = self.get_session_type().await;
+                self.set_session_type(Default::default()).await;
+                let _ = self.events.send(SessionEvent::Timeout { session });
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{SessionEvent, SessionManager};
+    use crate::server::{
+        context::{tests::test_context, CommunicationControlState},
+        SessionConfig,
+    };
+    use iso14229_1::{
+        CommunicationCtrlType, CommunicationType, DTCSettingType, DataIdentifier, SessionType,
+    };
+    use std::time::{Duration, Instant};
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn session_is_exited_when_s3_is_expired() {
+        let manager = SessionManager::new(Duration::from_millis(100));
+        let mut events = manager.subscribe();
+        let worker = manager.clone();
+        let handle = tokio::spawn(async move { worker.work().await });
+
+        manager.change(SessionType::Extended).await;
+        manager.keep().await;
+        assert_eq!(
+            events.recv().await.unwrap(),
+            SessionEvent::Changed {
+                from: SessionType::Default,
+                to: SessionType::Extended
+            }
+        );
+
+        // the timer is restarted by keep
+        let started = Instant::now();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        manager.keep().await;
+
+        let event = timeout(Duration::from_secs(1), async {
+            loop {
+                if let event @ SessionEvent::Timeout { .. } = events.recv().await.unwrap() {
+                    break event;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            event,
+            SessionEvent::Timeout {
+                session: SessionType::Extended
+            }
+        );
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(160), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+        assert_eq!(manager.get_session_type().await, SessionType::Default);
+
+        // the DTC setting and communication control are restored in the default session
+        let mut ctx = test_context();
+        ctx.config.sessions = SessionConfig::defaults();
+        ctx.set_dtc_setting(DTCSettingType::Off).await.unwrap();
+        *ctx.comm_ctrl_state.lock().await = CommunicationControlState {
+            ctrl_type: CommunicationCtrlType::DisableRxAndTx,
+            comm_type: CommunicationType::NormalCommunicationMessages,
+            node_id: None,
+        };
+        ctx.exit_session(SessionType::Extended).await;
+        assert!(ctx.dtc_setting_enabled().await);
+        assert_eq!(
+            ctx.communication_ctrl_state().await,
+            CommunicationControlState::default()
+        );
+
+        // the server is reset when the programming session is expired only
+        let did = DataIdentifier::from(0xF200);
+        ctx.did_dyn.lock().await.insert(did, vec![]);
+        ctx.exit_session(SessionType::Extended).await;
+        assert!(ctx.did_dyn.lock().await.contains_key(&did));
+
+        manager.change(SessionType::Programming).await;
+        manager.keep().await;
+        let session = timeout(Duration::from_secs(1), async {
+            loop {
+                if let SessionEvent::Timeout { session } = events.recv().await.unwrap() {
+                    break session;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(session, SessionType::Programming);
+        ctx.exit_session(session).await;
+        assert!(ctx.did_dyn.lock().await.is_empty());
+
+        handle.abort();
+    }
+}
@@ -0,0 +1,226 @@
+//! The non-volatile memory of server, the state is encoded as JSON and persisted by [`Storage`].
+
+use crate::{
+    server::{context::DtcRecord, dtc::DtcMonitor, DtcConfig},
+    DoCanError,
+};
+use iso14229_1::{Configuration, DataIdentifier, MemoryLocation};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, io::ErrorKind, path::PathBuf};
+use tokio::fs;
+
+/// The persistence backend of server state.
+#[async_trait::async_trait]
+pub trait Storage: Send + Sync {
+    /// Load the persisted data, `None` if nothing is persisted.
+    async fn load(&self) -> Result<Option<Vec<u8>>, DoCanError>;
+    async fn save(&self, data: &[u8]) -> Result<(), DoCanError>;
+}
+
+/// The storage of a file, the file is replaced atomically when saving.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for FileStorage {
+    async fn load(&self) -> Result<Option<Vec<u8>>, DoCanError> {
+        match fs::read(&self.path).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(DoCanError::OtherError(format!("{:?}", e))),
+        }
+    }
+
+    async fn save(&self, data: &[u8]) -> Result<(), DoCanError> {
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, data)
+            .await
+            .map_err(|e| DoCanError::OtherError(format!("{:?}", e)))?;
+        fs::rename(&temp, &self.path)
+            .await
+            .map_err(|e| DoCanError::OtherError(format!("{:?}", e)))
+    }
+}
+
+/// The persisted state of server.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct NvmState {
+    /// the non-volatile DIDs
+    #[serde(default)]
+    pub(crate) dids: BTreeMap<u16, Vec<u8>>,
+    /// the fault memory, the preconfigured DTCs are used if absent
+    #[serde(default)]
+    pub(crate) dtcs: Option<Vec<DtcState>>,
+    #[serde(default)]
+    pub(crate) memories: Vec<MemoryState>,
+    /// the failed attempts of SecurityAccess by requestSeed sub-function
+    #[serde(default)]
+    pub(crate) sa_attempts: BTreeMap<u8, u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DtcState {
+    pub(crate) config: DtcConfig,
+    #[serde(default)]
+    pub(crate) monitor: DtcMonitor,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct MemoryState {
+    /// addressAndLengthFormatIdentifier, memoryAddress and memorySize
+    #[serde(with = "hex_bytes")]
+    pub(crate) location: Vec<u8>,
+    /// the data is encoded as hex string
+    #[serde(with = "hex_bytes")]
+    pub(crate) data: Vec<u8>,
+}
+
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode_upper(data))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        hex::decode(String::deserialize(deserializer)?).map_err(Error::custom)
+    }
+}
+
+impl NvmState {
+    pub(crate) fn decode(data: &[u8]) -> Result<Self, DoCanError> {
+        serde_json::from_slice(data).map_err(|e| DoCanError::OtherError(format!("{:?}", e)))
+    }
+
+    pub(crate) fn encode(&self) -> Result<Vec<u8>, DoCanError> {
+        serde_json::to_vec_pretty(self).map_err(|e| DoCanError::OtherError(format!("{:?}", e)))
+    }
+
+    /// The non-volatile DIDs, the DID which data length is not matched with `cfg` is ignored.
+    pub(crate) fn did_records(
+        &self,
+        dids: &[DataIdentifier],
+        cfg: &Configuration,
+    ) -> Vec<(DataIdentifier, Vec<u8>)> {
+        self.dids
+            .iter()
+            .map(|(&did, data)| (DataIdentifier::from(did), data))
+            .filter(|(did, data)| dids.contains(did) && cfg.did.get(did) == Some(&data.len()))
+            .map(|(did, data)| (did, data.clone()))
+            .collect()
+    }
+
+    pub(crate) fn dtc_records(
+        &self,
+        cfg: &Configuration,
+    ) -> Option<Result<Vec<DtcRecord>, DoCanError>> {
+        self.dtcs.as_ref().map(|dtcs| {
+            dtcs.iter()
+                .map(|dtc| {
+                    let mut record = DtcRecord::from_config(&dtc.config, cfg)?;
+                    record.monitor = dtc.monitor;
+                    Ok(record)
+                })
+                .collect()
+        })
+    }
+
+    pub(crate) fn memory_records(&self) -> Result<Vec<(MemoryLocation, Vec<u8>)>, DoCanError> {
+        self.memories
+            .iter()
+            .map(|memory| {
+                let location = MemoryLocation::from_slice(&memory.location)
+                    .map_err(|e| DoCanError::OtherError(format!("{:?}", e)))?;
+                Ok((location, memory.data.clone()))
+            })
+            .collect()
+    }
+}
+
+impl From<&DtcRecord> for DtcState {
+    fn from(record: &DtcRecord) -> Self {
+        Self {
+            config: record.to_config(),
+            monitor: record.monitor,
+        }
+    }
+}
+
+impl From<(&MemoryLocation, &[u8])> for MemoryState {
+    fn from((location, data): (&MemoryLocation, &[u8])) -> Self {
+        Self {
+            location: (*location).into(),
+            data: data.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileStorage, NvmState, Storage};
+    use crate::server::context::DtcRecord;
+    use iso14229_1::{
+        utils::U24, AddressAndLengthFormatIdentifier, Configuration, DataIdentifier, MemoryLocation,
+    };
+
+    #[tokio::test]
+    async fn file_storage_saves_and_loads() {
+        let path = std::env::temp_dir().join(format!("docan-nvm-{}.json", std::process::id()));
+        let storage = FileStorage::new(&path);
+        let _ = tokio::fs::remove_file(&path).await;
+        assert_eq!(storage.load().await.unwrap(), None);
+
+        storage.save(b"{}").await.unwrap();
+        assert_eq!(storage.load().await.unwrap(), Some(b"{}".to_vec()));
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+
+    #[test]
+    fn nvm_state_round_trips() {
+        let did = DataIdentifier::from(0x4101);
+        let mut cfg = Configuration::default();
+        cfg.did.insert(did, 2);
+
+        let mut record = DtcRecord::new(U24::new(0x112233));
+        record.status = 0x2F;
+        record.monitor.aging_cycles = 3;
+        record.snapshots = vec![(0x01, vec![(did, vec![0x01, 0x02])])];
+        let location = MemoryLocation::new(
+            AddressAndLengthFormatIdentifier::new(0x04, 0x04).unwrap(),
+            0x0000_0001,
+            4,
+        )
+        .unwrap();
+
+        let mut state = NvmState::default();
+        state.dids.insert(0x4101, vec![0x12, 0x34]);
+        state.dids.insert(0xF190, vec![0x56]);
+        state.dtcs = Some(vec![(&record).into()]);
+        state.memories = vec![(&location, [0xAA, 0xBB].as_slice()).into()];
+
+        let data = state.encode().unwrap();
+        assert!(String::from_utf8_lossy(&data).contains(r#""data": "AABB""#));
+        let state = NvmState::decode(&data).unwrap();
+        assert_eq!(
+            state.did_records(&[did], &cfg),
+            vec![(did, vec![0x12, 0x34])]
+        );
+        assert_eq!(state.dtc_records(&cfg).unwrap().unwrap(), vec![record]);
+        assert_eq!(
+            state.memory_records().unwrap(),
+            vec![(location, vec![0xAA, 0xBB])]
+        );
+        assert!(NvmState::decode(b"{}").unwrap().dtc_records(&cfg).is_none());
+    }
+}
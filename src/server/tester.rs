@@ -0,0 +1,71 @@
+//! The arbitration of concurrent testers which are served by one server.
+
+use iso14229_1::SessionType;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// The tester of request in processing.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Tester {
+    /// the index of `testers`, 0 is the tester of `address`
+    pub(crate) index: usize,
+    /// the testers share the session and context if present
+    pub(crate) lock: Option<TesterLock>,
+}
+
+/// The owner of non-default session when the session and context are shared by testers.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct TesterLock {
+    owner: Arc<Mutex<Option<usize>>>,
+}
+
+impl TesterLock {
+    /// Check the request of tester is accepted in the active session,
+    /// the request of other tester is rejected until the owner exits the non-default session.
+    pub(crate) async fn acquire(&self, tester: usize, session: SessionType) -> bool {
+        let mut owner = self.owner.lock().await;
+        if session == SessionType::Default {
+            // the session is exited by S3 timeout or request
+            owner.take();
+        }
+
+        owner.is_none_or(|v| v == tester)
+    }
+
+    /// Update the owner after the request of tester is processed.
+    pub(crate) async fn update(&self, tester: usize, session: SessionType) {
+        let mut owner = self.owner.lock().await;
+        match session {
+            SessionType::Default => {
+                if *owner == Some(tester) {
+                    owner.take();
+                }
+            }
+            _ => {
+                owner.replace(tester);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn tester_is_rejected_in_session_of_other_tester() {
+        let lock = TesterLock::default();
+        assert!(lock.acquire(1, SessionType::Default).await);
+        lock.update(1, SessionType::Extended).await;
+
+        assert!(lock.acquire(1, SessionType::Extended).await);
+        assert!(!lock.acquire(0, SessionType::Extended).await);
+        // the session of owner is exited by S3 timeout
+        assert!(lock.acquire(0, SessionType::Default).await);
+        lock.update(0, SessionType::Programming).await;
+        assert!(!lock.acquire(1, SessionType::Programming).await);
+
+        lock.update(0, SessionType::Default).await;
+        assert!(lock.acquire(1, SessionType::Default).await);
+    }
+}
@@ -1,4 +1,6 @@
 use rand::{rng, RngExt};
+#[cfg(any(feature = "std2013", feature = "std2020"))]
+use std::path::{Component, Path, PathBuf};
 
 #[inline(always)]
 pub fn gen_seed(num: usize) -> Vec<u8> {
@@ -6,3 +8,31 @@ pub fn gen_seed(num: usize) -> Vec<u8> {
     rng().fill(&mut res);
     res
 }
+
+/// Resolve the file path of request under the sandbox root.
+///
+/// A leading `/` is relative to the root, `..` and symbolic links(even dangling) are rejected.
+#[cfg(any(feature = "std2013", feature = "std2020"))]
+pub fn sandbox_path(root: &Path, path: &str) -> Option<PathBuf> {
+    root.canonicalize().ok()?;
+
+    let mut result = root.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(v) => {
+                result.push(v);
+                // the file is created through a dangling link, so the link is not resolved
+                if result
+                    .symlink_metadata()
+                    .is_ok_and(|v| v.file_type().is_symlink())
+                {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(result)
+}